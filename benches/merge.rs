@@ -0,0 +1,47 @@
+//! Merging of the maps of the parallel read, with the reduction of the maps by hashing and with the k-way merge
+//! of their sorted entries. Run with `cargo bench --bench merge`.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rust_1brc::generate::generate;
+use rust_1brc::parse::ParseOptions;
+use rust_1brc::read::{merge_all, merge_all_sorted, read_stations_data_slice, slice_sized};
+use rust_1brc::{MinMeanMax, StationData};
+
+const ROWS: u64 = 4_000_000;
+const RUNS: usize = 5;
+
+type Stations<'a> = HashMap<&'a str, StationData>;
+
+// the fastest of the runs, the maps are cloned outside of the timed part
+fn best(maps: &[Stations], merge: fn(Vec<Stations>) -> Stations) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let maps = maps.to_vec();
+            let start = Instant::now();
+            black_box(merge(maps));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    for stations in [400, 10_000] {
+        let mut data = Vec::new();
+        generate(&mut data, ROWS, stations, 0).unwrap();
+        for slices in [8, 64, 512] {
+            let maps: Vec<Stations> = slice_sized(&data, data.len() / slices)
+                .iter()
+                .map(|slice| read_stations_data_slice(slice, &MinMeanMax, ParseOptions::default()))
+                .collect();
+            let hashed = best(&maps, |maps| merge_all(&MinMeanMax, maps));
+            let sorted = best(&maps, |maps| merge_all_sorted(&MinMeanMax, maps));
+            assert_eq!(merge_all(&MinMeanMax, maps.clone()), merge_all_sorted(&MinMeanMax, maps.clone()));
+            println!("{} stations, {} maps: {:?} reduced by hashing, {:?} k-way merged ({:+.1}%)",
+                     stations, maps.len(), hashed, sorted, (sorted.as_secs_f64() / hashed.as_secs_f64() - 1.0) * 100.0);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Parsing of a file sorted by station and of the same measurements in random order, with and without the cache
+//! of the station of the previous record. Run with `cargo bench --bench station_cache`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rust_1brc::generate::{generate, generate_sorted};
+use rust_1brc::parse::ParseOptions;
+use rust_1brc::read::read_stations_data_slice;
+use rust_1brc::MinMeanMax;
+
+const ROWS: u64 = 5_000_000;
+const STATIONS: usize = 400;
+const RUNS: usize = 5;
+
+// the fastest of the runs
+fn best(data: &[u8], options: ParseOptions) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(read_stations_data_slice(data, &MinMeanMax, options));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let (mut random, mut sorted) = (Vec::new(), Vec::new());
+    generate(&mut random, ROWS, STATIONS, 0).unwrap();
+    generate_sorted(&mut sorted, ROWS, STATIONS, 0).unwrap();
+    let uncached = ParseOptions { no_station_cache: true, ..Default::default() };
+    for (name, data) in [("random", &random), ("sorted", &sorted)] {
+        let (without, with) = (best(data, uncached), best(data, ParseOptions::default()));
+        println!("{} order, {} rows: {:?} with the station cache, {:?} without ({:+.1}%)",
+                 name, ROWS, with, without, (with.as_secs_f64() / without.as_secs_f64() - 1.0) * 100.0);
+    }
+}
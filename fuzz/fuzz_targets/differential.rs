@@ -0,0 +1,18 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use rust_1brc::parse::ParseOptions;
+use rust_1brc::read::{read_stations_data, read_stations_data_slice};
+use rust_1brc::{Bump, Cancel, Interner, MinMeanMax};
+
+// the streaming and the slice parsers must aggregate every input to the same map
+fuzz_target!(|data: &[u8]| {
+    let options = ParseOptions { lenient: true, ..Default::default() };
+    let arena = Bump::new();
+    let mut interner = Interner::new(&arena);
+    let (simple, _) = read_stations_data(data, &MinMeanMax, &mut interner, HashMap::new(), options, &Cancel::default(), |_, _| {});
+    let slice = read_stations_data_slice(data, &MinMeanMax, options);
+    assert_eq!(simple, slice);
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_1brc::parse::ParseOptions;
+use rust_1brc::read::read_stations_data_slice;
+use rust_1brc::MinMeanMax;
+
+// in lenient mode any input must parse without panicking, into station names that are subslices of the input
+fuzz_target!(|data: &[u8]| {
+    for fast_parse in [false, true] {
+        let m = read_stations_data_slice(data, &MinMeanMax, ParseOptions { fast_parse, lenient: true, ..Default::default() });
+        let range = data.as_ptr_range();
+        for (station, s) in &m {
+            let span = station.as_bytes().as_ptr_range();
+            assert!(range.start <= span.start && span.end <= range.end);
+            assert!(!station.is_empty() && !station.contains('\n'));
+            assert!(s.min_temp <= s.max_temp && s.n > 0);
+        }
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_1brc::parse::{parse_temp, parse_temp_fast};
+
+// the fast parser must never accept a value the checked parser rejects or parses differently
+fuzz_target!(|data: &[u8]| {
+    let checked = parse_temp(data);
+    if let Some(temp) = checked {
+        assert!(temp.abs() <= i16::MAX as i32);
+    }
+    if let Some(fast) = parse_temp_fast(data) {
+        assert_eq!(Some(fast), checked);
+    }
+});
@@ -0,0 +1,76 @@
+use std::io::Error;
+
+/// Per-station statistics computed while scanning the measurements.
+///
+/// The readers keep one `State` per station: it is created from the first temperature seen for the station,
+/// updated with every following one, and the states built by the parallel workers are merged at the end.
+/// Temperatures are passed in tenths of a degree.
+///
+/// [`MinMeanMax`](crate::MinMeanMax) is the default implementation computing a [`StationData`](crate::StationData)
+/// per station. A custom aggregator counting the frost days (readings below zero) of every station could look like this:
+///
+/// ```
+/// use rust_1brc::{aggregate_with, Aggregator};
+///
+/// struct FrostDays;
+///
+/// impl Aggregator for FrostDays {
+///     type State = u32;
+///
+///     fn init(&self, temp: i32) -> u32 {
+///         (temp < 0) as u32
+///     }
+///
+///     fn observe(&self, state: &mut u32, temp: i32) {
+///         *state += (temp < 0) as u32;
+///     }
+///
+///     fn merge(&self, state: &mut u32, other: u32) {
+///         *state += other;
+///     }
+/// }
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("rust-1brc-frost-days.txt");
+/// std::fs::write(&path, "Oslo;-3.5\nOslo;1.2\nRome;12.0\nOslo;-0.1\n")?;
+///
+/// let frost_days = aggregate_with(&path, &FrostDays)?;
+/// assert_eq!(frost_days["Oslo"], 2);
+/// assert_eq!(frost_days["Rome"], 0);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub trait Aggregator: Sync {
+    type State: Send;
+
+    /// Creates the state of a station from its first temperature.
+    fn init(&self, temp: i32) -> Self::State;
+
+    /// Adds a temperature to the state of a station.
+    fn observe(&self, state: &mut Self::State, temp: i32);
+
+    /// Like [`init`](Aggregator::init), with the byte offset of the record in the input.
+    ///
+    /// The readers call the `_at` variants, only the aggregators that keep the offsets need to override them.
+    fn init_at(&self, temp: i32, _offset: u64) -> Self::State {
+        self.init(temp)
+    }
+
+    /// Like [`observe`](Aggregator::observe), with the byte offset of the record in the input.
+    fn observe_at(&self, state: &mut Self::State, temp: i32, _offset: u64) {
+        self.observe(state, temp)
+    }
+
+    /// Merges the state of the same station computed over another part of the input.
+    ///
+    /// The parallel readers merge the parts in an order that depends on the number of threads and the size
+    /// of the slices, the merge must be associative and commutative for the results not to depend on them.
+    /// The aggregators of this crate keep integer tenths and counts and choose between the records of equal
+    /// temperatures by their offsets, so their merged states are bit-identical whatever the order.
+    fn merge(&self, state: &mut Self::State, other: Self::State);
+
+    /// Checks the final state of a station, the readers report a failed check as an error.
+    fn validate(&self, _state: &Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Results of earlier runs stored in a directory, keyed by the identity of the inputs and the configuration
+//! of the aggregation, so that unchanged inputs are not aggregated again.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash3_64;
+
+use crate::parse::ParseOptions;
+
+// bytes at the start and at the end of an input hashed into its fingerprint
+const FINGERPRINT_BYTES: u64 = 1 << 20;
+
+// of the entries, entries of other versions are ignored
+const CACHE_VERSION: u32 = 1;
+
+/// Identity of an input file: a file of the same path, size and modification time with the same first and last
+/// megabyte is taken to have the same contents.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputKey {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified: u128,
+    /// xxh3 of the first and the last megabyte
+    pub fingerprint: u64,
+}
+
+impl InputKey {
+    /// The identity of the local regular file, `None` for other inputs like pipes.
+    pub fn of(path: &Path) -> Result<Option<InputKey>, Error> {
+        let metadata = fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_err(Error::other)?.as_nanos();
+        let size = metadata.len();
+        let mut file = File::open(path)?;
+        let mut hasher = XxHash3_64::new();
+        let mut buf = Vec::new();
+        (&mut file).take(FINGERPRINT_BYTES).read_to_end(&mut buf)?;
+        hasher.write(&buf);
+        if size > FINGERPRINT_BYTES {
+            buf.clear();
+            file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_BYTES).max(FINGERPRINT_BYTES)))?;
+            file.read_to_end(&mut buf)?;
+            hasher.write(&buf);
+        }
+        Ok(Some(InputKey { path: fs::canonicalize(path)?, size, modified, fingerprint: hasher.finish() }))
+    }
+}
+
+/// Key of a cached result: the inputs in their order and the configuration of the aggregation,
+/// any description of the options that change the aggregated statistics.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub inputs: Vec<InputKey>,
+    pub config: String,
+}
+
+impl CacheKey {
+    /// The key of the inputs, `None` if any of them is not a local regular file.
+    pub fn of(paths: &[PathBuf], config: &str) -> Result<Option<CacheKey>, Error> {
+        let mut inputs = Vec::with_capacity(paths.len());
+        for path in paths {
+            match InputKey::of(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))? {
+                Some(input) => inputs.push(input),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(CacheKey { inputs, config: config.to_owned() }))
+    }
+
+    /// The description of the parse options that change the aggregated statistics, part of the configuration.
+    pub fn parse_config(options: ParseOptions) -> String {
+        // the names of the stations aggregated are hashed in their order, the list can be long
+        let only = options.only.map(|only| {
+            let mut names: Vec<&str> = only.iter().map(String::as_str).collect();
+            names.sort_unstable();
+            XxHash3_64::oneshot(names.join("\n").as_bytes())
+        });
+        format!("lenient={} columns={:?} scale={} dedup_consecutive={} only={:?}", options.lenient, options.columns, options.scale.factor(), options.dedup_consecutive, only)
+    }
+
+    fn file_name(&self) -> String {
+        let key = serde_json::to_vec(self).expect("a serializable key");
+        format!("{:016x}.json", XxHash3_64::oneshot(&key))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<M> {
+    version: u32,
+    key: CacheKey,
+    stations: M,
+}
+
+/// A directory of cached results, one file per key.
+pub struct ResultsCache {
+    dir: PathBuf,
+}
+
+impl ResultsCache {
+    pub fn new(dir: PathBuf) -> ResultsCache {
+        ResultsCache { dir }
+    }
+
+    /// The stations cached for the key, `None` if there are none.
+    ///
+    /// Fails if the entry cannot be read or is corrupt, an entry of another key (a collision of the file names)
+    /// or of another version is a miss.
+    pub fn load<S: DeserializeOwned>(&self, key: &CacheKey) -> Result<Option<HashMap<String, S>>, Error> {
+        let path = self.dir.join(key.file_name());
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::new(e.kind(), format!("{}: {}", path.display(), e))),
+        };
+        let entry: Entry<HashMap<String, S>> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        Ok((entry.version == CACHE_VERSION && entry.key == *key).then_some(entry.stations))
+    }
+
+    /// Stores the stations for the key, replacing the entry of the key if any.
+    pub fn store<K: AsRef<str> + Eq + Hash + Serialize, S: Serialize>(&self, key: &CacheKey, m: &HashMap<K, S>) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(key.file_name());
+        // written to a temporary file first, so that a reader never sees a partial entry
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut w, &Entry { version: CACHE_VERSION, key: key.clone(), stations: m })?;
+        w.flush()?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Scale;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    #[test]
+    fn results_are_cached_per_input_and_config() {
+        let dir = std::env::temp_dir().join(format!("rust-1brc-cache-{}", std::process::id()));
+        let input = dir.with_extension("txt");
+        fs::write(&input, b"Oslo;-3.4\nOslo;12.5\n").unwrap();
+        let paths = [input.clone()];
+        let cache = ResultsCache::new(dir.clone());
+        let key = CacheKey::of(&paths, "min/mean/max").unwrap().unwrap();
+        assert!(cache.load::<StationData>(&key).unwrap().is_none());
+
+        let mut oslo = MinMeanMax.init(-34);
+        MinMeanMax.observe(&mut oslo, 125);
+        cache.store(&key, &HashMap::from([("Oslo", oslo)])).unwrap();
+        assert_eq!(cache.load::<StationData>(&key).unwrap().unwrap()["Oslo"], oslo);
+        assert!(cache.load::<StationData>(&CacheKey::of(&paths, "histogram").unwrap().unwrap()).unwrap().is_none());
+        // the temperatures parsed into another scale, or without the repeated records, are another result
+        let tenths = CacheKey::of(&paths, &CacheKey::parse_config(ParseOptions::default())).unwrap().unwrap();
+        cache.store(&tenths, &HashMap::from([("Oslo", oslo)])).unwrap();
+        let hundredths = ParseOptions { scale: Scale::new(100).unwrap(), ..Default::default() };
+        assert!(cache.load::<StationData>(&CacheKey::of(&paths, &CacheKey::parse_config(hundredths)).unwrap().unwrap()).unwrap().is_none());
+        let deduplicated = ParseOptions { dedup_consecutive: true, ..Default::default() };
+        assert!(cache.load::<StationData>(&CacheKey::of(&paths, &CacheKey::parse_config(deduplicated)).unwrap().unwrap()).unwrap().is_none());
+
+        // a changed input is another key
+        fs::write(&input, b"Oslo;-3.4\nOslo;12.6\n").unwrap();
+        let changed = CacheKey::of(&paths, "min/mean/max").unwrap().unwrap();
+        assert_ne!(changed.inputs[0].fingerprint, key.inputs[0].fingerprint);
+        assert!(cache.load::<StationData>(&changed).unwrap().is_none());
+
+        // a corrupt entry is an error, not a result
+        fs::write(dir.join(key.file_name()), b"{\"version\":1,").unwrap();
+        assert_eq!(cache.load::<StationData>(&key).unwrap_err().kind(), ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&input).unwrap();
+    }
+}
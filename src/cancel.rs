@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CancelReason {
+    Timeout,
+    Interrupted,
+}
+
+/// Cooperative cancellation shared by the timeout and the Ctrl-C handler.
+///
+/// The readers poll it between chunks and stop early, keeping whatever they have aggregated so far.
+#[derive(Default)]
+pub struct Cancel {
+    cancelled: AtomicBool,
+    reason: AtomicU8,
+}
+
+impl Cancel {
+    /// Requests cancellation, returns `false` if the run has already been cancelled.
+    pub fn cancel(&self, reason: CancelReason) -> bool {
+        let code = match reason {
+            CancelReason::Timeout => 1,
+            CancelReason::Interrupted => 2,
+        };
+        let first = self.reason.compare_exchange(0, code, Ordering::SeqCst, Ordering::SeqCst).is_ok();
+        self.cancelled.store(true, Ordering::SeqCst);
+        first
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn reason(&self) -> Option<CancelReason> {
+        match self.reason.load(Ordering::SeqCst) {
+            1 => Some(CancelReason::Timeout),
+            2 => Some(CancelReason::Interrupted),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_cancel_is_reported() {
+        let cancel = Cancel::default();
+        assert!(cancel.cancel(CancelReason::Timeout));
+        assert!(!cancel.cancel(CancelReason::Interrupted));
+        assert_eq!(cancel.reason(), Some(CancelReason::Timeout));
+    }
+}
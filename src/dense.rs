@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::parse::ParseOptions;
+use crate::read::{for_each_record, lines_before, records_offset, slice_offsets, without_repeats};
+use crate::{Aggregator, Cancel};
+
+/// Stations of a part of the input stored in a flat `Vec` indexed by dense IDs.
+///
+/// The hash map is only used to assign the next ID to a station on its first sighting and to look the IDs up,
+/// the states themselves are updated in place in the `Vec`. The IDs are local to a part of the input and are
+/// remapped by name when the parts are merged.
+pub struct DenseStations<'a, S> {
+    ids: HashMap<&'a str, u32>,
+    names: Vec<&'a str>,
+    states: Vec<S>,
+}
+
+impl<'a, S> DenseStations<'a, S> {
+    pub fn new() -> DenseStations<'a, S> {
+        DenseStations { ids: HashMap::new(), names: Vec::new(), states: Vec::new() }
+    }
+
+    pub fn observe<A: Aggregator<State = S>>(&mut self, aggregator: &A, station: &'a str, temp: i32, offset: u64) {
+        match self.ids.get(station) {
+            Some(&id) => aggregator.observe_at(&mut self.states[id as usize], temp, offset),
+            None => self.insert(station, aggregator.init_at(temp, offset)),
+        }
+    }
+
+    /// Merges the stations of `other`, remapping its IDs to the IDs of `self`.
+    pub fn merge<A: Aggregator<State = S>>(&mut self, aggregator: &A, other: DenseStations<'a, S>) {
+        for (station, state) in other.names.into_iter().zip(other.states) {
+            match self.ids.get(station) {
+                Some(&id) => aggregator.merge(&mut self.states[id as usize], state),
+                None => self.insert(station, state),
+            }
+        }
+    }
+
+    pub fn into_map(self) -> HashMap<&'a str, S> {
+        self.names.into_iter().zip(self.states).collect()
+    }
+
+    fn insert(&mut self, station: &'a str, state: S) {
+        self.ids.insert(station, self.names.len() as u32);
+        self.names.push(station);
+        self.states.push(state);
+    }
+}
+
+impl<S> Default for DenseStations<'_, S> {
+    fn default() -> Self {
+        DenseStations::new()
+    }
+}
+
+/// Variant of [`read_slices_parallel`](crate::read::read_slices_parallel) aggregating every rayon job into
+/// [`DenseStations`], returns the merged map and the number of bytes processed.
+pub fn read_slices_dense<'a, A: Aggregator>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel) -> (HashMap<&'a str, A::State>, usize) {
+    let offsets = slice_offsets(slices);
+    let (stations, bytes_processed) = slices
+        .par_iter()
+        .enumerate()
+        .fold(|| (DenseStations::new(), 0),
+              |(mut stations, n), (i, slice)| {
+                  // cancellation point: skip the remaining slices once cancelled
+                  if cancel.is_cancelled() {
+                      return (stations, n);
+                  }
+                  let (records, repeats) = without_repeats(slices, i, options);
+                  let slice_start = records_offset(&offsets, slices, i, records);
+                  for_each_record(records, options, &|| lines_before(slices, i) + repeats, |station, temp, offset| stations.observe(aggregator, station, temp, slice_start + offset as u64));
+                  (stations, n + slice.len())
+              },
+        )
+        .reduce(|| (DenseStations::new(), 0),
+                |(mut s1, n1), (s2, n2)| {
+                    s1.merge(aggregator, s2);
+                    (s1, n1 + n2)
+                },
+        );
+    (stations.into_map(), bytes_processed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::{read_slices_parallel, slice_sized};
+    use crate::{ExtremeOffsets, MinMeanMax, TrackExtremes};
+
+    #[test]
+    fn matches_hash_map_aggregation() {
+        let data: String = (0..5_000).map(|i| format!("Station {};{}.{}\n", i % 97, i % 201 - 100, i % 10)).collect();
+        // one slice per line, so that the jobs see the stations in different orders
+        let slices: Vec<&[u8]> = data.as_bytes().split_inclusive(|&b| b == b'\n').collect();
+        let cancel = Cancel::default();
+        let (dense, n1) = read_slices_dense(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        let (map, n2) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        assert_eq!(n1, n2);
+        assert_eq!(dense.len(), 97);
+        for (station, d) in &map {
+            let e = &dense[station];
+            assert_eq!((d.min_temp, d.max_temp, d.sum_temp, d.n), (e.min_temp, e.max_temp, e.sum_temp, e.n));
+        }
+    }
+
+    #[test]
+    fn merge_remaps_ids() {
+        let mut a = DenseStations::new();
+        a.observe(&MinMeanMax, "Hamburg", 120, 0);
+        a.observe(&MinMeanMax, "Oslo", -40, 0);
+        let mut b = DenseStations::new();
+        b.observe(&MinMeanMax, "Oslo", 10, 0);
+        b.observe(&MinMeanMax, "Rome", 200, 0);
+        b.observe(&MinMeanMax, "Hamburg", -34, 0);
+        a.merge(&MinMeanMax, b);
+        let m = a.into_map();
+        assert_eq!(m.len(), 3);
+        assert_eq!((m["Hamburg"].min_temp, m["Hamburg"].max_temp, m["Hamburg"].n), (-34, 120, 2));
+        assert_eq!((m["Oslo"].min_temp, m["Oslo"].max_temp), (-40, 10));
+        assert_eq!(m["Rome"].n, 1);
+    }
+
+    #[test]
+    fn files_have_offsets_of_their_own() {
+        let (a, b): (String, String) = ((0..1_000).map(|i| format!("A;{}.0\n", i % 10)).collect(), (0..1_000).map(|i| format!("B;{}.0\n", (i + 5) % 10)).collect());
+        // the second file below the first in memory, as the mappings of the files often are
+        let files = if a.as_ptr() > b.as_ptr() { [a.as_bytes(), b.as_bytes()] } else { [b.as_bytes(), a.as_bytes()] };
+        let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, 256)).collect();
+        let (m, n) = read_slices_dense(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default());
+        // the offsets are counted from the start of every file
+        assert_eq!((m["A"].offsets, m["B"].offsets), (ExtremeOffsets { min: 0, max: 54 }, ExtremeOffsets { min: 30, max: 24 }));
+        assert_eq!((m["A"].data.n, m["B"].data.n), (1_000, 1_000));
+        assert_eq!(n, read_slices_parallel(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default()).1);
+    }
+}
@@ -0,0 +1,148 @@
+//! Comparison of saved result files.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+/// Statistics of a station as written to a result file.
+///
+/// The count is only known for the formats that include it, like JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct SavedStats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub count: Option<u32>,
+}
+
+// the JSON output written with `--metadata`
+#[derive(Deserialize)]
+struct WithMetadata {
+    stations: BTreeMap<String, SavedStats>,
+}
+
+/// Parses a result file written in the brace, plain or JSON format, with or without the metadata.
+pub fn parse_results(s: &str) -> Result<BTreeMap<String, SavedStats>, Error> {
+    let s = s.trim();
+    if let Ok(m) = serde_json::from_str(s) {
+        return Ok(m);
+    }
+    if let Ok(WithMetadata { stations }) = serde_json::from_str(s) {
+        return Ok(stations);
+    }
+    // the brace format is a single line, the plain format has one station per line
+    let entries: Vec<&str> = match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        Some(list) => list.split(", ").collect(),
+        None => s.lines().collect(),
+    };
+    let mut m = BTreeMap::new();
+    let mut pending = String::new();
+    for entry in entries.into_iter().filter(|e| !e.is_empty()) {
+        // the separator may also appear in the station names, such entries are joined with the next ones
+        if !pending.is_empty() {
+            pending.push_str(", ");
+        }
+        pending.push_str(entry);
+        if let Some((station, stats)) = parse_entry(&pending) {
+            m.insert(station.to_owned(), stats);
+            pending.clear();
+        }
+    }
+    if !pending.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Invalid result entry: {}", pending)));
+    }
+    Ok(m)
+}
+
+// parses `station=min/mean/max`
+fn parse_entry(entry: &str) -> Option<(&str, SavedStats)> {
+    let (station, values) = entry.rsplit_once('=')?;
+    let mut values = values.split('/').map(|v| v.parse::<f64>());
+    match (values.next(), values.next(), values.next(), values.next()) {
+        (Some(Ok(min)), Some(Ok(mean)), Some(Ok(max)), None) => Some((station, SavedStats { min, mean, max, count: None })),
+        _ => None,
+    }
+}
+
+/// A statistic of a station that differs between the two results.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub old: f64,
+    pub new: f64,
+}
+
+/// Differences between two results.
+#[derive(Debug, Default, Serialize)]
+pub struct ResultsDiff {
+    pub only_old: Vec<String>,
+    pub only_new: Vec<String>,
+    pub changed: BTreeMap<String, Vec<FieldDiff>>,
+}
+
+impl ResultsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_old.is_empty() && self.only_new.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two results, statistics that differ by at most `tolerance` are considered equal.
+///
+/// The counts are compared only if both results include them.
+pub fn diff(old: &BTreeMap<String, SavedStats>, new: &BTreeMap<String, SavedStats>, tolerance: f64) -> ResultsDiff {
+    let mut d = ResultsDiff {
+        only_new: new.keys().filter(|s| !old.contains_key(*s)).cloned().collect(),
+        ..Default::default()
+    };
+    for (station, o) in old {
+        let Some(n) = new.get(station) else {
+            d.only_old.push(station.clone());
+            continue;
+        };
+        let mut fields = vec![("min", o.min, n.min), ("mean", o.mean, n.mean), ("max", o.max, n.max)];
+        if let (Some(o), Some(n)) = (o.count, n.count) {
+            fields.push(("count", o as f64, n as f64));
+        }
+        let changed: Vec<FieldDiff> = fields.into_iter()
+            // the epsilon absorbs the rounding error of the decimal values, so that a tolerance of 0.1 allows 0.1
+            .filter(|(_, old, new)| (old - new).abs() > tolerance + 1e-9)
+            .map(|(field, old, new)| FieldDiff { field, old, new })
+            .collect();
+        if !changed.is_empty() {
+            d.changed.insert(station.clone(), changed);
+        }
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brace_plain_and_json_are_parsed() {
+        let brace = parse_results("{A, B=-1.5/2.0/3.0, Oslo=1.0/1.0/1.0}\n").unwrap();
+        let plain = parse_results("A, B=-1.5/2.0/3.0\nOslo=1.0/1.0/1.0\n").unwrap();
+        let json = parse_results(r#"{"A, B":{"min":-1.5,"mean":2.0,"max":3.0,"count":2},"Oslo":{"min":1.0,"mean":1.0,"max":1.0,"count":1}}"#).unwrap();
+        assert_eq!(brace, plain);
+        assert_eq!(brace["A, B"], SavedStats { min: -1.5, mean: 2.0, max: 3.0, count: None });
+        assert_eq!(json["A, B"].count, Some(2));
+        let meta = parse_results(r#"{"unit":"celsius","generated_at":"2024-01-01T00:00:00Z","collation":"bytes","stations":{"Oslo":{"min":1.0,"mean":1.0,"max":1.0,"count":1}}}"#).unwrap();
+        assert_eq!(meta["Oslo"], json["Oslo"]);
+        assert!(parse_results("{}").unwrap().is_empty());
+        assert!(parse_results("{A=1.0/x/2.0}").is_err());
+    }
+
+    #[test]
+    fn differences_beyond_tolerance() {
+        let old = parse_results(r#"{"A":{"min":1.0,"mean":2.0,"max":3.0,"count":2},"B":{"min":0.0,"mean":0.0,"max":0.0,"count":1}}"#).unwrap();
+        let new = parse_results("{A=1.1/2.5/3.0, C=0.0/0.0/0.0}").unwrap();
+        assert!(diff(&old, &new, 0.5).changed.is_empty());
+        let d = diff(&old, &new, 0.1);
+        assert_eq!((d.only_old, d.only_new), (vec!["B".to_owned()], vec!["C".to_owned()]));
+        assert_eq!(d.changed["A"], vec![FieldDiff { field: "mean", old: 2.0, new: 2.5 }]);
+        assert!(diff(&old, &old, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! Transcoding of Latin-1 and UTF-16 inputs to the UTF-8 the parsers expect.
+//!
+//! A byte order mark at the start of an input selects its encoding whatever the configured one is,
+//! the mark itself is dropped. The temperatures and the delimiters are ASCII in all the encodings,
+//! so the parsers stay byte-oriented on the transcoded input.
+
+use std::borrow::Cow;
+use std::io::{Error, Read};
+
+// bytes read from the inner reader of a `Decoder` at a time
+const DECODER_CHUNK_SIZE: usize = 64 << 10;
+
+/// Character encoding of an input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, every byte is the code point of the same value
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// The encoding of the byte order mark `data` starts with and the length of the mark,
+    /// the encoding itself and 0 if there is none.
+    pub fn sniff(self, data: &[u8]) -> (Encoding, usize) {
+        if data.starts_with(b"\xef\xbb\xbf") {
+            (Encoding::Utf8, 3)
+        } else if data.starts_with(b"\xff\xfe") {
+            (Encoding::Utf16Le, 2)
+        } else if data.starts_with(b"\xfe\xff") {
+            (Encoding::Utf16Be, 2)
+        } else {
+            (self, 0)
+        }
+    }
+}
+
+/// Transcodes a whole input to UTF-8, the input is borrowed if it is UTF-8 or ASCII Latin-1 without a byte order mark.
+pub fn decode(data: &[u8], encoding: Encoding) -> Cow<'_, [u8]> {
+    let (encoding, bom) = encoding.sniff(data);
+    let data = &data[bom..];
+    match encoding {
+        Encoding::Utf8 if bom == 0 => Cow::Borrowed(data),
+        Encoding::Latin1 if data.is_ascii() => Cow::Borrowed(data),
+        _ => {
+            let mut out = Vec::with_capacity(data.len());
+            decode_into(data, encoding, &mut out, true);
+            Cow::Owned(out)
+        }
+    }
+}
+
+// appends the UTF-8 of the data to `out`, returns the number of bytes decoded, less than the data if it ends
+// in the middle of a UTF-16 code unit or surrogate pair and this is not the `last` part of the input,
+// the invalid UTF-16 sequences are replaced with U+FFFD
+fn decode_into(data: &[u8], encoding: Encoding, out: &mut Vec<u8>, last: bool) -> usize {
+    let unit: fn([u8; 2]) -> u16 = match encoding {
+        Encoding::Utf8 => {
+            out.extend_from_slice(data);
+            return data.len();
+        }
+        Encoding::Latin1 => {
+            for &b in data {
+                match b.is_ascii() {
+                    true => out.push(b),
+                    false => out.extend_from_slice(char::from(b).encode_utf8(&mut [0; 2]).as_bytes()),
+                }
+            }
+            return data.len();
+        }
+        Encoding::Utf16Le => u16::from_le_bytes,
+        Encoding::Utf16Be => u16::from_be_bytes,
+    };
+    let mut units: Vec<u16> = data.chunks_exact(2).map(|u| unit([u[0], u[1]])).collect();
+    // a high surrogate waits for the rest of the pair
+    if !last && units.last().is_some_and(|u| (0xd800..0xdc00).contains(u)) {
+        units.pop();
+    }
+    let mut buf = [0; 4];
+    for c in char::decode_utf16(units.iter().copied()) {
+        out.extend_from_slice(c.unwrap_or(char::REPLACEMENT_CHARACTER).encode_utf8(&mut buf).as_bytes());
+    }
+    if last && data.len() % 2 == 1 {
+        out.extend_from_slice(char::REPLACEMENT_CHARACTER.encode_utf8(&mut buf).as_bytes());
+        return data.len();
+    }
+    2 * units.len()
+}
+
+/// Reader transcoding the input of the inner reader to UTF-8 as it is read, see [`decode`].
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    sniffed: bool,
+    eof: bool,
+    // read from the inner reader but not decoded yet
+    input: Vec<u8>,
+    // decoded but not read yet from `output[pos..]`
+    output: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Decoder<R> {
+        Decoder { inner, encoding, sniffed: false, eof: false, input: Vec::new(), output: Vec::new(), pos: 0 }
+    }
+
+    // decodes the next chunk of the inner reader
+    fn fill(&mut self) -> Result<(), Error> {
+        let len = self.input.len();
+        self.input.resize(len + DECODER_CHUNK_SIZE, 0);
+        let n = self.inner.read(&mut self.input[len..]);
+        self.input.truncate(len + *n.as_ref().unwrap_or(&0));
+        self.eof = n? == 0;
+        // the longest byte order mark has 3 bytes
+        if !self.sniffed && (self.input.len() >= 3 || self.eof) {
+            let (encoding, bom) = self.encoding.sniff(&self.input);
+            self.input.drain(..bom);
+            (self.encoding, self.sniffed) = (encoding, true);
+        }
+        if self.sniffed {
+            self.output.clear();
+            self.pos = 0;
+            let decoded = decode_into(&self.input, self.encoding, &mut self.output, self.eof);
+            self.input.drain(..decoded);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.pos == self.output.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reads one byte at a time, so that the code units and the surrogate pairs are split between the reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let n = buf.len().min(self.0.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn decoded(data: &[u8], encoding: Encoding) -> String {
+        let mut streamed = String::new();
+        Decoder::new(Trickle(data), encoding).read_to_string(&mut streamed).unwrap();
+        assert_eq!(decode(data, encoding), streamed.as_bytes());
+        streamed
+    }
+
+    fn utf16(s: &str, bom: bool, le: bool) -> Vec<u8> {
+        let units = bom.then_some(0xfeff).into_iter().chain(s.encode_utf16());
+        units.flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() }).collect()
+    }
+
+    #[test]
+    fn encodings_are_transcoded_to_utf8() {
+        let text = "Zürich;1.5\nSão Paulo;-3.0\n𝄞;2.0\n";
+        assert_eq!(decoded(text.as_bytes(), Encoding::Utf8), text);
+        assert_eq!(decoded(b"Z\xfcrich;1.5\nS\xe3o Paulo;-3.0\n", Encoding::Latin1), "Zürich;1.5\nSão Paulo;-3.0\n");
+        assert!(matches!(decode(b"Oslo;1.0\n", Encoding::Latin1), Cow::Borrowed(_)));
+        for (bom, le) in [(true, true), (true, false), (false, true)] {
+            // the byte order mark overrides the configured encoding
+            let encoding = if bom { Encoding::Latin1 } else { Encoding::Utf16Le };
+            assert_eq!(decoded(&utf16(text, bom, le), encoding), text);
+        }
+        let bom: Vec<u8> = [b"\xef\xbb\xbf".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decoded(&bom, Encoding::Latin1), text);
+
+        // a lone surrogate and an odd trailing byte
+        assert_eq!(decoded(&[0x3d, 0xd8, b'x', 0, b'y'], Encoding::Utf16Le), "\u{fffd}x\u{fffd}");
+        assert_eq!(decoded(b"", Encoding::Utf16Le), "");
+    }
+}
@@ -0,0 +1,1100 @@
+//! Output formats of the aggregated results.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, Write};
+
+use anstyle::{AnsiColor, Style};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::parse::Scale;
+use crate::{CheckedStationData, ExtremeOffsets, FirstLast, FirstLastStationData, Histogram, StationData, StationHistogram, TrackedStationData};
+
+mod html;
+mod metrics;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod prometheus;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod template;
+
+pub use html::{write_html, RunMeta};
+pub use metrics::{metrics_rows, write_metrics_brace, write_metrics_csv, write_metrics_json, write_metrics_plain, MetricsRow};
+#[cfg(feature = "parquet")]
+pub use parquet::{record_batch, write_parquet};
+pub use prometheus::write_prometheus;
+#[cfg(feature = "sqlite")]
+pub use sqlite::write_sqlite;
+pub use template::{write_template, Template};
+
+/// Aggregation states that can be written by the output formats.
+pub trait Stats {
+    fn data(&self) -> &StationData;
+
+    fn histogram(&self) -> Option<&[u64]> {
+        None
+    }
+
+    fn offsets(&self) -> Option<ExtremeOffsets> {
+        None
+    }
+
+    fn first_last(&self) -> Option<FirstLast> {
+        None
+    }
+}
+
+impl<S: Stats> Stats for &S {
+    fn data(&self) -> &StationData {
+        (*self).data()
+    }
+
+    fn histogram(&self) -> Option<&[u64]> {
+        (*self).histogram()
+    }
+
+    fn offsets(&self) -> Option<ExtremeOffsets> {
+        (*self).offsets()
+    }
+
+    fn first_last(&self) -> Option<FirstLast> {
+        (*self).first_last()
+    }
+}
+
+impl Stats for StationData {
+    fn data(&self) -> &StationData {
+        self
+    }
+}
+
+impl Stats for StationHistogram {
+    fn data(&self) -> &StationData {
+        &self.data
+    }
+
+    fn histogram(&self) -> Option<&[u64]> {
+        Some(&self.counts)
+    }
+}
+
+impl Stats for TrackedStationData {
+    fn data(&self) -> &StationData {
+        &self.data
+    }
+
+    fn offsets(&self) -> Option<ExtremeOffsets> {
+        Some(self.offsets)
+    }
+}
+
+impl Stats for FirstLastStationData {
+    fn data(&self) -> &StationData {
+        &self.data
+    }
+
+    fn first_last(&self) -> Option<FirstLast> {
+        Some(self.first_last)
+    }
+}
+
+impl Stats for CheckedStationData {
+    fn data(&self) -> &StationData {
+        &self.data
+    }
+}
+
+/// A single station of the output.
+#[derive(Clone, Copy)]
+pub struct Row<'a> {
+    pub station: &'a str,
+    pub data: &'a StationData,
+    pub histogram: Option<&'a [u64]>,
+    /// Offsets of the records with the min and the max, see [`TrackExtremes`](crate::TrackExtremes)
+    pub offsets: Option<ExtremeOffsets>,
+    /// First and last temperature, see [`TrackFirstLast`](crate::TrackFirstLast)
+    pub first_last: Option<FirstLast>,
+    /// Number of decimals of the min, mean and max
+    pub precision: usize,
+    pub rounding: Rounding,
+    /// Fixed-point scale of the temperatures of the data
+    pub scale: Scale,
+}
+
+impl<'a> Row<'a> {
+    /// A station without records, written with the [`NO_DATA`] marker or as null. Its min, mean and max are NaN.
+    pub fn no_data(station: &'a str) -> Row<'a> {
+        Row { station, data: &NO_RECORDS, histogram: None, offsets: None, first_last: None, precision: DEFAULT_PRECISION, rounding: Rounding::HalfUp, scale: Scale::TENTHS }
+    }
+
+    pub fn has_data(&self) -> bool {
+        self.data.count() > 0
+    }
+
+    pub fn min(&self) -> f64 {
+        if self.has_data() { round_scaled(self.data.min_temp as i64, 1, self.scale, self.precision, self.rounding) } else { f64::NAN }
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.has_data() { round_scaled(self.data.sum_temp, self.data.count() as u64, self.scale, self.precision, self.rounding) } else { f64::NAN }
+    }
+
+    pub fn max(&self) -> f64 {
+        if self.has_data() { round_scaled(self.data.max_temp as i64, 1, self.scale, self.precision, self.rounding) } else { f64::NAN }
+    }
+
+    /// The first temperature of the station, if tracked, rounded like the min and max.
+    pub fn first(&self) -> Option<f64> {
+        self.first_last.map(|f| round_scaled(f.first as i64, 1, self.scale, self.precision, self.rounding))
+    }
+
+    /// The last temperature of the station, if tracked, rounded like the min and max.
+    pub fn last(&self) -> Option<f64> {
+        self.first_last.map(|f| round_scaled(f.last as i64, 1, self.scale, self.precision, self.rounding))
+    }
+}
+
+/// Written by the text formats instead of the statistics of a [`Row::no_data`].
+pub const NO_DATA: &str = "N/A";
+
+const NO_RECORDS: StationData = StationData { min_temp: 0, max_temp: 0, n: 0, sum_temp: 0 };
+
+/// Number of decimals of the challenge, the only precision that follows its rounding rule.
+pub const DEFAULT_PRECISION: usize = 1;
+
+// halves are rounded up like `Math.round` in the reference implementation
+pub(crate) fn round(v: f64) -> f64 {
+    (v * 10.0 + 0.5).floor() / 10.0
+}
+
+/// How the min, mean and max are rounded to the precision of the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Halves toward positive infinity, like `Math.round` in the reference implementation
+    #[default]
+    HalfUp,
+    /// Halves to the even neighbour
+    HalfEven,
+    /// Toward zero
+    Truncate,
+}
+
+impl Rounding {
+    // rounds `num / den` to an integer, `den` is positive
+    fn apply(self, num: i128, den: i128) -> i128 {
+        match self {
+            Rounding::HalfUp => (2 * num + den).div_euclid(2 * den),
+            Rounding::HalfEven => {
+                let (q, r) = (num.div_euclid(den), num.rem_euclid(den));
+                match (2 * r).cmp(&den) {
+                    Ordering::Less => q,
+                    Ordering::Greater => q + 1,
+                    Ordering::Equal => q + q.rem_euclid(2),
+                }
+            }
+            Rounding::Truncate => num / den,
+        }
+    }
+}
+
+// the ratio `value / count` of temperatures in the scale rounded to `precision` decimals, computed exactly with integers
+fn round_scaled(value: i64, count: u64, scale: Scale, precision: usize, rounding: Rounding) -> f64 {
+    // in units of the last decimal
+    let decimals = scale.decimals() as usize;
+    let (num, den) = match precision.checked_sub(decimals) {
+        Some(more) => (value as i128 * 10i128.pow(more as u32), count as i128),
+        None => (value as i128, count as i128 * 10i128.pow((decimals - precision) as u32)),
+    };
+    let units = rounding.apply(num, den);
+    // 0.0 rather than -0.0 for the negative values rounded to zero
+    units as f64 / 10f64.powi(precision as i32) + 0.0
+}
+
+/// The statistics of all the stations together, see [`Total::of`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Total {
+    pub stations: usize,
+    pub count: u64,
+    pub sum_temp: i64,
+    pub min_temp: i16,
+    pub max_temp: i16,
+    /// Number of decimals and rounding of the min, mean and max and the scale of the temperatures, those of the rows
+    pub precision: usize,
+    pub rounding: Rounding,
+    pub scale: Scale,
+}
+
+impl Total {
+    /// Sums up the rows, `None` if none of them has data.
+    ///
+    /// The mean is that of all the measurements, from the total sum and count, not the mean of the means of the stations.
+    pub fn of(rows: &[Row]) -> Option<Total> {
+        let first = rows.iter().find(|r| r.has_data())?;
+        let mut total = Total {
+            stations: rows.len(),
+            count: 0,
+            sum_temp: 0,
+            min_temp: first.data.min_temp,
+            max_temp: first.data.max_temp,
+            precision: first.precision,
+            rounding: first.rounding,
+            scale: first.scale,
+        };
+        for r in rows.iter().filter(|r| r.has_data()) {
+            total.count += r.data.count() as u64;
+            total.sum_temp += r.data.sum_temp;
+            total.min_temp = total.min_temp.min(r.data.min_temp);
+            total.max_temp = total.max_temp.max(r.data.max_temp);
+        }
+        Some(total)
+    }
+
+    pub fn min(&self) -> f64 {
+        round_scaled(self.min_temp as i64, 1, self.scale, self.precision, self.rounding)
+    }
+
+    pub fn mean(&self) -> f64 {
+        round_scaled(self.sum_temp, self.count, self.scale, self.precision, self.rounding)
+    }
+
+    pub fn max(&self) -> f64 {
+        round_scaled(self.max_temp as i64, 1, self.scale, self.precision, self.rounding)
+    }
+}
+
+#[derive(Serialize)]
+struct TotalRecord {
+    min: f64,
+    mean: f64,
+    max: f64,
+    count: u64,
+    stations: usize,
+}
+
+impl From<&Total> for TotalRecord {
+    fn from(t: &Total) -> Self {
+        TotalRecord { min: t.min(), mean: t.mean(), max: t.max(), count: t.count, stations: t.stations }
+    }
+}
+
+/// Writes the total as a line of its own after the rows of the brace, plain and Markdown formats.
+///
+/// The line has no `=`, so that it cannot be read as a station.
+pub fn write_total_line<W: Write>(w: &mut W, total: &Total) -> Result<(), Error> {
+    writeln!(w, "Total of {} stations, {} measurements: {:.p$}/{:.p$}/{:.p$}", total.stations, total.count, total.min(), total.mean(), total.max(), p = total.precision)
+}
+
+/// Returns the rows of the output sorted by station name.
+pub fn rows<K: AsRef<str>, S: Stats>(m: &HashMap<K, S>) -> Vec<Row<'_>> {
+    let mut rows: Vec<Row> = m.iter()
+        .map(|(station, stats)| Row { station: station.as_ref(), data: stats.data(), histogram: stats.histogram(), offsets: stats.offsets(), first_last: stats.first_last(), precision: DEFAULT_PRECISION, rounding: Rounding::HalfUp, scale: Scale::TENTHS })
+        .collect();
+    rows.sort_unstable_by(|r1, r2| r1.station.cmp(r2.station));
+    rows
+}
+
+/// The stations with the highest max and the lowest min.
+#[derive(Debug, PartialEq)]
+pub struct Extremes<'a> {
+    pub hottest: &'a str,
+    pub max: f64,
+    pub coldest: &'a str,
+    pub min: f64,
+}
+
+/// Finds the hottest and the coldest station in one pass over the map, ties go to the first station alphabetically.
+/// The temperatures of the map are in the scale.
+pub fn extremes<K: AsRef<str>, S: Stats>(m: &HashMap<K, S>, scale: Scale) -> Option<Extremes<'_>> {
+    let mut iter = m.iter().map(|(station, stats)| (station.as_ref(), stats.data()));
+    let (station, data) = iter.next()?;
+    let (mut hottest, mut coldest) = ((station, data.max_temp), (station, data.min_temp));
+    for (station, data) in iter {
+        if (data.max_temp, std::cmp::Reverse(station)) > (hottest.1, std::cmp::Reverse(hottest.0)) {
+            hottest = (station, data.max_temp);
+        }
+        if (data.min_temp, station) < (coldest.1, coldest.0) {
+            coldest = (station, data.min_temp);
+        }
+    }
+    let factor = scale.factor() as f64;
+    Some(Extremes { hottest: hottest.0, max: hottest.1 as f64 / factor, coldest: coldest.0, min: coldest.1 as f64 / factor })
+}
+
+/// Order of the stations in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    /// Byte order of the UTF-8 names, as in the reference output
+    #[default]
+    Bytes,
+    /// Case-insensitive order of the names without their accents (NFD without the combining marks),
+    /// names that are equal this way are ordered by their accents, then by their bytes
+    Unicode,
+}
+
+impl Collation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Collation::Bytes => "bytes",
+            Collation::Unicode => "unicode",
+        }
+    }
+}
+
+/// Sorts the rows by station in the order of the collation.
+pub fn collate(rows: &mut [Row], collation: Collation) {
+    match collation {
+        Collation::Bytes => rows.sort_unstable_by(|r1, r2| r1.station.cmp(r2.station)),
+        Collation::Unicode => rows.sort_by_cached_key(|r| {
+            let folded: String = r.station.nfd().collect::<String>().to_lowercase();
+            let base: String = folded.chars().filter(|&c| !is_combining_mark(c)).collect();
+            (base, folded, r.station)
+        }),
+    }
+}
+
+/// Statistic the rows are sorted by with [`sort_rows`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// The order of the collation
+    #[default]
+    Station,
+    Min,
+    Mean,
+    Max,
+    Count,
+}
+
+/// Sorts the rows by the statistic in ascending order, the stations with equal values keep their order.
+///
+/// The statistics are compared as written, rounded to the precision. The stations without records come last.
+pub fn sort_rows<'a>(rows: &mut [Row<'a>], key: SortKey) {
+    let value: fn(&Row<'a>) -> f64 = match key {
+        SortKey::Station => return,
+        SortKey::Min => Row::min,
+        SortKey::Mean => Row::mean,
+        SortKey::Max => Row::max,
+        SortKey::Count => |r| r.data.count() as f64,
+    };
+    // the NaN of the stations without records is after all the numbers
+    rows.sort_by(|r1, r2| value(r1).total_cmp(&value(r2)));
+}
+
+/// Names of the files of the rows written one per file, without an extension, in the order of the rows.
+///
+/// Characters other than letters, digits, `-` and `_` are replaced with `_`. Names that would collide,
+/// also on case-insensitive file systems, get a numeric suffix.
+pub fn file_names(rows: &[Row]) -> Vec<String> {
+    let mut taken: HashSet<String> = HashSet::new();
+    rows.iter()
+        .map(|r| {
+            let base: String = r.station.chars()
+                .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                .take(MAX_FILE_NAME_CHARS)
+                .collect();
+            let base = if base.is_empty() { "_".to_owned() } else { base };
+            let mut name = base.clone();
+            let mut n = 1;
+            while !taken.insert(name.to_lowercase()) {
+                n += 1;
+                name = format!("{}-{}", base, n);
+            }
+            name
+        })
+        .collect()
+}
+
+// leaves room for the suffix and the extension within the usual limit of 255 bytes, with up to 4 bytes per character
+const MAX_FILE_NAME_CHARS: usize = 60;
+
+const COLDEST: Style = AnsiColor::Blue.on_default();
+const HOTTEST: Style = AnsiColor::Red.on_default();
+
+/// Writes the `{station=min/mean/max, ...}` format of the challenge.
+///
+/// With `highlight`, the coldest min and the hottest max are colored using ANSI escape codes.
+pub fn write_brace<W: Write>(w: &mut W, rows: &[Row], highlight: bool) -> Result<(), Error> {
+    let coldest = rows.iter().filter(|r| r.has_data()).map(|r| r.data.min_temp).min().filter(|_| highlight);
+    let hottest = rows.iter().filter(|r| r.has_data()).map(|r| r.data.max_temp).max().filter(|_| highlight);
+    let list: Vec<String> = rows.iter()
+        .map(|r| {
+            if !r.has_data() {
+                return format!("{}={}", r.station, NO_DATA);
+            }
+            let min_style = if Some(r.data.min_temp) == coldest { COLDEST } else { Style::new() };
+            let max_style = if Some(r.data.max_temp) == hottest { HOTTEST } else { Style::new() };
+            format!("{}={min_style}{:.p$}{min_style:#}/{:.p$}/{max_style}{:.p$}{max_style:#}", r.station, r.min(), r.mean(), r.max(), p = r.precision)
+        })
+        .collect();
+    writeln!(w, "{{{}}}", list.join(", "))
+}
+
+/// Writes one `station=min/mean/max` line per station.
+pub fn write_plain<W: Write>(w: &mut W, rows: &[Row]) -> Result<(), Error> {
+    for r in rows {
+        match r.has_data() {
+            true => writeln!(w, "{}={:.p$}/{:.p$}/{:.p$}", r.station, r.min(), r.mean(), r.max(), p = r.precision)?,
+            false => writeln!(w, "{}={}", r.station, NO_DATA)?,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct StationRecord<'a> {
+    min: f64,
+    mean: f64,
+    max: f64,
+    count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<&'a [u64]>,
+}
+
+impl<'a> From<&Row<'a>> for StationRecord<'a> {
+    fn from(r: &Row<'a>) -> Self {
+        StationRecord {
+            min: r.min(),
+            mean: r.mean(),
+            max: r.max(),
+            count: r.data.count(),
+            min_offset: r.offsets.map(|o| o.min),
+            max_offset: r.offsets.map(|o| o.max),
+            first: r.first(),
+            last: r.last(),
+            histogram: r.histogram,
+        }
+    }
+}
+
+// serializes the rows as a map of the station names to their records, in the order of the rows
+struct Records<'a>(&'a [Row<'a>]);
+
+impl Serialize for Records<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for r in self.0 {
+            map.serialize_entry(r.station, &StationRecord::from(r))?;
+        }
+        map.end()
+    }
+}
+
+/// Writes a JSON object mapping the station names to their statistics.
+pub fn write_json<W: Write>(w: &mut W, rows: &[Row]) -> Result<(), Error> {
+    serde_json::to_writer(&mut *w, &Records(rows))?;
+    writeln!(w)
+}
+
+#[derive(Serialize)]
+struct NdjsonRecord<'a> {
+    station: &'a str,
+    #[serde(flatten)]
+    record: StationRecord<'a>,
+}
+
+#[derive(Serialize)]
+struct NdjsonSummary {
+    stations: usize,
+    count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<f64>,
+}
+
+/// Writes a JSON array of one `[station, min, mean, max]` array per row, in the order of the rows.
+///
+/// Unlike the keys of [`write_json`], the order survives any JSON parser. The statistics of the stations without
+/// records are null.
+pub fn write_json_array<W: Write>(w: &mut W, rows: &[Row]) -> Result<(), Error> {
+    let tuples: Vec<(&str, f64, f64, f64)> = rows.iter().map(|r| (r.station, r.min(), r.mean(), r.max())).collect();
+    serde_json::to_writer(&mut *w, &tuples)?;
+    writeln!(w)
+}
+
+/// Writes one JSON object per station and line, with the statistics of [`write_json`] and the station name under `station`.
+///
+/// The lines are written one by one as the rows are serialized. With `summary`, a last line
+/// `{"summary":{...}}` holds the number of stations and measurements and the overall min, mean and max.
+pub fn write_ndjson<W: Write>(w: &mut W, rows: &[Row], summary: bool) -> Result<(), Error> {
+    for r in rows {
+        serde_json::to_writer(&mut *w, &NdjsonRecord { station: r.station, record: StationRecord::from(r) })?;
+        writeln!(w)?;
+    }
+    if summary {
+        let count: u64 = rows.iter().map(|r| r.data.count() as u64).sum();
+        let sum: i64 = rows.iter().map(|r| r.data.sum_temp).sum();
+        let summary = NdjsonSummary {
+            stations: rows.len(),
+            count,
+            min: rows.iter().map(|r| r.min()).reduce(f64::min),
+            mean: (count > 0).then(|| round_scaled(sum, count, rows[0].scale, rows[0].precision, rows[0].rounding)),
+            max: rows.iter().map(|r| r.max()).reduce(f64::max),
+        };
+        serde_json::to_writer(&mut *w, &HashMap::from([("summary", summary)]))?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Header of the JSON output written by [`write_json_with_metadata`].
+#[derive(Serialize)]
+pub struct JsonMeta<'a> {
+    pub unit: &'a str,
+    pub generated_at: &'a str,
+    pub collation: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    #[serde(flatten)]
+    meta: Option<&'a JsonMeta<'a>>,
+    stations: Records<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<TotalRecord>,
+}
+
+/// Writes a JSON object with the metadata and the stations of [`write_json`] under `stations`.
+pub fn write_json_with_metadata<W: Write>(w: &mut W, rows: &[Row], meta: &JsonMeta) -> Result<(), Error> {
+    write_json_document(w, rows, Some(meta), None)
+}
+
+/// Writes a JSON object with the stations of [`write_json`] under `stations`, the metadata if any
+/// and the total under `total`.
+pub fn write_json_document<W: Write>(w: &mut W, rows: &[Row], meta: Option<&JsonMeta>, total: Option<&Total>) -> Result<(), Error> {
+    serde_json::to_writer(&mut *w, &JsonDocument { meta, stations: Records(rows), total: total.map(TotalRecord::from) })?;
+    writeln!(w)
+}
+
+/// Writes a YAML mapping of the station names to their statistics, structured like the JSON output.
+///
+/// With a total, the stations are under `stations` and the total under `total`, like in [`write_json_document`].
+pub fn write_yaml<W: Write>(w: &mut W, rows: &[Row], total: Option<&Total>) -> Result<(), Error> {
+    match total {
+        Some(total) => serde_yaml::to_writer(w, &JsonDocument { meta: None, stations: Records(rows), total: Some(total.into()) }),
+        None => serde_yaml::to_writer(w, &Records(rows)),
+    }
+    .map_err(Error::other)
+}
+
+const COLUMNS: [&str; 5] = ["station", "min", "mean", "max", "count"];
+
+// the cells of the tabular formats, in the order of `COLUMNS`
+fn cells(r: &Row) -> [String; 5] {
+    let value = |v: f64| if r.has_data() { format!("{:.p$}", v, p = r.precision) } else { NO_DATA.to_owned() };
+    [r.station.to_owned(), value(r.min()), value(r.mean()), value(r.max()), r.data.count().to_string()]
+}
+
+/// Writes a CSV table with a header, with one column per bucket if the rows have histograms,
+/// `min_offset` and `max_offset` columns if they have the offsets of the extremes
+/// and `first` and `last` columns if they have the first and last temperatures.
+///
+/// The total is a last row with an empty station name, which no station has.
+pub fn write_csv<W: Write>(w: &mut W, rows: &[Row], histogram: Option<&Histogram>, total: Option<&Total>) -> Result<(), Error> {
+    write!(w, "{}", COLUMNS.join(","))?;
+    let offsets = rows.iter().any(|r| r.offsets.is_some());
+    if offsets {
+        write!(w, ",min_offset,max_offset")?;
+    }
+    let first_last = rows.iter().any(|r| r.first_last.is_some());
+    if first_last {
+        write!(w, ",first,last")?;
+    }
+    if let Some(h) = histogram {
+        for i in 0..h.buckets() {
+            write!(w, ",{:.1}", h.bucket_start(i) as f64 / 10.0)?;
+        }
+    }
+    writeln!(w)?;
+    for r in rows {
+        let [station, rest @ ..] = cells(r);
+        write!(w, "{},{}", csv_escape(&station), rest.join(","))?;
+        if let Some(o) = r.offsets.filter(|_| offsets) {
+            write!(w, ",{},{}", o.min, o.max)?;
+        }
+        if let (Some(first), Some(last)) = (r.first(), r.last()) {
+            write!(w, ",{:.p$},{:.p$}", first, last, p = r.precision)?;
+        }
+        for count in r.histogram.unwrap_or_default() {
+            write!(w, ",{}", count)?;
+        }
+        writeln!(w)?;
+    }
+    if let Some(t) = total {
+        let p = t.precision;
+        write!(w, ",{:.p$},{:.p$},{:.p$},{}", t.min(), t.mean(), t.max(), t.count)?;
+        // the other columns are left empty
+        let empty = 2 * offsets as usize + 2 * first_last as usize + histogram.map_or(0, |h| h.buckets());
+        writeln!(w, "{}", ",".repeat(empty))?;
+    }
+    Ok(())
+}
+
+/// Writes a Markdown table with right-aligned numeric columns.
+pub fn write_markdown<W: Write>(w: &mut W, rows: &[Row]) -> Result<(), Error> {
+    let header: Vec<String> = COLUMNS.iter().map(|c| c[..1].to_uppercase() + &c[1..]).collect();
+    writeln!(w, "| {} |", header.join(" | "))?;
+    writeln!(w, "|---|---:|---:|---:|---:|")?;
+    for r in rows {
+        let [station, rest @ ..] = cells(r);
+        writeln!(w, "| {} | {} |", station.replace('|', "\\|"), rest.join(" | "))?;
+    }
+    Ok(())
+}
+
+/// Default of the widest station name of [`write_table`], in characters.
+pub const DEFAULT_TABLE_STATION_WIDTH: usize = 32;
+
+/// Writes a table for terminals with the columns aligned with spaces, the numbers right-aligned.
+///
+/// The columns are as wide as their widest cell, the station names longer than `max_station_width` characters
+/// are truncated with an ellipsis. The widths are counted in characters, wide characters misalign the rows.
+pub fn write_table<W: Write>(w: &mut W, rows: &[Row], max_station_width: usize) -> Result<(), Error> {
+    assert!(max_station_width > 0, "The station names need at least one character");
+    let header: [String; 5] = COLUMNS.map(|c| c[..1].to_uppercase() + &c[1..]);
+    let table: Vec<[String; 5]> = rows.iter()
+        .map(|r| {
+            let mut cells = cells(r);
+            if cells[0].chars().nth(max_station_width).is_some() {
+                cells[0] = cells[0].chars().take(max_station_width - 1).chain(std::iter::once('…')).collect();
+            }
+            cells
+        })
+        .collect();
+    let widths: [usize; 5] = std::array::from_fn(|i| std::iter::once(&header).chain(&table).map(|c| c[i].chars().count()).max().unwrap_or(0));
+    for [station, rest @ ..] in std::iter::once(&header).chain(&table) {
+        write!(w, "{:<width$}", station, width = widths[0])?;
+        for (cell, width) in rest.iter().zip(&widths[1..]) {
+            write!(w, "  {:>width$}", cell, width = width)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Formats of the station counts written by [`write_counts`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountsFormat {
+    Brace,
+    Plain,
+    Json,
+    Ndjson,
+    Yaml,
+    Csv,
+    Markdown,
+}
+
+/// The number of measurements of every station, the largest first, the ties in the order of the names.
+pub fn counts<'a>(rows: &[Row<'a>]) -> Vec<(&'a str, u32)> {
+    let mut counts: Vec<(&str, u32)> = rows.iter().map(|r| (r.station, r.data.count())).collect();
+    counts.sort_unstable_by(|(s1, c1), (s2, c2)| c2.cmp(c1).then_with(|| s1.cmp(s2)));
+    counts
+}
+
+// serializes the counts as a map of the station names to their counts, in the order of the counts
+struct CountMap<'a>(&'a [(&'a str, u32)]);
+
+impl Serialize for CountMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (station, count) in self.0 {
+            map.serialize_entry(station, count)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct NdjsonCount<'a> {
+    station: &'a str,
+    count: u32,
+}
+
+/// Writes the station counts of [`counts`] in the format, `station,count` lines with a header in CSV.
+pub fn write_counts<W: Write>(w: &mut W, counts: &[(&str, u32)], format: CountsFormat) -> Result<(), Error> {
+    match format {
+        CountsFormat::Brace => {
+            let list: Vec<String> = counts.iter().map(|(station, count)| format!("{}={}", station, count)).collect();
+            writeln!(w, "{{{}}}", list.join(", "))?;
+        }
+        CountsFormat::Plain => {
+            for (station, count) in counts {
+                writeln!(w, "{}={}", station, count)?;
+            }
+        }
+        CountsFormat::Json => {
+            serde_json::to_writer(&mut *w, &CountMap(counts))?;
+            writeln!(w)?;
+        }
+        CountsFormat::Ndjson => {
+            for &(station, count) in counts {
+                serde_json::to_writer(&mut *w, &NdjsonCount { station, count })?;
+                writeln!(w)?;
+            }
+        }
+        CountsFormat::Yaml => serde_yaml::to_writer(w, &CountMap(counts)).map_err(Error::other)?,
+        CountsFormat::Csv => {
+            writeln!(w, "station,count")?;
+            for (station, count) in counts {
+                writeln!(w, "{},{}", csv_escape(station), count)?;
+            }
+        }
+        CountsFormat::Markdown => {
+            writeln!(w, "| Station | Count |")?;
+            writeln!(w, "|---|---:|")?;
+            for (station, count) in counts {
+                writeln!(w, "| {} | {} |", station.replace('|', "\\|"), count)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Aggregator, MinMeanMax, TrackExtremes, TrackFirstLast};
+
+    fn stations() -> HashMap<&'static str, StationData> {
+        let mut m = HashMap::new();
+        let mut hamburg = MinMeanMax.init(120);
+        MinMeanMax.observe(&mut hamburg, -34);
+        m.insert("Hamburg", hamburg);
+        m.insert("Bulawayo", MinMeanMax.init(89));
+        m.insert("St. \"John\", NL", MinMeanMax.init(-5));
+        m
+    }
+
+    fn output<F: Fn(&mut Vec<u8>) -> Result<(), Error>>(f: F) -> String {
+        let mut out = Vec::new();
+        f(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn precision_0_and_2() {
+        let mut oslo = StationData::new(-34);
+        MinMeanMax.observe(&mut oslo, 125);
+        MinMeanMax.observe(&mut oslo, 16);
+        let m = HashMap::from([("Oslo", oslo)]);
+        let output = |precision: usize| {
+            let mut rows = rows(&m);
+            rows[0].precision = precision;
+            let (mut plain, mut json, mut csv) = (Vec::new(), Vec::new(), Vec::new());
+            write_plain(&mut plain, &rows).unwrap();
+            write_json(&mut json, &rows).unwrap();
+            write_csv(&mut csv, &rows, None, None).unwrap();
+            [plain, json, csv].map(|out| String::from_utf8(out).unwrap())
+        };
+        // the mean is 3.5666...
+        assert_eq!(output(1), ["Oslo=-3.4/3.6/12.5\n", "{\"Oslo\":{\"min\":-3.4,\"mean\":3.6,\"max\":12.5,\"count\":3}}\n",
+                               "station,min,mean,max,count\nOslo,-3.4,3.6,12.5,3\n"]);
+        assert_eq!(output(2), ["Oslo=-3.40/3.57/12.50\n", "{\"Oslo\":{\"min\":-3.4,\"mean\":3.57,\"max\":12.5,\"count\":3}}\n",
+                               "station,min,mean,max,count\nOslo,-3.40,3.57,12.50,3\n"]);
+        // the halves are rounded up at every precision
+        assert_eq!(output(0), ["Oslo=-3/4/13\n", "{\"Oslo\":{\"min\":-3.0,\"mean\":4.0,\"max\":13.0,\"count\":3}}\n",
+                               "station,min,mean,max,count\nOslo,-3,4,13,3\n"]);
+    }
+
+    #[test]
+    fn rounding_modes() {
+        // the means of 0.0 and 0.1, -0.0 and -0.1, 0.1 and 0.2, -0.1 and -0.2
+        let means = |rounding: Rounding, precision: usize| -> Vec<String> {
+            [(0, 1), (0, -1), (1, 2), (-2, -1)].into_iter()
+                .map(|(t1, t2)| {
+                    let data = StationData { min_temp: t1.min(t2), max_temp: t1.max(t2), n: 2, sum_temp: (t1 + t2) as i64 };
+                    let row = Row { precision, rounding, ..Row::no_data("") };
+                    format!("{:.p$}", Row { data: &data, ..row }.mean(), p = precision)
+                })
+                .collect()
+        };
+        assert_eq!(means(Rounding::HalfUp, 1), ["0.1", "0.0", "0.2", "-0.1"]);
+        assert_eq!(means(Rounding::HalfEven, 1), ["0.0", "0.0", "0.2", "-0.2"]);
+        assert_eq!(means(Rounding::Truncate, 1), ["0.0", "0.0", "0.1", "-0.1"]);
+        assert_eq!(means(Rounding::HalfUp, 2), ["0.05", "-0.05", "0.15", "-0.15"]);
+
+        let data = StationData { min_temp: -25, max_temp: 35, n: 1, sum_temp: 0 };
+        let row = |rounding| Row { data: &data, precision: 0, rounding, ..Row::no_data("") };
+        let extremes = |r: Row| (r.min(), r.max());
+        assert_eq!(extremes(row(Rounding::HalfUp)), (-2.0, 4.0));
+        assert_eq!(extremes(row(Rounding::HalfEven)), (-2.0, 4.0));
+        assert_eq!(extremes(row(Rounding::Truncate)), (-2.0, 3.0));
+    }
+
+    #[test]
+    fn hundredths_are_divided_back() {
+        // 12.34, -5.67 and 0.05 in hundredths
+        let data = StationData { min_temp: -567, max_temp: 1234, n: 3, sum_temp: 1234 - 567 + 5 };
+        let hundredths = Scale::new(100).unwrap();
+        let row = |precision| Row { data: &data, precision, scale: hundredths, ..Row::no_data("Oslo") };
+        assert_eq!((row(2).min(), row(2).mean(), row(2).max()), (-5.67, 2.24, 12.34));
+        assert_eq!((row(1).min(), row(1).mean(), row(1).max()), (-5.7, 2.2, 12.3));
+        assert_eq!(row(3).mean(), 2.24);
+        let rows = [row(2)];
+        let total = Total::of(&rows).unwrap();
+        assert_eq!((total.min(), total.mean(), total.max()), (-5.67, 2.24, 12.34));
+        let mut out = Vec::new();
+        write_brace(&mut out, &rows, false).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{Oslo=-5.67/2.24/12.34}\n");
+        let m = HashMap::from([("Oslo", data)]);
+        assert_eq!(extremes(&m, hundredths), Some(Extremes { hottest: "Oslo", max: 12.34, coldest: "Oslo", min: -5.67 }));
+    }
+
+    #[test]
+    fn ndjson_lines() {
+        let m = stations();
+        let mut out = Vec::new();
+        write_ndjson(&mut out, &rows(&m)[..1], true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"station\":\"Bulawayo\",\"min\":8.9,\"mean\":8.9,\"max\":8.9,\"count\":1}\n\
+            {\"summary\":{\"stations\":1,\"count\":1,\"min\":8.9,\"mean\":8.9,\"max\":8.9}}\n");
+        let mut out = Vec::new();
+        write_ndjson(&mut out, &[], true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"summary\":{\"stations\":0,\"count\":0}}\n");
+    }
+
+    #[test]
+    fn file_names_are_sanitized_and_unique() {
+        let m: HashMap<&str, StationData> = ["São Paulo", "São_Paulo", "../etc", "a/b", "A/B", ""].into_iter()
+            .map(|s| (s, StationData::new(0)))
+            .collect();
+        let mut rows = rows(&m);
+        collate(&mut rows, Collation::Bytes);
+        assert_eq!(file_names(&rows), ["_", "___etc", "A_B", "São_Paulo", "São_Paulo-2", "a_b-2"].map(str::to_owned));
+    }
+
+    #[test]
+    fn json_metadata_header() {
+        let m = stations();
+        let meta = JsonMeta { unit: "celsius", generated_at: "2024-01-01T00:00:00Z", collation: Collation::Bytes.name() };
+        let mut out = Vec::new();
+        write_json_with_metadata(&mut out, &rows(&m)[..1], &meta).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"unit\":\"celsius\",\"generated_at\":\"2024-01-01T00:00:00Z\",\"collation\":\"bytes\",\
+            \"stations\":{\"Bulawayo\":{\"min\":8.9,\"mean\":8.9,\"max\":8.9,\"count\":1}}}\n");
+    }
+
+    #[test]
+    fn extremes_prefer_first_station_on_ties() {
+        let mut m = stations();
+        m.insert("Aachen", MinMeanMax.init(120));
+        m.insert("Zagreb", MinMeanMax.init(-34));
+        let e = extremes(&m, Scale::TENTHS).unwrap();
+        assert_eq!(e, Extremes { hottest: "Aachen", max: 12.0, coldest: "Hamburg", min: -3.4 });
+        assert_eq!(extremes(&HashMap::<&str, StationData>::new(), Scale::TENTHS), None);
+    }
+
+    #[test]
+    fn collations() {
+        let names = ["Zürich", "Ürümqi", "Urumqi", "zagreb", "Abha", "Évora", "Oslo"];
+        let m: HashMap<&str, StationData> = names.iter().map(|&n| (n, StationData::new(0))).collect();
+        let mut rows = rows(&m);
+        let order = |rows: &[Row]| rows.iter().map(|r| r.station.to_owned()).collect::<Vec<String>>();
+        assert_eq!(order(&rows), ["Abha", "Oslo", "Urumqi", "Zürich", "zagreb", "Évora", "Ürümqi"]);
+        collate(&mut rows, Collation::Unicode);
+        assert_eq!(order(&rows), ["Abha", "Évora", "Oslo", "Urumqi", "Ürümqi", "zagreb", "Zürich"]);
+        collate(&mut rows, Collation::Bytes);
+        assert_eq!(order(&rows), ["Abha", "Oslo", "Urumqi", "Zürich", "zagreb", "Évora", "Ürümqi"]);
+    }
+
+    #[test]
+    fn brace_is_sorted() {
+        let m = stations();
+        let out = output(|w| write_brace(w, &rows(&m), false));
+        assert_eq!(out, "{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/4.3/12.0, St. \"John\", NL=-0.5/-0.5/-0.5}\n");
+    }
+
+    #[test]
+    fn plain_has_one_station_per_line() {
+        let m = stations();
+        let out = output(|w| write_plain(w, &rows(&m)));
+        assert_eq!(out, "Bulawayo=8.9/8.9/8.9\nHamburg=-3.4/4.3/12.0\nSt. \"John\", NL=-0.5/-0.5/-0.5\n");
+    }
+
+    #[test]
+    fn json() {
+        let m = stations();
+        let out = output(|w| write_json(w, &rows(&m)));
+        assert_eq!(out, concat!(
+            r#"{"Bulawayo":{"min":8.9,"mean":8.9,"max":8.9,"count":1},"#,
+            r#""Hamburg":{"min":-3.4,"mean":4.3,"max":12.0,"count":2},"#,
+            r#""St. \"John\", NL":{"min":-0.5,"mean":-0.5,"max":-0.5,"count":1}}"#, "\n"));
+    }
+
+    #[test]
+    fn yaml_quotes_special_station_names() {
+        let mut m = stations();
+        m.insert("true", MinMeanMax.init(0));
+        m.insert("A: B", MinMeanMax.init(0));
+        let out = output(|w| write_yaml(w, &rows(&m), None));
+        assert_eq!(out, concat!(
+            "'A: B':\n  min: 0.0\n  mean: 0.0\n  max: 0.0\n  count: 1\n",
+            "Bulawayo:\n  min: 8.9\n  mean: 8.9\n  max: 8.9\n  count: 1\n",
+            "Hamburg:\n  min: -3.4\n  mean: 4.3\n  max: 12.0\n  count: 2\n",
+            "St. \"John\", NL:\n  min: -0.5\n  mean: -0.5\n  max: -0.5\n  count: 1\n",
+            "'true':\n  min: 0.0\n  mean: 0.0\n  max: 0.0\n  count: 1\n"));
+        let parsed: HashMap<String, HashMap<String, f64>> = serde_yaml::from_str(&out).unwrap();
+        assert_eq!(parsed["A: B"]["count"], 1.0);
+        assert_eq!(parsed["true"]["max"], 0.0);
+    }
+
+    #[test]
+    fn csv_quotes_station_names() {
+        let m = stations();
+        let out = output(|w| write_csv(w, &rows(&m), None, None));
+        assert_eq!(out, "station,min,mean,max,count\nBulawayo,8.9,8.9,8.9,1\nHamburg,-3.4,4.3,12.0,2\n\"St. \"\"John\"\", NL\",-0.5,-0.5,-0.5,1\n");
+    }
+
+    #[test]
+    fn table_columns_are_aligned() {
+        let mut m = stations();
+        m.insert("Llanfairpwllgwyngyll", MinMeanMax.init(-123));
+        let rows = rows(&m);
+        assert_eq!(output(|w| write_table(w, &rows, DEFAULT_TABLE_STATION_WIDTH)), concat!(
+            "Station                 Min   Mean    Max  Count\n",
+            "Bulawayo                8.9    8.9    8.9      1\n",
+            "Hamburg                -3.4    4.3   12.0      2\n",
+            "Llanfairpwllgwyngyll  -12.3  -12.3  -12.3      1\n",
+            "St. \"John\", NL         -0.5   -0.5   -0.5      1\n"));
+        // the header is not truncated
+        assert_eq!(output(|w| write_table(w, &rows[..3], 5)), concat!(
+            "Station    Min   Mean    Max  Count\n",
+            "Bula…      8.9    8.9    8.9      1\n",
+            "Hamb…     -3.4    4.3   12.0      2\n",
+            "Llan…    -12.3  -12.3  -12.3      1\n"));
+    }
+
+    #[test]
+    fn markdown_escapes_pipes() {
+        let mut m = stations();
+        m.insert("A|B", MinMeanMax.init(0));
+        let out = output(|w| write_markdown(w, &rows(&m)));
+        assert_eq!(out, concat!(
+            "| Station | Min | Mean | Max | Count |\n",
+            "|---|---:|---:|---:|---:|\n",
+            "| A\\|B | 0.0 | 0.0 | 0.0 | 1 |\n",
+            "| Bulawayo | 8.9 | 8.9 | 8.9 | 1 |\n",
+            "| Hamburg | -3.4 | 4.3 | 12.0 | 2 |\n",
+            "| St. \"John\", NL | -0.5 | -0.5 | -0.5 | 1 |\n"));
+    }
+
+    #[test]
+    fn histograms_in_json_and_csv() {
+        let h = Histogram::with_bucket_width(1000);
+        let mut hamburg = h.init(120);
+        h.observe(&mut hamburg, -34);
+        let m = HashMap::from([("Hamburg", hamburg)]);
+
+        let out = output(|w| write_json(w, &rows(&m)));
+        assert_eq!(out, "{\"Hamburg\":{\"min\":-3.4,\"mean\":4.3,\"max\":12.0,\"count\":2,\"histogram\":[1,1]}}\n");
+        let out = output(|w| write_csv(w, &rows(&m), Some(&h), None));
+        assert_eq!(out, "station,min,mean,max,count,-99.9,0.1\nHamburg,-3.4,4.3,12.0,2,1,1\n");
+        let out = output(|w| write_brace(w, &rows(&m), false));
+        assert_eq!(out, "{Hamburg=-3.4/4.3/12.0}\n");
+    }
+
+    #[test]
+    fn extreme_offsets_in_json_and_csv() {
+        let mut hamburg = TrackExtremes.init_at(120, 0);
+        TrackExtremes.observe_at(&mut hamburg, -34, 13);
+        let m = HashMap::from([("Hamburg", hamburg)]);
+
+        let out = output(|w| write_json(w, &rows(&m)));
+        assert_eq!(out, "{\"Hamburg\":{\"min\":-3.4,\"mean\":4.3,\"max\":12.0,\"count\":2,\"min_offset\":13,\"max_offset\":0}}\n");
+        let out = output(|w| write_csv(w, &rows(&m), None, None));
+        assert_eq!(out, "station,min,mean,max,count,min_offset,max_offset\nHamburg,-3.4,4.3,12.0,2,13,0\n");
+    }
+
+    #[test]
+    fn rows_sorted_by_mean_as_an_array() {
+        let mut oslo = StationData::new(-34);
+        MinMeanMax.observe(&mut oslo, 125);
+        let m = HashMap::from([("Oslo", oslo), ("Rome", StationData::new(150)), ("Abha", StationData::new(46)), ("Bern", StationData::new(150))]);
+        let mut rows = rows(&m);
+        rows.insert(0, Row::no_data("Accra"));
+        sort_rows(&mut rows, SortKey::Mean);
+        let out = output(|w| write_json_array(w, &rows));
+        assert_eq!(out, "[[\"Abha\",4.6,4.6,4.6],[\"Oslo\",-3.4,4.6,12.5],[\"Bern\",15.0,15.0,15.0],[\"Rome\",15.0,15.0,15.0],[\"Accra\",null,null,null]]\n");
+        sort_rows(&mut rows, SortKey::Count);
+        let stations: Vec<&str> = rows.iter().map(|r| r.station).collect();
+        assert_eq!(stations, ["Accra", "Abha", "Bern", "Rome", "Oslo"]);
+    }
+
+    #[test]
+    fn first_and_last_in_json_and_csv() {
+        let mut hamburg = TrackFirstLast.init_at(120, 0);
+        TrackFirstLast.observe_at(&mut hamburg, -34, 13);
+        let m = HashMap::from([("Hamburg", hamburg)]);
+
+        let out = output(|w| write_json(w, &rows(&m)));
+        assert_eq!(out, "{\"Hamburg\":{\"min\":-3.4,\"mean\":4.3,\"max\":12.0,\"count\":2,\"first\":12.0,\"last\":-3.4}}\n");
+        let out = output(|w| write_csv(w, &rows(&m), None, None));
+        assert_eq!(out, "station,min,mean,max,count,first,last\nHamburg,-3.4,4.3,12.0,2,12.0,-3.4\n");
+    }
+
+    #[test]
+    fn stations_without_data() {
+        let m = HashMap::from([("Oslo", StationData::new(15))]);
+        let mut rows = rows(&m);
+        rows.insert(0, Row::no_data("Abha"));
+        assert_eq!(output(|w| write_brace(w, &rows, true)), "{Abha=N/A, Oslo=\u{1b}[34m1.5\u{1b}[0m/1.5/\u{1b}[31m1.5\u{1b}[0m}\n");
+        assert_eq!(output(|w| write_plain(w, &rows)), "Abha=N/A\nOslo=1.5/1.5/1.5\n");
+        assert_eq!(output(|w| write_json(w, &rows)), "{\"Abha\":{\"min\":null,\"mean\":null,\"max\":null,\"count\":0},\"Oslo\":{\"min\":1.5,\"mean\":1.5,\"max\":1.5,\"count\":1}}\n");
+        assert_eq!(output(|w| write_csv(w, &rows, None, None)), "station,min,mean,max,count\nAbha,N/A,N/A,N/A,0\nOslo,1.5,1.5,1.5,1\n");
+        let summary = output(|w| write_ndjson(w, &rows, true));
+        assert!(summary.ends_with("{\"summary\":{\"stations\":2,\"count\":1,\"min\":1.5,\"mean\":1.5,\"max\":1.5}}\n"), "{}", summary);
+    }
+
+    #[test]
+    fn total_of_all_the_measurements() {
+        let m = stations();
+        let mut rows = rows(&m);
+        rows.push(Row::no_data("Abha"));
+        let total = Total::of(&rows).unwrap();
+        // (120 - 34 + 89 - 5) / 4 = 42.5 tenths, the mean of the means would be 4.2
+        assert_eq!((total.stations, total.count, total.min(), total.mean(), total.max()), (4, 4, -3.4, 4.3, 12.0));
+        assert_eq!(Total::of(&[Row::no_data("Abha")]), None);
+
+        let rows = &rows[..1];
+        let total = Total::of(rows).unwrap();
+        assert_eq!(output(|w| write_total_line(w, &total)), "Total of 1 stations, 1 measurements: 8.9/8.9/8.9\n");
+        assert_eq!(output(|w| write_json_document(w, rows, None, Some(&total))),
+                   "{\"stations\":{\"Bulawayo\":{\"min\":8.9,\"mean\":8.9,\"max\":8.9,\"count\":1}},\"total\":{\"min\":8.9,\"mean\":8.9,\"max\":8.9,\"count\":1,\"stations\":1}}\n");
+        assert_eq!(output(|w| write_yaml(w, rows, Some(&total))),
+                   "stations:\n  Bulawayo:\n    min: 8.9\n    mean: 8.9\n    max: 8.9\n    count: 1\ntotal:\n  min: 8.9\n  mean: 8.9\n  max: 8.9\n  count: 1\n  stations: 1\n");
+        let h = Histogram::with_bucket_width(1000);
+        assert_eq!(output(|w| write_csv(w, rows, Some(&h), Some(&total))), "station,min,mean,max,count,-99.9,0.1\nBulawayo,8.9,8.9,8.9,1\n,8.9,8.9,8.9,1,,\n");
+    }
+
+    #[test]
+    fn counts_largest_first() {
+        let m = stations();
+        let counts = counts(&rows(&m));
+        assert_eq!(counts, [("Hamburg", 2), ("Bulawayo", 1), ("St. \"John\", NL", 1)]);
+        let counts = &counts[..2];
+        assert_eq!(output(|w| write_counts(w, counts, CountsFormat::Brace)), "{Hamburg=2, Bulawayo=1}\n");
+        assert_eq!(output(|w| write_counts(w, counts, CountsFormat::Csv)), "station,count\nHamburg,2\nBulawayo,1\n");
+        assert_eq!(output(|w| write_counts(w, counts, CountsFormat::Json)), "{\"Hamburg\":2,\"Bulawayo\":1}\n");
+        assert_eq!(output(|w| write_counts(w, counts, CountsFormat::Ndjson)), "{\"station\":\"Hamburg\",\"count\":2}\n{\"station\":\"Bulawayo\",\"count\":1}\n");
+        assert_eq!(output(|w| write_counts(w, counts, CountsFormat::Yaml)), "Hamburg: 2\nBulawayo: 1\n");
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::{Error, Write};
+use std::time::Duration;
+
+use super::{cells, round_scaled, Row, COLUMNS};
+
+/// Description of the run shown in the header of the HTML report.
+pub struct RunMeta<'a> {
+    pub input: &'a str,
+    pub bytes: u64,
+    pub duration: Duration,
+    pub threads: usize,
+    pub implementation: &'a str,
+    pub generated_at: &'a str,
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 0.2em 0.8em; border-bottom: 1px solid #ddd; }
+#stations th { cursor: pointer; user-select: none; }
+#stations td.num { text-align: right; font-variant-numeric: tabular-nums; }
+";
+
+// sorts the table by the clicked column, clicking the same column again reverses the order
+const SCRIPT: &str = "\
+document.querySelectorAll('#stations th').forEach((th, col) => {
+  th.addEventListener('click', () => {
+    const tbody = document.querySelector('#stations tbody');
+    const asc = th.dataset.order !== 'asc';
+    document.querySelectorAll('#stations th').forEach(h => delete h.dataset.order);
+    th.dataset.order = asc ? 'asc' : 'desc';
+    const numeric = col > 0;
+    const key = tr => numeric ? parseFloat(tr.cells[col].textContent) : tr.cells[col].textContent;
+    const rows = Array.from(tbody.rows);
+    rows.sort((a, b) => {
+      const ka = key(a), kb = key(b);
+      const c = ka < kb ? -1 : ka > kb ? 1 : 0;
+      return asc ? c : -c;
+    });
+    rows.forEach(tr => tbody.appendChild(tr));
+  });
+});
+";
+
+/// Writes a self-contained HTML page with the run metadata, summary totals and a sortable table of the stations.
+pub fn write_html<W: Write>(w: &mut W, rows: &[Row], meta: &RunMeta) -> Result<(), Error> {
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html lang=\"en\">")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>Weather stations: {}</title>", escape(meta.input))?;
+    writeln!(w, "<style>\n{}</style>", STYLE)?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(w, "<h1>Weather stations</h1>")?;
+
+    writeln!(w, "<table id=\"run\">")?;
+    let run = [
+        ("Input", meta.input.to_owned()),
+        ("Size", format!("{} bytes", meta.bytes)),
+        ("Duration", format!("{:?}", meta.duration)),
+        ("Threads", meta.threads.to_string()),
+        ("Implementation", meta.implementation.to_owned()),
+        ("Generated", meta.generated_at.to_owned()),
+    ];
+    for (name, value) in run {
+        writeln!(w, "<tr><th>{}</th><td>{}</td></tr>", name, escape(&value))?;
+    }
+    writeln!(w, "</table>")?;
+
+    let count: u64 = rows.iter().map(|r| r.data.count() as u64).sum();
+    let sum: i64 = rows.iter().map(|r| r.data.sum_temp).sum();
+    let min = rows.iter().map(|r| r.min()).reduce(f64::min);
+    let max = rows.iter().map(|r| r.max()).reduce(f64::max);
+    writeln!(w, "<h2>Summary</h2>")?;
+    writeln!(w, "<table id=\"summary\">")?;
+    writeln!(w, "<tr><th>Stations</th><td>{}</td></tr>", rows.len())?;
+    writeln!(w, "<tr><th>Measurements</th><td>{}</td></tr>", count)?;
+    if let (Some(min), Some(max), true) = (min, max, count > 0) {
+        let (p, rounding, scale) = (rows[0].precision, rows[0].rounding, rows[0].scale);
+        writeln!(w, "<tr><th>Min</th><td>{:.p$}</td></tr>", min)?;
+        writeln!(w, "<tr><th>Mean</th><td>{:.p$}</td></tr>", round_scaled(sum, count, scale, p, rounding))?;
+        writeln!(w, "<tr><th>Max</th><td>{:.p$}</td></tr>", max)?;
+    }
+    writeln!(w, "</table>")?;
+
+    writeln!(w, "<h2>Stations</h2>")?;
+    writeln!(w, "<table id=\"stations\">")?;
+    let header: Vec<String> = COLUMNS.iter().map(|c| format!("<th>{}{}</th>", c[..1].to_uppercase(), &c[1..])).collect();
+    writeln!(w, "<thead><tr>{}</tr></thead>", header.concat())?;
+    writeln!(w, "<tbody>")?;
+    for r in rows {
+        let [station, rest @ ..] = cells(r);
+        let numbers: Vec<String> = rest.iter().map(|c| format!("<td class=\"num\">{}</td>", c)).collect();
+        writeln!(w, "<tr><td>{}</td>{}</tr>", escape(&station), numbers.concat())?;
+    }
+    writeln!(w, "</tbody>")?;
+    writeln!(w, "</table>")?;
+    writeln!(w, "<script>\n{}</script>", SCRIPT)?;
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::format::rows;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    #[test]
+    fn golden_report() {
+        let mut hamburg = StationData::new(120);
+        MinMeanMax.observe(&mut hamburg, -34);
+        let m = HashMap::from([("Hamburg", hamburg), ("<b>Tom & Jerry's</b>", StationData::new(-5))]);
+        let meta = RunMeta {
+            input: "measurements.txt",
+            bytes: 52,
+            duration: Duration::from_millis(1500),
+            threads: 8,
+            implementation: "parallel mmap read",
+            generated_at: "2024-01-31T12:00:00Z",
+        };
+        let mut out = Vec::new();
+        write_html(&mut out, &rows(&m), &meta).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), include_str!("../../tests/golden/report.html"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Output of the records with several metrics per station, see [`crate::metrics`].
+//!
+//! Every metric of a station is a [`Row`] of its own, the names of the metrics label them in the order of the columns.
+
+use std::collections::HashMap;
+use std::io::{Error, Write};
+
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+use super::{csv_escape, Row, StationRecord};
+use crate::metrics::StationMetrics;
+
+/// A single station of the output with a row per metric.
+pub struct MetricsRow<'a> {
+    pub station: &'a str,
+    pub metrics: Vec<Row<'a>>,
+}
+
+/// The rows of the stations sorted by name like [`rows`](super::rows).
+pub fn metrics_rows<K: AsRef<str>>(m: &HashMap<K, StationMetrics>) -> Vec<MetricsRow<'_>> {
+    let mut rows: Vec<MetricsRow> = m.iter()
+        .map(|(station, metrics)| {
+            let station = station.as_ref();
+            MetricsRow { station, metrics: metrics.iter().map(|data| Row { data, ..Row::no_data(station) }).collect() }
+        })
+        .collect();
+    rows.sort_unstable_by(|r1, r2| r1.station.cmp(r2.station));
+    rows
+}
+
+// `name=min/mean/max` of every metric, separated by `, `
+fn labelled(names: &[String], r: &MetricsRow) -> String {
+    let metrics: Vec<String> = names.iter().zip(&r.metrics)
+        .map(|(name, m)| format!("{}={:.p$}/{:.p$}/{:.p$}", name, m.min(), m.mean(), m.max(), p = m.precision))
+        .collect();
+    metrics.join(", ")
+}
+
+/// Writes `{station=[metric=min/mean/max, ...], ...}` like the brace format of the challenge.
+pub fn write_metrics_brace<W: Write>(w: &mut W, names: &[String], rows: &[MetricsRow]) -> Result<(), Error> {
+    let list: Vec<String> = rows.iter().map(|r| format!("{}=[{}]", r.station, labelled(names, r))).collect();
+    writeln!(w, "{{{}}}", list.join(", "))
+}
+
+/// Writes one `station=[metric=min/mean/max, ...]` line per station.
+pub fn write_metrics_plain<W: Write>(w: &mut W, names: &[String], rows: &[MetricsRow]) -> Result<(), Error> {
+    for r in rows {
+        writeln!(w, "{}=[{}]", r.station, labelled(names, r))?;
+    }
+    Ok(())
+}
+
+// serializes the rows as a map of the station names to the maps of the metric names to their records
+struct MetricsRecords<'a>(&'a [String], &'a [MetricsRow<'a>]);
+
+struct Metrics<'a>(&'a [String], &'a MetricsRow<'a>);
+
+impl Serialize for MetricsRecords<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.1.len()))?;
+        for r in self.1 {
+            map.serialize_entry(r.station, &Metrics(self.0, r))?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Metrics<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, m) in self.0.iter().zip(&self.1.metrics) {
+            map.serialize_entry(name, &StationRecord::from(m))?;
+        }
+        map.end()
+    }
+}
+
+/// Writes a JSON object of the stations with an object of the min, mean, max and count of every metric.
+pub fn write_metrics_json<W: Write>(w: &mut W, names: &[String], rows: &[MetricsRow]) -> Result<(), Error> {
+    serde_json::to_writer(&mut *w, &MetricsRecords(names, rows))?;
+    writeln!(w)
+}
+
+/// Writes a CSV table with a header and the `_min`, `_mean`, `_max` and `_count` columns of every metric.
+pub fn write_metrics_csv<W: Write>(w: &mut W, names: &[String], rows: &[MetricsRow]) -> Result<(), Error> {
+    write!(w, "station")?;
+    for name in names {
+        let name = csv_escape(name);
+        write!(w, ",{name}_min,{name}_mean,{name}_max,{name}_count")?;
+    }
+    writeln!(w)?;
+    for r in rows {
+        write!(w, "{}", csv_escape(r.station))?;
+        for m in &r.metrics {
+            write!(w, ",{:.p$},{:.p$},{:.p$},{}", m.min(), m.mean(), m.max(), m.data.count(), p = m.precision)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::read_metrics_slice;
+    use crate::parse::ParseOptions;
+
+    type Writer = fn(&mut Vec<u8>, &[String], &[MetricsRow]) -> Result<(), Error>;
+
+    #[test]
+    fn metrics_in_every_format() {
+        let m = read_metrics_slice(b"Oslo;-3.4;80\nRome;15.0;40.5\nOslo;12.5;60\n", 2, ParseOptions::default());
+        let names = ["temperature".to_owned(), "humidity".to_owned()];
+        let rows = metrics_rows(&m);
+        let output = |f: Writer| {
+            let mut out = Vec::new();
+            f(&mut out, &names, &rows).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output(write_metrics_brace), "{Oslo=[temperature=-3.4/4.6/12.5, humidity=60.0/70.0/80.0], Rome=[temperature=15.0/15.0/15.0, humidity=40.5/40.5/40.5]}\n");
+        assert_eq!(output(write_metrics_plain), "Oslo=[temperature=-3.4/4.6/12.5, humidity=60.0/70.0/80.0]\nRome=[temperature=15.0/15.0/15.0, humidity=40.5/40.5/40.5]\n");
+        assert_eq!(output(write_metrics_json), concat!(
+            "{\"Oslo\":{\"temperature\":{\"min\":-3.4,\"mean\":4.6,\"max\":12.5,\"count\":2},\"humidity\":{\"min\":60.0,\"mean\":70.0,\"max\":80.0,\"count\":2}},",
+            "\"Rome\":{\"temperature\":{\"min\":15.0,\"mean\":15.0,\"max\":15.0,\"count\":1},\"humidity\":{\"min\":40.5,\"mean\":40.5,\"max\":40.5,\"count\":1}}}\n"));
+        assert_eq!(output(write_metrics_csv), concat!(
+            "station,temperature_min,temperature_mean,temperature_max,temperature_count,humidity_min,humidity_mean,humidity_max,humidity_count\n",
+            "Oslo,-3.4,4.6,12.5,2,60.0,70.0,80.0,2\n",
+            "Rome,15.0,15.0,15.0,1,40.5,40.5,40.5,1\n"));
+    }
+}
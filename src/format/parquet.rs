@@ -0,0 +1,81 @@
+use std::io::{Error, Write};
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array};
+use parquet::arrow::ArrowWriter;
+use parquet::file::metadata::KeyValue;
+
+use super::{Row, Total, TotalRecord};
+
+/// The `station` (Utf8), `min`, `mean`, `max` (Float64) and `count` (UInt32) columns of the rows in an Arrow batch,
+/// the columns of [`write_parquet`] and of the other conversions to Arrow-based formats, so that their values agree.
+pub fn record_batch(rows: &[Row]) -> Result<RecordBatch, Error> {
+    RecordBatch::try_from_iter([
+        ("station", Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.station))) as ArrayRef),
+        ("min", Arc::new(Float64Array::from_iter_values(rows.iter().map(Row::min)))),
+        ("mean", Arc::new(Float64Array::from_iter_values(rows.iter().map(Row::mean)))),
+        ("max", Arc::new(Float64Array::from_iter_values(rows.iter().map(Row::max)))),
+        ("count", Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.data.count())))),
+    ]).map_err(Error::other)
+}
+
+/// Writes an Apache Parquet file with the columns of [`record_batch`] in a single row group.
+///
+/// The total is stored as JSON under the `total` key of the metadata of the file.
+pub fn write_parquet<W: Write + Send>(w: &mut W, rows: &[Row], total: Option<&Total>) -> Result<(), Error> {
+    let batch = record_batch(rows)?;
+    let mut writer = ArrowWriter::try_new(w, batch.schema(), None).map_err(Error::other)?;
+    writer.write(&batch).map_err(Error::other)?;
+    if let Some(t) = total {
+        writer.append_key_value_metadata(KeyValue::new("total".to_owned(), serde_json::to_string(&TotalRecord::from(t))?));
+    }
+    writer.close().map_err(Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::File;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, UInt32Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::format::rows;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    #[test]
+    fn columns_round_trip() {
+        let mut hamburg = StationData::new(120);
+        MinMeanMax.observe(&mut hamburg, -34);
+        let m = HashMap::from([("Hamburg", hamburg), ("Bulawayo", StationData::new(89))]);
+        let path = std::env::temp_dir().join(format!("rust-1brc-{}.parquet", std::process::id()));
+        write_parquet(&mut File::create(&path).unwrap(), &rows(&m), None).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        let stations: Vec<&str> = batch.column(0).as_string::<i32>().iter().flatten().collect();
+        assert_eq!(stations, ["Bulawayo", "Hamburg"]);
+        assert_eq!(batch.column(1).as_primitive::<Float64Type>().values(), &[8.9, -3.4]);
+        assert_eq!(batch.column(2).as_primitive::<Float64Type>().values(), &[8.9, 4.3]);
+        assert_eq!(batch.column(3).as_primitive::<Float64Type>().values(), &[8.9, 12.0]);
+        assert_eq!(batch.column(4).as_primitive::<UInt32Type>().values(), &[1, 2]);
+    }
+
+    #[test]
+    fn batch_has_the_schema_of_the_file() {
+        let m = HashMap::from([("Hamburg", StationData::new(120)), ("Bulawayo", StationData::new(89))]);
+        let batch = record_batch(&rows(&m)).unwrap();
+        // the types by their names, arrow-schema is not a dependency of its own
+        let fields: Vec<String> = batch.schema_ref().fields().iter().map(|f| format!("{} {:?}", f.name(), f.data_type())).collect();
+        assert_eq!(fields, ["station Utf8", "min Float64", "mean Float64", "max Float64", "count UInt32"]);
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column(0).as_string::<i32>().value(1), "Hamburg");
+        assert_eq!(batch.column(3).as_primitive::<Float64Type>().value(1), 12.0);
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::{Error, Write};
+use std::time::Duration;
+
+use super::{Row, Total};
+
+// name, help and the value of a row
+type StationMetric = (&'static str, &'static str, fn(&Row) -> f64);
+
+const STATION_METRICS: [StationMetric; 4] = [
+    ("station_temp_min", "Minimum temperature of the station in degrees Celsius.", |r| r.min()),
+    ("station_temp_mean", "Mean temperature of the station in degrees Celsius.", |r| r.mean()),
+    ("station_temp_max", "Maximum temperature of the station in degrees Celsius.", |r| r.max()),
+    ("station_temp_count", "Number of measurements of the station.", |r| r.data.count() as f64),
+];
+
+/// Writes the results in the Prometheus text exposition format.
+///
+/// Every statistic is a gauge labelled by the station, followed by the duration of the run and the number of
+/// processed rows. The total is written as unlabelled `all_stations_temp_*` gauges.
+pub fn write_prometheus<W: Write>(w: &mut W, rows: &[Row], duration: Duration, total: Option<&Total>) -> Result<(), Error> {
+    for (name, help, value) in STATION_METRICS {
+        write_header(w, name, help)?;
+        for r in rows {
+            writeln!(w, "{}{{station=\"{}\"}} {}", name, escape_label(r.station), value(r))?;
+        }
+    }
+    if let Some(t) = total {
+        let metrics = [("min", "Minimum", t.min()), ("mean", "Mean", t.mean()), ("max", "Maximum", t.max())];
+        for (name, help, value) in metrics {
+            let name = format!("all_stations_temp_{}", name);
+            write_header(w, &name, &format!("{} temperature of all the stations in degrees Celsius.", help))?;
+            writeln!(w, "{} {}", name, value)?;
+        }
+    }
+    write_header(w, "aggregation_duration_seconds", "Duration of the aggregation in seconds.")?;
+    writeln!(w, "aggregation_duration_seconds {}", duration.as_secs_f64())?;
+    let processed: u64 = rows.iter().map(|r| r.data.count() as u64).sum();
+    write_header(w, "aggregation_rows_processed", "Number of processed rows.")?;
+    writeln!(w, "aggregation_rows_processed {}", processed)
+}
+
+fn write_header<W: Write>(w: &mut W, name: &str, help: &str) -> Result<(), Error> {
+    writeln!(w, "# HELP {} {}", name, help)?;
+    writeln!(w, "# TYPE {} gauge", name)
+}
+
+// label values escape backslashes, double quotes and line feeds
+fn escape_label(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::format::rows;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("Hamburg"), "Hamburg");
+        assert_eq!(escape_label(r#"St. "John""#), r#"St. \"John\""#);
+        assert_eq!(escape_label(r"C:\temp"), r"C:\\temp");
+        assert_eq!(escape_label("a\nb"), r"a\nb");
+    }
+
+    #[test]
+    fn metrics() {
+        let mut hamburg = StationData::new(120);
+        MinMeanMax.observe(&mut hamburg, -34);
+        let m = HashMap::from([("Hamburg", hamburg), ("Say \"hi\"", StationData::new(-5))]);
+        let mut out = Vec::new();
+        write_prometheus(&mut out, &rows(&m), Duration::from_millis(1500), None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "# HELP station_temp_min Minimum temperature of the station in degrees Celsius.\n",
+            "# TYPE station_temp_min gauge\n",
+            "station_temp_min{station=\"Hamburg\"} -3.4\n",
+            "station_temp_min{station=\"Say \\\"hi\\\"\"} -0.5\n",
+            "# HELP station_temp_mean Mean temperature of the station in degrees Celsius.\n",
+            "# TYPE station_temp_mean gauge\n",
+            "station_temp_mean{station=\"Hamburg\"} 4.3\n",
+            "station_temp_mean{station=\"Say \\\"hi\\\"\"} -0.5\n",
+            "# HELP station_temp_max Maximum temperature of the station in degrees Celsius.\n",
+            "# TYPE station_temp_max gauge\n",
+            "station_temp_max{station=\"Hamburg\"} 12\n",
+            "station_temp_max{station=\"Say \\\"hi\\\"\"} -0.5\n",
+            "# HELP station_temp_count Number of measurements of the station.\n",
+            "# TYPE station_temp_count gauge\n",
+            "station_temp_count{station=\"Hamburg\"} 2\n",
+            "station_temp_count{station=\"Say \\\"hi\\\"\"} 1\n",
+            "# HELP aggregation_duration_seconds Duration of the aggregation in seconds.\n",
+            "# TYPE aggregation_duration_seconds gauge\n",
+            "aggregation_duration_seconds 1.5\n",
+            "# HELP aggregation_rows_processed Number of processed rows.\n",
+            "# TYPE aggregation_rows_processed gauge\n",
+            "aggregation_rows_processed 3\n"));
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::Error;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use super::{Row, RunMeta, Total};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    input TEXT NOT NULL,
+    implementation TEXT NOT NULL,
+    duration REAL NOT NULL,
+    rows INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS station_stats (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    station TEXT NOT NULL,
+    min REAL NOT NULL,
+    mean REAL NOT NULL,
+    max REAL NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (run_id, station)
+);
+CREATE TABLE IF NOT EXISTS run_totals (
+    run_id INTEGER PRIMARY KEY REFERENCES runs (id),
+    min REAL NOT NULL,
+    mean REAL NOT NULL,
+    max REAL NOT NULL,
+    count INTEGER NOT NULL
+);
+";
+
+/// Appends the run to the `runs` table of the SQLite database and its stations to the `station_stats` table,
+/// in a single transaction, returns the id of the run.
+///
+/// The database and the tables are created if they do not exist. The `rows` of the run are the measurements,
+/// the `duration` is in seconds. The total is added to the `run_totals` table.
+pub fn write_sqlite(path: &Path, rows: &[Row], meta: &RunMeta, total: Option<&Total>) -> Result<i64, Error> {
+    let mut db = Connection::open(path).map_err(Error::other)?;
+    let tx = db.transaction().map_err(Error::other)?;
+    tx.execute_batch(SCHEMA).map_err(Error::other)?;
+    let measurements: u64 = rows.iter().map(|r| r.data.count() as u64).sum();
+    tx.execute("INSERT INTO runs (timestamp, input, implementation, duration, rows) VALUES (?1, ?2, ?3, ?4, ?5)",
+               params![meta.generated_at, meta.input, meta.implementation, meta.duration.as_secs_f64(), measurements as i64])
+        .map_err(Error::other)?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut insert = tx.prepare("INSERT INTO station_stats (run_id, station, min, mean, max, count) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+            .map_err(Error::other)?;
+        for r in rows {
+            insert.execute(params![run_id, r.station, r.min(), r.mean(), r.max(), r.data.count()]).map_err(Error::other)?;
+        }
+    }
+    if let Some(t) = total {
+        tx.execute("INSERT INTO run_totals (run_id, min, mean, max, count) VALUES (?1, ?2, ?3, ?4, ?5)",
+                   params![run_id, t.min(), t.mean(), t.max(), t.count as i64])
+            .map_err(Error::other)?;
+    }
+    tx.commit().map_err(Error::other)?;
+    Ok(run_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::format::rows;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    #[test]
+    fn runs_are_appended() {
+        let mut hamburg = StationData::new(120);
+        MinMeanMax.observe(&mut hamburg, -34);
+        let m = HashMap::from([("Hamburg", hamburg), ("Bulawayo", StationData::new(89))]);
+        let path = std::env::temp_dir().join(format!("rust-1brc-{}.db", std::process::id()));
+        let meta = RunMeta {
+            input: "measurements.txt",
+            bytes: 100,
+            duration: Duration::from_millis(1500),
+            threads: 4,
+            implementation: "parallel mmap read",
+            generated_at: "2024-01-01T00:00:00Z",
+        };
+        assert_eq!(write_sqlite(&path, &rows(&m), &meta, None).unwrap(), 1);
+        assert_eq!(write_sqlite(&path, &rows(&m)[..1], &meta, None).unwrap(), 2);
+
+        let db = Connection::open(&path).unwrap();
+        let runs: Vec<(i64, String, f64, i64)> = db.prepare("SELECT id, input, duration, rows FROM runs ORDER BY id").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))).unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let stats: Vec<(i64, String, f64, f64, f64, u32)> = db.prepare("SELECT run_id, station, min, mean, max, count FROM station_stats ORDER BY run_id, station").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?))).unwrap()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(runs, [(1, "measurements.txt".to_owned(), 1.5, 3), (2, "measurements.txt".to_owned(), 1.5, 1)]);
+        assert_eq!(stats, [
+            (1, "Bulawayo".to_owned(), 8.9, 8.9, 8.9, 1),
+            (1, "Hamburg".to_owned(), -3.4, 4.3, 12.0, 2),
+            (2, "Bulawayo".to_owned(), 8.9, 8.9, 8.9, 1),
+        ]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::{Error, Write};
+
+use super::{Row, NO_DATA};
+
+// the placeholders and the values they are replaced with
+type Field = (&'static str, fn(&Row) -> String);
+
+const FIELDS: [Field; 5] = [
+    ("station", |r| r.station.to_owned()),
+    ("min", |r| number(r, r.min())),
+    ("mean", |r| number(r, r.mean())),
+    ("max", |r| number(r, r.max())),
+    ("count", |r| r.data.count().to_string()),
+];
+
+// the statistics are written like in the plain format
+fn number(r: &Row, v: f64) -> String {
+    if r.has_data() { format!("{:.p$}", v, p = r.precision) } else { NO_DATA.to_owned() }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(usize),
+}
+
+/// A line written for every station, with `{station}`, `{min}`, `{mean}`, `{max}` and `{count}` placeholders.
+///
+/// `\t`, `\n` and `\\` are escapes of a tab, a line feed and a backslash, `{{` and `}}` of the braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses the template, the unknown placeholders and escapes are errors.
+    pub fn parse(s: &str) -> Result<Template, String> {
+        let mut parts: Vec<Part> = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => text.push(match chars.next() {
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some('\\') => '\\',
+                    Some(c) => return Err(format!("Unknown escape \\{} in the template, expected \\t, \\n or \\\\", c)),
+                    None => return Err("The template ends with a backslash".to_owned()),
+                }),
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(format!("Unclosed placeholder {{{} in the template", rest));
+                    };
+                    let name = &rest[..end];
+                    let Some(field) = FIELDS.iter().position(|(n, _)| *n == name) else {
+                        let names: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                        return Err(format!("Unknown placeholder {{{}}} in the template, expected one of {}", name, names.join(", ")));
+                    };
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err("Unmatched } in the template, write }} for a brace".to_owned()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+}
+
+/// Writes the template filled with the statistics of every station, each followed by a line feed.
+pub fn write_template<W: Write>(w: &mut W, rows: &[Row], template: &Template) -> Result<(), Error> {
+    for r in rows {
+        for part in &template.parts {
+            match part {
+                Part::Text(text) => w.write_all(text.as_bytes())?,
+                Part::Field(field) => w.write_all((FIELDS[*field].1)(r).as_bytes())?,
+            }
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::format::rows;
+    use crate::{Aggregator, MinMeanMax, StationData};
+
+    fn fill(template: &str, rows: &[Row]) -> String {
+        let mut out = Vec::new();
+        write_template(&mut out, rows, &Template::parse(template).unwrap()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn placeholders_and_escapes() {
+        let mut oslo = StationData::new(-34);
+        MinMeanMax.observe(&mut oslo, 125);
+        let m = HashMap::from([("Oslo", oslo), ("Rome", StationData::new(150))]);
+        let mut rows = rows(&m);
+        assert_eq!(fill("{station}\\t{min}\\t{mean}\\t{max}\\t{count}", &rows), "Oslo\t-3.4\t4.6\t12.5\t2\nRome\t15.0\t15.0\t15.0\t1\n");
+        assert_eq!(fill("{{{station}}} \\\\ {count}\\n--", &rows[..1]), "{Oslo} \\ 2\n--\n");
+        rows[0].precision = 0;
+        rows[1] = Row::no_data("Abha");
+        assert_eq!(fill("{station}: {mean}", &rows), "Oslo: 5\nAbha: N/A\n");
+        assert_eq!(fill("", &rows), "\n\n");
+    }
+
+    #[test]
+    fn invalid_templates_are_errors() {
+        for (template, error) in [
+            ("{station} {p99}", "Unknown placeholder {p99}"),
+            ("{station", "Unclosed placeholder {station"),
+            ("{min} }", "Unmatched }"),
+            ("{min}\\x", "Unknown escape \\x"),
+            ("{min}\\", "The template ends with a backslash"),
+        ] {
+            let e = Template::parse(template).unwrap_err();
+            assert!(e.starts_with(error), "{}: {}", template, e);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Generator of measurements files with the expected results computed independently of the parsers.
+
+use std::collections::BTreeMap;
+use std::io::{Error, Write};
+
+use memchr::memrchr;
+
+// a subset of the weather stations of the challenge with their mean temperatures in tenths of a degree
+const STATIONS: [(&str, i32); 40] = [
+    ("Abha", 180), ("Abidjan", 260), ("Adelaide", 173), ("Alexandria", 200), ("Anchorage", 28),
+    ("Athens", 192), ("Bangkok", 286), ("Beijing", 129), ("Berlin", 103), ("Bogotá", 156),
+    ("Bulawayo", 189), ("Cairo", 214), ("Conakry", 264), ("Cracow", 93), ("Dakar", 240),
+    ("Dublin", 98), ("Hamburg", 97), ("Helsinki", 59), ("Istanbul", 139), ("Jakarta", 267),
+    ("Kyiv", 84), ("Lagos", 268), ("Lhasa", 76), ("Lima", 185), ("Mexico City", 175),
+    ("Moscow", 58), ("Nairobi", 178), ("Oslo", 57), ("Palembang", 273), ("Paris", 123),
+    ("Reykjavík", 43), ("Roseau", 262), ("São Paulo", 199), ("St. John's", 50), ("Tokyo", 154),
+    ("Toronto", 94), ("Vladivostok", 49), ("Yakutsk", -88), ("Zürich", 93), ("Ürümqi", 74),
+];
+
+/// Number of distinct stations the generator picks from before it starts numbering the names.
+pub const BUILTIN_STATIONS: usize = STATIONS.len();
+
+/// Exact aggregates of the generated measurements, in integer tenths of a degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Expected {
+    pub min: i32,
+    pub max: i32,
+    pub sum: i64,
+    pub count: u64,
+}
+
+impl Expected {
+    /// The mean in tenths, rounded half up like `Math.round` in the reference implementation.
+    pub fn mean(&self) -> i64 {
+        let count = self.count as i64;
+        (2 * self.sum + count).div_euclid(2 * count)
+    }
+}
+
+// SplitMix64, so that the files are reproducible for a seed without depending on an RNG crate
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// How often each station is picked.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Distribution {
+    /// Every station equally often
+    #[default]
+    Uniform,
+    /// The station of rank `k` (from 1) with a probability proportional to `1 / k^s`
+    Zipf(f64),
+}
+
+impl Distribution {
+    /// Parses `uniform` or `zipf:<s>` with a finite exponent `s` of at least 0.
+    pub fn parse(s: &str) -> Result<Distribution, String> {
+        match s.split_once(':') {
+            None if s == "uniform" => Ok(Distribution::Uniform),
+            Some(("zipf", exponent)) => match exponent.parse::<f64>() {
+                Ok(e) if e.is_finite() && e >= 0.0 => Ok(Distribution::Zipf(e)),
+                _ => Err(format!("Invalid Zipf exponent {}, expected a number of at least 0", exponent)),
+            },
+            _ => Err(format!("Invalid distribution {}, expected uniform or zipf:<s>", s)),
+        }
+    }
+
+    // the cumulative probabilities of the stations, `None` for the uniform distribution
+    fn cumulative(self, stations: usize) -> Option<Vec<f64>> {
+        let Distribution::Zipf(exponent) = self else { return None };
+        let mut total = 0.0;
+        let mut cumulative: Vec<f64> = (1..=stations)
+            .map(|k| {
+                total += (k as f64).powf(-exponent);
+                total
+            })
+            .collect();
+        cumulative.iter_mut().for_each(|c| *c /= total);
+        Some(cumulative)
+    }
+}
+
+/// Options of the generated measurements beyond their number, the stations and the seed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GenerateOptions {
+    pub distribution: Distribution,
+    /// Standard deviation of the temperatures of a station around its mean, in degrees
+    pub stddev: f64,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> GenerateOptions {
+        GenerateOptions { distribution: Distribution::Uniform, stddev: DEFAULT_STDDEV }
+    }
+}
+
+/// Standard deviation of the temperatures of a station, in degrees.
+pub const DEFAULT_STDDEV: f64 = 10.0;
+
+/// Writes `rows` measurements of `stations` stations generated from `seed`,
+/// returns the exact aggregates of the written values.
+///
+/// The stations are picked uniformly and the temperatures are normally distributed around the mean of the station
+/// with a deviation of 10 degrees, see [`generate_with`] for other distributions.
+pub fn generate<W: Write>(w: &mut W, rows: u64, stations: usize, seed: u64) -> Result<BTreeMap<String, Expected>, Error> {
+    generate_with(w, rows, stations, seed, GenerateOptions::default())
+}
+
+/// Writes the measurements of [`generate`] with the stations picked from the distribution of the options
+/// and the temperatures normally distributed with their deviation, clamped to -99.9..99.9.
+///
+/// The default options produce the same measurements as [`generate`] for a seed.
+pub fn generate_with<W: Write>(w: &mut W, rows: u64, stations: usize, seed: u64, options: GenerateOptions) -> Result<BTreeMap<String, Expected>, Error> {
+    assert!(stations > 0, "At least one station is needed");
+    assert!(options.stddev.is_finite() && options.stddev >= 0.0, "The standard deviation must be finite and at least 0");
+    // the built-in names are reused with a number if more stations are requested
+    let names: Vec<(String, i32)> = (0..stations)
+        .map(|i| {
+            let (name, mean) = STATIONS[i % STATIONS.len()];
+            let round = i / STATIONS.len();
+            (if round == 0 { name.to_owned() } else { format!("{} {}", name, round) }, mean)
+        })
+        .collect();
+    let mut expected: Vec<Option<Expected>> = vec![None; stations];
+    let cumulative = options.distribution.cumulative(stations);
+    let mut rng = SplitMix64(seed);
+    for _ in 0..rows {
+        let i = match &cumulative {
+            None => (rng.next() % stations as u64) as usize,
+            // the rounding may leave the last cumulative probability a little below 1
+            Some(cumulative) => {
+                let u = rng.next_f64();
+                cumulative.partition_point(|&c| c <= u).min(stations - 1)
+            }
+        };
+        let (name, mean) = &names[i];
+        // Irwin-Hall approximation of a standard normal distribution
+        let z: f64 = (0..12).map(|_| rng.next_f64()).sum::<f64>() - 6.0;
+        let temp: i32 = (*mean as f64 + (z * options.stddev * 10.0).round()).clamp(-999.0, 999.0) as i32;
+        writeln!(w, "{};{}", name, format_tenths(temp as i64))?;
+        let e = expected[i].get_or_insert(Expected { min: temp, max: temp, sum: 0, count: 0 });
+        e.min = e.min.min(temp);
+        e.max = e.max.max(temp);
+        e.sum += temp as i64;
+        e.count += 1;
+    }
+    Ok(names.into_iter()
+        .zip(expected)
+        .filter_map(|((name, _), e)| Some((name, e?)))
+        .collect())
+}
+
+/// Writes the measurements of [`generate`] sorted by station, the measurements of a station in the order they are generated.
+///
+/// Like the files of systems that emit the measurements of a station together, the whole file is generated in memory.
+pub fn generate_sorted<W: Write>(w: &mut W, rows: u64, stations: usize, seed: u64) -> Result<BTreeMap<String, Expected>, Error> {
+    generate_sorted_with(w, rows, stations, seed, GenerateOptions::default())
+}
+
+/// Writes the measurements of [`generate_with`] sorted by station like [`generate_sorted`].
+pub fn generate_sorted_with<W: Write>(w: &mut W, rows: u64, stations: usize, seed: u64, options: GenerateOptions) -> Result<BTreeMap<String, Expected>, Error> {
+    let mut data = Vec::new();
+    let expected = generate_with(&mut data, rows, stations, seed, options)?;
+    let mut lines: Vec<&[u8]> = data.split_inclusive(|&b| b == b'\n').collect();
+    // stable, so that the measurements of a station keep their order
+    lines.sort_by_key(|l| &l[..memrchr(b';', l).unwrap_or(l.len())]);
+    for l in lines {
+        w.write_all(l)?;
+    }
+    Ok(expected)
+}
+
+/// Writes the expected results in the `{station=min/mean/max, ...}` format of the challenge.
+pub fn write_expected<W: Write>(w: &mut W, expected: &BTreeMap<String, Expected>) -> Result<(), Error> {
+    let list: Vec<String> = expected.iter()
+        .map(|(station, e)| format!("{}={}/{}/{}", station, format_tenths(e.min as i64), format_tenths(e.mean()), format_tenths(e.max as i64)))
+        .collect();
+    writeln!(w, "{{{}}}", list.join(", "))
+}
+
+// formats tenths of a degree with one decimal without going through floating point
+fn format_tenths(t: i64) -> String {
+    format!("{}{}.{}", if t < 0 { "-" } else { "" }, t.abs() / 10, t.abs() % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::format::{rows, write_brace};
+    use crate::parse::ParseOptions;
+    use crate::read::read_stations_data_slice;
+    use crate::MinMeanMax;
+
+    #[test]
+    fn mean_rounds_halves_up() {
+        let e = |sum: i64, count: u64| Expected { min: 0, max: 0, sum, count }.mean();
+        assert_eq!((e(5, 2), e(-5, 2), e(-7, 2), e(-1, 3), e(10, 3)), (3, -2, -3, 0, 3));
+        assert_eq!((format_tenths(-5), format_tenths(0), format_tenths(1234)), ("-0.5".to_owned(), "0.0".to_owned(), "123.4".to_owned()));
+    }
+
+    #[test]
+    fn output_is_deterministic_per_seed() {
+        let (mut a, mut b, mut c) = (Vec::new(), Vec::new(), Vec::new());
+        assert_eq!(generate(&mut a, 1000, 100, 7).unwrap(), generate(&mut b, 1000, 100, 7).unwrap());
+        generate(&mut c, 1000, 100, 8).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        let mut sorted = Vec::new();
+        assert_eq!(generate_sorted(&mut sorted, 1000, 100, 7).unwrap(), generate(&mut Vec::new(), 1000, 100, 7).unwrap());
+        let stations: Vec<&[u8]> = sorted.split(|&b| b == b'\n').filter(|l| !l.is_empty()).filter_map(|l| l.split(|&b| b == b';').next()).collect();
+        assert!(stations.is_sorted());
+        assert_eq!(sorted.len(), a.len());
+    }
+
+    #[test]
+    fn aggregation_matches_expected_results() {
+        // few measurements per station, so that the means often fall on the halves
+        for seed in 0..20 {
+            let mut data = Vec::new();
+            let expected = generate(&mut data, 200, 60, seed).unwrap();
+            let mut golden = Vec::new();
+            write_expected(&mut golden, &expected).unwrap();
+            let m = read_stations_data_slice(&data, &MinMeanMax, ParseOptions::default());
+            let mut out = Vec::new();
+            write_brace(&mut out, &rows(&m), false).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(golden).unwrap(), "seed {}", seed);
+        }
+    }
+
+    // chi-squared statistic of the station counts against the probabilities of the distribution
+    fn chi_squared(expected: &BTreeMap<String, Expected>, stations: usize, rows: u64, distribution: Distribution) -> f64 {
+        let probabilities: Vec<f64> = match distribution.cumulative(stations) {
+            None => vec![1.0 / stations as f64; stations],
+            Some(c) => (0..stations).map(|i| c[i] - if i == 0 { 0.0 } else { c[i - 1] }).collect(),
+        };
+        // the stations in the order of their ranks
+        let names: Vec<&str> = STATIONS[..stations].iter().map(|(name, _)| *name).collect();
+        names.iter().zip(probabilities)
+            .map(|(name, p)| {
+                let observed = expected.get(*name).map_or(0, |e| e.count) as f64;
+                let e = p * rows as f64;
+                (observed - e).powi(2) / e
+            })
+            .sum()
+    }
+
+    #[test]
+    fn distributions_are_deterministic_per_seed() {
+        assert_eq!(Distribution::parse("zipf:1.2"), Ok(Distribution::Zipf(1.2)));
+        assert_eq!(Distribution::parse("uniform"), Ok(Distribution::Uniform));
+        assert!(Distribution::parse("zipf:-1").is_err() && Distribution::parse("zipf").is_err() && Distribution::parse("normal").is_err());
+
+        for distribution in [Distribution::Uniform, Distribution::Zipf(0.0), Distribution::Zipf(1.5)] {
+            let options = GenerateOptions { distribution, stddev: 3.5 };
+            let (mut a, mut b) = (Vec::new(), Vec::new());
+            assert_eq!(generate_with(&mut a, 1000, 100, 7, options).unwrap(), generate_with(&mut b, 1000, 100, 7, options).unwrap());
+            assert_eq!(a, b, "{:?}", distribution);
+        }
+        // the defaults are the files of `generate`
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        generate(&mut a, 1000, 100, 7).unwrap();
+        generate_with(&mut b, 1000, 100, 7, GenerateOptions::default()).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn station_frequencies_follow_the_distribution() {
+        const ROWS: u64 = 1_000_000;
+        // the 99.9% quantile of the chi-squared distribution with 39 degrees of freedom
+        const CRITICAL: f64 = 72.05;
+        let stations = STATIONS.len();
+        for (distribution, other) in [(Distribution::Uniform, Distribution::Zipf(1.1)), (Distribution::Zipf(1.1), Distribution::Zipf(0.9))] {
+            let options = GenerateOptions { distribution, ..GenerateOptions::default() };
+            let expected = generate_with(&mut io::sink(), ROWS, stations, 11, options).unwrap();
+            let fit = chi_squared(&expected, stations, ROWS, distribution);
+            assert!(fit < CRITICAL, "{:?}: {}", distribution, fit);
+            assert!(chi_squared(&expected, stations, ROWS, other) > 100.0 * CRITICAL, "{:?} vs {:?}", distribution, other);
+        }
+    }
+
+    #[test]
+    fn temperatures_spread_by_the_deviation() {
+        for stddev in [0.0, 2.5, 20.0] {
+            let mut data = Vec::new();
+            let options = GenerateOptions { stddev, ..GenerateOptions::default() };
+            let expected = generate_with(&mut data, 100_000, 1, 5, options).unwrap();
+            let temps: Vec<f64> = data.split(|&b| b == b'\n')
+                .filter_map(|l| std::str::from_utf8(l).ok()?.split_once(';')?.1.parse().ok())
+                .collect();
+            let mean = temps.iter().sum::<f64>() / temps.len() as f64;
+            let deviation = (temps.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / temps.len() as f64).sqrt();
+            assert!((deviation - stddev).abs() <= 0.02 * stddev + 0.03, "{}: {}", stddev, deviation);
+            // one decimal within the valid range
+            assert!(temps.iter().all(|t| (-99.9..=99.9).contains(t)));
+            assert!(data.split(|&b| b == b'\n').filter(|l| !l.is_empty()).all(|l| l[l.len() - 2] == b'.'));
+            assert!((-999..=999).contains(&expected["Abha"].min) && (-999..=999).contains(&expected["Abha"].max));
+        }
+    }
+}
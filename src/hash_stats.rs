@@ -0,0 +1,143 @@
+//! Diagnostics of the hash tables of the stations.
+//!
+//! The std `HashMap` does not expose its internals, so the keys of a finished map are inserted again into an
+//! empty map whose hasher counts its invocations. The hasher is a clone of the hasher of the map, so the keys land
+//! in the same buckets. The number of grows and rehashes only depends on the number of keys, not on their order.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+// number of the most crowded buckets reported
+const TOP_BUCKETS: usize = 5;
+
+/// Statistics of one or more hash tables, see [`HashStats::of`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HashStats {
+    /// Number of maps the statistics are merged from
+    pub maps: usize,
+    pub entries: usize,
+    pub buckets: usize,
+    /// Number of times the table grows when the entries are inserted into an empty map
+    pub grows: usize,
+    /// Invocations of the hasher by these inserts, the entries moved by a grow are hashed again
+    pub hashes: usize,
+    /// Entries whose home bucket is also the home bucket of another entry
+    pub collisions: usize,
+    /// Station names of the buckets that are the home of the most entries, the most crowded first
+    pub top_buckets: Vec<Vec<String>>,
+}
+
+impl HashStats {
+    /// Measures the table of the map.
+    pub fn of<K: AsRef<str> + Hash + Eq, V, S: BuildHasher + Clone>(m: &HashMap<K, V, S>) -> HashStats {
+        let count = Cell::new(0);
+        let mut replay: HashMap<&K, (), Counting<S>> = HashMap::with_hasher(Counting { inner: m.hasher().clone(), count: &count });
+        let mut grows: usize = 0;
+        for key in m.keys() {
+            let capacity = replay.capacity();
+            replay.insert(key, ());
+            grows += (replay.capacity() != capacity) as usize;
+        }
+
+        // the tables of hashbrown have a power of two buckets, up to 7/8 of them are used
+        let buckets = if m.capacity() == 0 { 0 } else { (m.capacity() + 1).next_power_of_two() };
+        let mut homes: HashMap<usize, Vec<&str>> = HashMap::new();
+        for key in m.keys() {
+            // the home bucket is selected by the low bits of the hash
+            let home = m.hasher().hash_one(key) as usize & buckets.wrapping_sub(1);
+            homes.entry(home).or_default().push(key.as_ref());
+        }
+        let collisions = homes.values().filter(|stations| stations.len() > 1).map(|stations| stations.len()).sum();
+        let mut crowded: Vec<Vec<&str>> = homes.into_values().filter(|stations| stations.len() > 1).collect();
+        for stations in &mut crowded {
+            stations.sort_unstable();
+        }
+        crowded.sort_unstable_by(|s1, s2| s2.len().cmp(&s1.len()).then_with(|| s1.cmp(s2)));
+        crowded.truncate(TOP_BUCKETS);
+
+        HashStats {
+            maps: 1,
+            entries: m.len(),
+            buckets,
+            grows,
+            hashes: count.get(),
+            collisions,
+            top_buckets: crowded.into_iter().map(|stations| stations.into_iter().map(str::to_owned).collect()).collect(),
+        }
+    }
+
+    /// Adds the statistics of the maps of other workers.
+    pub fn merge(&mut self, other: HashStats) {
+        self.maps += other.maps;
+        self.entries += other.entries;
+        self.buckets += other.buckets;
+        self.grows += other.grows;
+        self.hashes += other.hashes;
+        self.collisions += other.collisions;
+        self.top_buckets.extend(other.top_buckets);
+        self.top_buckets.sort_by_key(|stations| std::cmp::Reverse(stations.len()));
+        self.top_buckets.truncate(TOP_BUCKETS);
+    }
+
+    /// Fraction of the buckets that hold an entry.
+    pub fn load_factor(&self) -> f64 {
+        if self.buckets == 0 { 0.0 } else { self.entries as f64 / self.buckets as f64 }
+    }
+}
+
+// counts the hashers built, one per hashed key
+struct Counting<'c, S> {
+    inner: S,
+    count: &'c Cell<usize>,
+}
+
+impl<S: BuildHasher> BuildHasher for Counting<'_, S> {
+    type Hasher = S::Hasher;
+
+    fn build_hasher(&self) -> S::Hasher {
+        self.count.set(self.count.get() + 1);
+        self.inner.build_hasher()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    use super::*;
+
+    // hashes every key to its length, so that the collisions are known
+    #[derive(Default)]
+    struct LengthHasher(u64);
+
+    impl Hasher for LengthHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            // `str` also writes a 0xff terminator
+            self.0 += bytes.len() as u64 * (bytes != [0xff]) as u64;
+        }
+    }
+
+    #[test]
+    fn collisions_grows_and_hashes() {
+        let mut m: HashMap<&str, u32, BuildHasherDefault<LengthHasher>> = HashMap::default();
+        for station in ["Oslo", "Rome", "Lima", "Paris", "Abha", "Cairo", "Accra"] {
+            m.insert(station, 0);
+        }
+        let stats = HashStats::of(&m);
+        assert_eq!((stats.entries, stats.buckets, stats.collisions), (7, 8, 7));
+        // 0 -> 4 -> 8 buckets, the 3 entries of the first table are hashed again
+        assert_eq!((stats.grows, stats.hashes), (2, 10));
+        let names = |stations: &[&str]| stations.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(stats.top_buckets, [names(&["Abha", "Lima", "Oslo", "Rome"]), names(&["Accra", "Cairo", "Paris"])]);
+        assert_eq!(stats.load_factor(), 7.0 / 8.0);
+
+        let mut merged = stats.clone();
+        merged.merge(HashStats::of(&HashMap::<&str, u32>::new()));
+        assert_eq!((merged.maps, merged.entries, merged.buckets, merged.top_buckets.len()), (2, 7, 8, 2));
+    }
+}
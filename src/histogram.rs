@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Aggregator, MinMeanMax, StationData};
+
+/// [`Aggregator`] computing the min/mean/max of every station together with a histogram of its temperatures.
+///
+/// The buckets have a fixed width and cover the valid range of -99.9..=99.9, temperatures outside of it are
+/// counted in the first or the last bucket.
+pub struct Histogram {
+    // in tenths of a degree
+    bucket_width: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StationHistogram {
+    pub data: StationData,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub const MIN_TEMP: i32 = -999;
+    pub const MAX_TEMP: i32 = 999;
+
+    /// Creates a histogram with buckets of `bucket_width` tenths of a degree.
+    pub fn with_bucket_width(bucket_width: i32) -> Histogram {
+        assert!(bucket_width > 0, "Bucket width must be positive");
+        Histogram { bucket_width }
+    }
+
+    pub fn bucket_width(&self) -> i32 {
+        self.bucket_width
+    }
+
+    pub fn buckets(&self) -> usize {
+        ((Self::MAX_TEMP - Self::MIN_TEMP) / self.bucket_width + 1) as usize
+    }
+
+    /// Lower bound of the bucket `i`, in tenths of a degree.
+    pub fn bucket_start(&self, i: usize) -> i32 {
+        Self::MIN_TEMP + i as i32 * self.bucket_width
+    }
+
+    /// Memory taken by the histogram of a single station, in bytes.
+    pub fn bytes_per_station(&self) -> usize {
+        self.buckets() * size_of::<u64>()
+    }
+
+    fn bucket(&self, temp: i32) -> usize {
+        ((temp.clamp(Self::MIN_TEMP, Self::MAX_TEMP) - Self::MIN_TEMP) / self.bucket_width) as usize
+    }
+}
+
+impl Aggregator for Histogram {
+    type State = StationHistogram;
+
+    fn init(&self, temp: i32) -> StationHistogram {
+        let mut counts = vec![0; self.buckets()];
+        counts[self.bucket(temp)] = 1;
+        StationHistogram { data: MinMeanMax.init(temp), counts }
+    }
+
+    fn observe(&self, state: &mut StationHistogram, temp: i32) {
+        MinMeanMax.observe(&mut state.data, temp);
+        state.counts[self.bucket(temp)] += 1;
+    }
+
+    fn merge(&self, state: &mut StationHistogram, other: StationHistogram) {
+        MinMeanMax.merge(&mut state.data, other.data);
+        for (c, o) in state.counts.iter_mut().zip(other.counts) {
+            *c += o;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_the_valid_range() {
+        let h = Histogram::with_bucket_width(10);
+        assert_eq!(h.buckets(), 200);
+        assert_eq!(h.bucket(-999), 0);
+        assert_eq!(h.bucket(-990), 0);
+        assert_eq!(h.bucket(-989), 1);
+        assert_eq!(h.bucket(999), 199);
+        assert_eq!(h.bucket(1500), 199);
+        assert_eq!(h.bucket_start(1), -989);
+    }
+
+    #[test]
+    fn merge_adds_counts() {
+        let h = Histogram::with_bucket_width(500);
+        let mut a = h.init(-999);
+        h.observe(&mut a, 0);
+        let mut b = h.init(999);
+        h.observe(&mut b, 10);
+        h.merge(&mut a, b);
+        assert_eq!(a.counts, vec![1, 1, 1, 1]);
+        assert_eq!(a.data.n, 4);
+    }
+}
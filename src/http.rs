@@ -0,0 +1,222 @@
+//! Minimal blocking HTTP/1.1 server, behind the `http` feature.
+//!
+//! The connections are served one at a time and closed after the response, which is all the tools
+//! polling the results need. The request bodies must have a `Content-Length`, chunked bodies are refused.
+
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use crate::Cancel;
+
+// the request line and the headers
+const MAX_HEADER_BYTES: usize = 16 << 10;
+const MAX_HEADERS: usize = 64;
+
+// how long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// how often the accept loop checks for cancellation
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// A request with its whole body, the path includes the query string.
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: Vec<u8>) -> Response {
+        Response { status, content_type: "application/json", body }
+    }
+
+    /// A plain text response, a line feed is appended to the message.
+    pub fn text(status: u16, message: impl Into<String>) -> Response {
+        let mut body = message.into().into_bytes();
+        body.push(b'\n');
+        Response { status, content_type: "text/plain; charset=utf-8", body }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Serves the connections of the listener with the handler until cancelled, the request bodies are capped at `max_body` bytes.
+///
+/// The errors of a connection are printed to stderr and do not stop the server.
+pub fn serve<H: Fn(&Request) -> Response>(listener: &TcpListener, max_body: usize, handler: H, cancel: &Cancel) -> Result<(), Error> {
+    // polled, so that Ctrl-C stops the server between the connections
+    listener.set_nonblocking(true)?;
+    while !cancel.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = handle_connection(stream, max_body, &handler) {
+                    eprintln!("Warning: HTTP connection failed: {}", e);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the `%XX` escapes of a path segment, `None` if an escape is invalid or the result is not UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let digit = |b: u8| (b as char).to_digit(16);
+                out.push((digit(bytes.next()?)? * 16 + digit(bytes.next()?)?) as u8);
+            }
+            _ => out.push(b),
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn handle_connection<H: Fn(&Request) -> Response>(mut stream: TcpStream, max_body: usize, handler: &H) -> Result<(), Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(&mut stream, max_body)? {
+        Ok(request) => handler(&request),
+        Err(response) => response,
+    };
+    write_response(&mut stream, &response)
+}
+
+// the request, or the error response if it cannot be served
+fn read_request(stream: &mut TcpStream, max_body: usize) -> Result<Result<Request, Response>, Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        match parsed.parse(&buf) {
+            Ok(httparse::Status::Complete(header_len)) => {
+                let header = |name: &str| parsed.headers.iter().find(|h| h.name.eq_ignore_ascii_case(name)).map(|h| h.value);
+                if header("transfer-encoding").is_some() {
+                    return Ok(Err(Response::text(411, "The request body needs a Content-Length")));
+                }
+                let length = match header("content-length").map(|v| std::str::from_utf8(v).ok().and_then(|v| v.trim().parse::<usize>().ok())) {
+                    None => 0,
+                    Some(Some(length)) => length,
+                    Some(None) => return Ok(Err(Response::text(400, "Invalid Content-Length"))),
+                };
+                if length > max_body {
+                    return Ok(Err(Response::text(413, format!("The request body is larger than {} bytes", max_body))));
+                }
+                let method = parsed.method.unwrap_or_default().to_owned();
+                let path = parsed.path.unwrap_or_default().to_owned();
+                let mut body = buf.split_off(header_len);
+                body.truncate(length);
+                if body.len() < length {
+                    let missing = length - body.len();
+                    body.reserve_exact(missing);
+                    stream.take(missing as u64).read_to_end(&mut body)?;
+                    if body.len() < length {
+                        return Err(Error::new(ErrorKind::UnexpectedEof, "the request body is shorter than its Content-Length"));
+                    }
+                }
+                return Ok(Ok(Request { method, path, body }));
+            }
+            Ok(httparse::Status::Partial) if buf.len() >= MAX_HEADER_BYTES => {
+                return Ok(Err(Response::text(431, format!("The request headers are larger than {} bytes", MAX_HEADER_BYTES))));
+            }
+            Ok(httparse::Status::Partial) => {}
+            Err(e) => return Ok(Err(Response::text(400, format!("Invalid request: {}", e)))),
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the connection was closed before the end of the request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> Result<(), Error> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           response.status, reason(response.status), response.content_type, response.body.len())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::CancelReason;
+
+    // sends the raw request to a server echoing the method, the path and the body, returns the raw response
+    fn exchange(request: &[u8], max_body: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancel = Arc::new(Cancel::default());
+        let server = {
+            let cancel = Arc::clone(&cancel);
+            thread::spawn(move || {
+                let echo = |r: &Request| Response::text(200, format!("{} {} {}", r.method, r.path, String::from_utf8_lossy(&r.body)));
+                serve(&listener, max_body, echo, &cancel).unwrap();
+            })
+        };
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        cancel.cancel(CancelReason::Interrupted);
+        server.join().unwrap();
+        response
+    }
+
+    #[test]
+    fn requests_are_read_whole() {
+        let response = exchange(b"POST /aggregate?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 10\r\n\r\nOslo;-3.4\n", 100);
+        assert_eq!(response, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 31\r\nConnection: close\r\n\r\nPOST /aggregate?x=1 Oslo;-3.4\n\n");
+        let response = exchange(b"GET /healthz HTTP/1.1\r\n\r\n", 100);
+        assert!(response.ends_with("\r\n\r\nGET /healthz \n"), "{}", response);
+    }
+
+    #[test]
+    fn invalid_requests_are_refused() {
+        let status = |request: &[u8]| exchange(request, 10).lines().next().unwrap().to_owned();
+        assert_eq!(status(b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\n"), "HTTP/1.1 413 Payload Too Large");
+        assert_eq!(status(b"POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"), "HTTP/1.1 411 Length Required");
+        assert_eq!(status(b"NOT HTTP\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(percent_decode("S%C3%A3o%20Paulo").as_deref(), Some("São Paulo"));
+        assert_eq!(percent_decode("Z%c3%bcrich+1").as_deref(), Some("Zürich+1"));
+        assert_eq!(percent_decode("St.%20John%27s").as_deref(), Some("St. John's"));
+        for invalid in ["%", "%4", "%zz", "%+1", "%ff"] {
+            assert_eq!(percent_decode(invalid), None, "{}", invalid);
+        }
+    }
+}
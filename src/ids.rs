@@ -0,0 +1,236 @@
+//! Aggregation of records keyed by integer station IDs, `4217;12.3`, named from a separate mapping only at the output.
+//!
+//! The IDs are cheaper keys than the names: the small ones index a `Vec`, only the others are hashed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::parse::ParseOptions;
+use crate::read::{for_each_record, invalid_record, lines_before, records_offset, slice_offsets, without_repeats, Invalid};
+use crate::{Aggregator, Cancel};
+
+/// IDs below this index the `Vec` of [`IdStations`], the larger ones are kept in a hash map.
+pub const MAX_DENSE_ID: u64 = 1 << 16;
+
+/// Reads the `id,name` lines of the mapping, the first line is skipped as a header if its ID is not a number.
+///
+/// The name is everything after the first comma, blank lines are skipped. An ID mapped twice is an error.
+pub fn read_id_map(path: &Path) -> Result<HashMap<u64, String>, Error> {
+    let invalid = |line: usize, message: String| Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, message));
+    let text = fs::read_to_string(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let mut names = HashMap::new();
+    for (i, l) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let Some((id, name)) = l.split_once(',') else {
+            return Err(invalid(i + 1, format!("Expected an ID and a name separated by a comma: {}", l)));
+        };
+        let id = match id.trim().parse::<u64>() {
+            Ok(id) => id,
+            Err(_) if i == 0 => continue,
+            Err(_) => return Err(invalid(i + 1, format!("Invalid station ID {}", id))),
+        };
+        if names.insert(id, name.to_owned()).is_some() {
+            return Err(invalid(i + 1, format!("Station ID {} is mapped twice", id)));
+        }
+    }
+    Ok(names)
+}
+
+/// Stations keyed by their IDs: a `Vec` indexed by the IDs below [`MAX_DENSE_ID`], grown to the largest of them seen,
+/// and a hash map of the others.
+pub struct IdStations<S> {
+    dense: Vec<Option<S>>,
+    sparse: HashMap<u64, S>,
+}
+
+impl<S> IdStations<S> {
+    pub fn new() -> IdStations<S> {
+        IdStations { dense: Vec::new(), sparse: HashMap::new() }
+    }
+
+    pub fn observe<A: Aggregator<State = S>>(&mut self, aggregator: &A, id: u64, temp: i32, offset: u64) {
+        match self.entry(id) {
+            Some(state) => aggregator.observe_at(state, temp, offset),
+            None => self.insert(id, aggregator.init_at(temp, offset)),
+        }
+    }
+
+    pub fn merge<A: Aggregator<State = S>>(&mut self, aggregator: &A, other: IdStations<S>) {
+        for (id, state) in other.into_iter() {
+            match self.entry(id) {
+                Some(s) => aggregator.merge(s, state),
+                None => self.insert(id, state),
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.iter().flatten().count() + self.sparse.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The stations named by the mapping, the IDs it does not have are named by their number if `keep_unknown` is set.
+    ///
+    /// Fails with the smallest unknown ID otherwise, or if two IDs are mapped to the same name.
+    pub fn into_named(self, names: &HashMap<u64, String>, keep_unknown: bool) -> Result<HashMap<String, S>, Error> {
+        let mut stations: Vec<(u64, S)> = self.into_iter().collect();
+        stations.sort_unstable_by_key(|&(id, _)| id);
+        let mut m = HashMap::with_capacity(stations.len());
+        for (id, state) in stations {
+            let name = match names.get(&id) {
+                Some(name) => name.clone(),
+                None if keep_unknown => id.to_string(),
+                None => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown station ID {}, it is not in the ID map", id))),
+            };
+            if m.contains_key(&name) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Station {} is the name of more than one ID", name)));
+            }
+            m.insert(name, state);
+        }
+        Ok(m)
+    }
+
+    fn entry(&mut self, id: u64) -> Option<&mut S> {
+        match id < MAX_DENSE_ID {
+            true => self.dense.get_mut(id as usize).and_then(Option::as_mut),
+            false => self.sparse.get_mut(&id),
+        }
+    }
+
+    fn insert(&mut self, id: u64, state: S) {
+        if id < MAX_DENSE_ID {
+            let i = id as usize;
+            if i >= self.dense.len() {
+                self.dense.resize_with(i + 1, || None);
+            }
+            self.dense[i] = Some(state);
+        } else {
+            self.sparse.insert(id, state);
+        }
+    }
+
+    fn into_iter(self) -> impl Iterator<Item = (u64, S)> {
+        self.dense.into_iter().enumerate()
+            .filter_map(|(id, state)| state.map(|s| (id as u64, s)))
+            .chain(self.sparse)
+    }
+}
+
+impl<S> Default for IdStations<S> {
+    fn default() -> Self {
+        IdStations::new()
+    }
+}
+
+/// Variant of [`read_slices_parallel`](crate::read::read_slices_parallel) parsing the stations as integer IDs,
+/// returns the stations and the number of bytes processed.
+///
+/// Stations that are not an ID are invalid records, skipped with `options.lenient`.
+pub fn read_slices_ids<A: Aggregator>(slices: &[&[u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel) -> (IdStations<A::State>, usize) {
+    let offsets = slice_offsets(slices);
+    slices
+        .par_iter()
+        .enumerate()
+        .fold(|| (IdStations::new(), 0),
+              |(mut stations, n), (i, slice)| {
+                  // cancellation point: skip the remaining slices once cancelled
+                  if cancel.is_cancelled() {
+                      return (stations, n);
+                  }
+                  let (records, repeats) = without_repeats(slices, i, options);
+                  let slice_start = records_offset(&offsets, slices, i, records);
+                  let lines_before = || lines_before(slices, i) + repeats;
+                  for_each_record(records, options, &lines_before, |station, temp, offset| match station.parse::<u64>() {
+                      Ok(id) => stations.observe(aggregator, id, temp, slice_start + offset as u64),
+                      Err(_) if options.lenient => {}
+                      Err(_) => invalid_record(records, Invalid::new(format!("Invalid station ID {}", station), station.as_bytes()), &lines_before, options),
+                  });
+                  (stations, n + slice.len())
+              },
+        )
+        .reduce(|| (IdStations::new(), 0),
+                |(mut s1, n1), (s2, n2)| {
+                    s1.merge(aggregator, s2);
+                    (s1, n1 + n2)
+                },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::{load_file, read_slices_parallel, slice_sized, ErrorLog, ReadOptions};
+    use crate::{ExtremeOffsets, MinMeanMax, TrackExtremes};
+
+    #[test]
+    fn ids_are_named_at_the_output() {
+        let data: String = (0..5_000i32).map(|i| format!("{};{}.{}\n", [7u64, 4217, 1 << 40][i as usize % 3], i % 201 - 100, i % 10)).collect();
+        let slices: Vec<&[u8]> = data.as_bytes().split_inclusive(|&b| b == b'\n').collect();
+        let cancel = Cancel::default();
+        let (ids, n1) = read_slices_ids(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        let (map, n2) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        assert_eq!((ids.len(), n1), (3, n2));
+        assert_eq!(ids.dense.len(), 4218);
+
+        let names = HashMap::from([(7, "Oslo".to_owned()), (4217, "Rome".to_owned())]);
+        let e = ids.into_named(&names, false).unwrap_err();
+        assert_eq!(e.to_string(), "Unknown station ID 1099511627776, it is not in the ID map");
+        let (ids, _) = read_slices_ids(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        let named = ids.into_named(&names, true).unwrap();
+        assert_eq!(named["Oslo"], map["7"]);
+        assert_eq!(named["Rome"], map["4217"]);
+        assert_eq!(named["1099511627776"], map["1099511627776"]);
+    }
+
+    #[test]
+    fn stations_that_are_not_ids_are_invalid() {
+        let data = b"7;1.0\nOslo;2.0\n7;3.0\n";
+        let cancel = Cancel::default();
+        let (ids, _) = read_slices_ids(&[data], &MinMeanMax, ParseOptions { lenient: true, ..Default::default() }, &cancel);
+        assert_eq!(ids.len(), 1);
+        let log: &'static ErrorLog = Box::leak(Box::new(ErrorLog::new(10)));
+        let (ids, _) = read_slices_ids(&[data], &MinMeanMax, ParseOptions { errors: Some(log), ..Default::default() }, &cancel);
+        assert_eq!(ids.into_named(&HashMap::from([(7, "Oslo".to_owned())]), false).unwrap()["Oslo"].n, 2);
+        let (errors, _) = log.take();
+        assert_eq!((errors[0].line, errors[0].column, errors[0].message.as_str()), (2, 1, "Invalid station ID Oslo"));
+    }
+
+    #[test]
+    fn mappings_are_read_with_an_optional_header() {
+        let path = std::env::temp_dir().join(format!("rust-1brc-ids-{}.csv", std::process::id()));
+        fs::write(&path, "id,name\n7,Oslo\n\n4217,St. John's, Newfoundland\n").unwrap();
+        let names = read_id_map(&path).unwrap();
+        assert_eq!(names, HashMap::from([(7, "Oslo".to_owned()), (4217, "St. John's, Newfoundland".to_owned())]));
+        fs::write(&path, "7,Oslo\n7,Rome\n").unwrap();
+        assert_eq!(read_id_map(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        fs::write(&path, "7,Oslo\nx,Rome\n").unwrap();
+        assert!(read_id_map(&path).unwrap_err().to_string().ends_with(":2: Invalid station ID x"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_are_read_together() {
+        let paths = [7, 8].map(|id| std::env::temp_dir().join(format!("rust-1brc-ids-{}-{}.txt", std::process::id(), id)));
+        for (path, id) in paths.iter().zip([7, 8]) {
+            fs::write(path, (0..1_000).map(|i| format!("{};{}.0\n", id, (i + id) % 10)).collect::<String>()).unwrap();
+        }
+        let files = paths.each_ref().map(|path| load_file(path, ReadOptions::default()).unwrap());
+        // the slices of the files are flattened like by `--id-map`, the mappings in any order in memory
+        let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, 256)).collect();
+        let (ids, _) = read_slices_ids(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default());
+        let m = ids.into_named(&HashMap::from([(7, "Oslo".to_owned()), (8, "Rome".to_owned())]), false).unwrap();
+        // the offsets are counted from the start of every file, the lines are 6 bytes
+        assert_eq!(m["Oslo"].offsets, ExtremeOffsets { min: 3 * 6, max: 2 * 6 });
+        assert_eq!(m["Rome"].offsets, ExtremeOffsets { min: 2 * 6, max: 6 });
+        assert_eq!((m["Oslo"].data.n, m["Rome"].data.n), (1_000, 1_000));
+        for path in &paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use bumpalo::Bump;
+
+/// Deduplicating interner of station names.
+///
+/// The names are copied into a bump arena, so that every station costs no separate heap allocation and the names
+/// sit next to each other in memory. The interned names live as long as the arena and are never freed one by one.
+pub struct Interner<'a> {
+    arena: &'a Bump,
+    names: HashSet<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new(arena: &'a Bump) -> Interner<'a> {
+        Interner { arena, names: HashSet::new() }
+    }
+
+    /// Returns the interned copy of `name`, copying it into the arena the first time it is seen.
+    pub fn intern(&mut self, name: &str) -> &'a str {
+        match self.names.get(name) {
+            Some(interned) => interned,
+            None => {
+                let interned: &'a str = self.arena.alloc_str(name);
+                self.names.insert(interned);
+                interned
+            }
+        }
+    }
+
+    /// Returns the interned copy of `name` if there is one.
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.names.get(name).copied()
+    }
+
+    /// Number of distinct names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        assert_eq!(interner.get("Hamburg"), None);
+        let hamburg = interner.intern("Hamburg");
+        assert_eq!(hamburg, "Hamburg");
+        assert_eq!(interner.get("Hamburg"), Some("Hamburg"));
+        assert_eq!(interner.get("Ham"), None);
+    }
+
+    #[test]
+    fn dedup() {
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let a = interner.intern("Hamburg");
+        let b = interner.intern(&String::from("Hamburg"));
+        assert!(std::ptr::eq(a, b));
+        interner.intern("Bulawayo");
+        assert_eq!(interner.len(), 2);
+        assert!(std::ptr::eq(interner.get("Hamburg").unwrap(), a));
+    }
+
+    #[test]
+    fn growth() {
+        let arena = Bump::with_capacity(16);
+        let mut interner = Interner::new(&arena);
+        let names: Vec<String> = (0..10_000).map(|i| format!("Station {}", i)).collect();
+        let interned: Vec<&str> = names.iter().map(|n| interner.intern(n)).collect();
+        assert_eq!(interner.len(), names.len());
+        // names interned before the arena grew stay valid
+        for (name, interned) in names.iter().zip(interned) {
+            assert_eq!(name, interned);
+            assert!(std::ptr::eq(interner.get(name).unwrap(), interned));
+        }
+    }
+}
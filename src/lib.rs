@@ -0,0 +1,169 @@
+//! Aggregation of weather station measurements in the format of
+//! [The One Billion Row Challenge](https://github.com/gunnarmorling/1brc): one `station;temperature` record per line.
+
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use rayon::prelude::*;
+
+mod aggregator;
+pub mod cache;
+mod cancel;
+pub mod dense;
+pub mod diff;
+pub mod encoding;
+pub mod format;
+pub mod generate;
+pub mod hash_stats;
+mod histogram;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod ids;
+mod intern;
+pub mod metrics;
+mod offsets;
+pub mod parse;
+pub mod perf;
+pub mod quality;
+pub mod read;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sample;
+pub mod shared;
+mod station;
+
+pub use aggregator::Aggregator;
+pub use cancel::{Cancel, CancelReason};
+pub use bumpalo::Bump;
+pub use histogram::{Histogram, StationHistogram};
+pub use intern::Interner;
+pub use offsets::{ExtremeOffsets, FirstLast, FirstLastStationData, TrackExtremes, TrackFirstLast, TrackedStationData};
+pub use station::{CheckedMinMeanMax, CheckedStationData, MinMeanMax, StationData};
+
+/// Computes the min/mean/max temperature of every station in the file.
+pub fn aggregate<P: AsRef<Path>>(path: P) -> Result<HashMap<String, StationData>, Error> {
+    aggregate_with(path, &MinMeanMax)
+}
+
+/// Computes custom per-station statistics defined by the [`Aggregator`].
+pub fn aggregate_with<A: Aggregator, P: AsRef<Path>>(path: P, aggregator: &A) -> Result<HashMap<String, A::State>, Error> {
+    let arena = Bump::new();
+    let (m, _) = read::read_file_parallel(path.as_ref(), aggregator, &mut Interner::new(&arena), parse::ParseOptions::default(), &Cancel::default())?;
+    read::validate(aggregator, &m)?;
+    Ok(m.into_iter().map(|(station, state)| (station.to_owned(), state)).collect())
+}
+
+/// Computes the min/mean/max temperature of every station like [`aggregate`], calling `f` on the current thread
+/// every `interval` with the stations aggregated so far and the progress of the read, and once more with the result.
+///
+/// The workers are not stopped for the snapshots, they are only copied while the workers merge their slices.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("rust-1brc-progress.txt");
+/// std::fs::write(&path, "Oslo;-3.5\nRome;12.0\nOslo;1.5\n")?;
+///
+/// let mut snapshots = Vec::new();
+/// let result = rust_1brc::aggregate_with_progress(&path, Duration::from_millis(100), |stations, progress| {
+///     snapshots.push((stations.clone(), progress.rows));
+/// })?;
+/// assert_eq!(snapshots.last(), Some(&(result, 3)));
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub fn aggregate_with_progress<P: AsRef<Path>, F: FnMut(&HashMap<String, StationData>, read::Progress)>(path: P, interval: Duration, mut f: F) -> Result<HashMap<String, StationData>, Error> {
+    let data = read::load_file(path.as_ref(), read::ReadOptions::default())?;
+    let slices = read::slice(&data);
+    let owned = |m: &HashMap<&str, StationData>| m.iter().map(|(&station, &d)| (station.to_owned(), d)).collect();
+    let (m, progress) = read::read_slices_snapshots(&slices, &MinMeanMax, parse::ParseOptions::default(), &Cancel::default(), interval, |m, progress| f(&owned(m), progress));
+    read::validate(&MinMeanMax, &m)?;
+    let m = owned(&m);
+    f(&m, progress);
+    Ok(m)
+}
+
+/// Calls `f` with the station and the temperature in tenths of a degree of every record of the file,
+/// without aggregating them.
+///
+/// The file is memory mapped and parsed in slices like by [`aggregate`], `f` is called from the rayon worker
+/// threads at the same time and in no particular order, hence `Sync`. [`for_each_record_serial`] calls it
+/// on the current thread in the order of the file. Invalid records panic like in [`aggregate`].
+///
+/// ```
+/// use std::sync::atomic::{AtomicI64, Ordering};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("rust-1brc-for-each.txt");
+/// std::fs::write(&path, "Oslo;-3.5\nRome;12.0\nOslo;1.5\n")?;
+///
+/// let frost_tenths = AtomicI64::new(0);
+/// rust_1brc::for_each_record(&path, |_station, temp| {
+///     if temp < 0 {
+///         frost_tenths.fetch_add(temp as i64, Ordering::Relaxed);
+///     }
+/// })?;
+/// assert_eq!(frost_tenths.into_inner(), -35);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub fn for_each_record<P: AsRef<Path>, F: Fn(&str, i32) + Sync>(path: P, f: F) -> Result<(), Error> {
+    let data = read::load_file(path.as_ref(), read::ReadOptions::default())?;
+    let slices = read::slice(&data);
+    slices.par_iter().enumerate().for_each(|(i, slice)| {
+        read::for_each_record(slice, parse::ParseOptions::default(), &|| read::lines_before(&slices, i), |station, temp, _| f(station, temp))
+    });
+    Ok(())
+}
+
+/// Calls `f` with every record of the file like [`for_each_record`], on the current thread in the order of the file.
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("rust-1brc-for-each-serial.txt");
+/// std::fs::write(&path, "Oslo;-3.5\nRome;12.0\nOslo;1.5\n")?;
+///
+/// let mut records = Vec::new();
+/// rust_1brc::for_each_record_serial(&path, |station, temp| records.push((station.to_owned(), temp)))?;
+/// assert_eq!(records, [("Oslo".to_owned(), -35), ("Rome".to_owned(), 120), ("Oslo".to_owned(), 15)]);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub fn for_each_record_serial<P: AsRef<Path>, F: FnMut(&str, i32)>(path: P, mut f: F) -> Result<(), Error> {
+    let data = read::load_file(path.as_ref(), read::ReadOptions::default())?;
+    read::for_each_record(&data, parse::ParseOptions::default(), &|| 0, |station, temp, _| f(station, temp));
+    Ok(())
+}
+
+/// Sorted iteration over the result of [`aggregate`].
+///
+/// The finished map is not aggregated again, only the station names are sorted:
+///
+/// ```
+/// use rust_1brc::{aggregate, SortedStations};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("rust-1brc-sorted.txt");
+/// std::fs::write(&path, "Oslo;-3.5\nAbha;25.0\nOslo;1.5\n")?;
+///
+/// let result = aggregate(&path)?;
+/// let stations: Vec<(&str, u32)> = result.iter_sorted().map(|(station, data)| (station, data.count())).collect();
+/// assert_eq!(stations, [("Abha", 1), ("Oslo", 2)]);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub trait SortedStations {
+    /// Iterates over the stations in the byte order of their names, as in the output of the challenge.
+    fn iter_sorted(&self) -> impl Iterator<Item = (&str, StationData)>;
+}
+
+impl<K: AsRef<str>> SortedStations for HashMap<K, StationData> {
+    fn iter_sorted(&self) -> impl Iterator<Item = (&str, StationData)> {
+        let mut stations: Vec<(&str, &StationData)> = self.iter().map(|(station, data)| (station.as_ref(), data)).collect();
+        stations.sort_unstable_by_key(|&(station, _)| station);
+        stations.into_iter().map(|(station, &data)| (station, data))
+    }
+}
@@ -1,171 +1,3063 @@
-use std::collections::HashMap;
-use std::env;
-use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Error};
-use std::path::Path;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::hint::black_box;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use memmap::Mmap;
-use rayon::prelude::*;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-struct StationData {
-    min_temp: f64,
-    max_temp: f64,
-    sum_temp: f64,
-    n: u32,
+use rust_1brc::cache::{CacheKey, ResultsCache};
+use rust_1brc::metrics::{merge_metrics, read_metrics_parallel, StationMetrics};
+use rust_1brc::dense::read_slices_dense;
+use rust_1brc::diff::{self, ResultsDiff};
+use rust_1brc::encoding::Encoding;
+use rust_1brc::format::{self, Collation, CountsFormat, JsonMeta, Rounding, Row, RunMeta, SortKey, Stats, Template, Total};
+use rust_1brc::hash_stats::HashStats;
+use rust_1brc::ids::{self, read_slices_ids};
+use rust_1brc::generate;
+use rust_1brc::sample;
+use rust_1brc::shared::read_slices_shared;
+use rust_1brc::parse::{Columns, ParseOptions, Scale};
+use rust_1brc::perf::{PerfCounters, PerfCounts};
+use rust_1brc::quality::QualityReport;
+use rust_1brc::read::{byte_range, count_lines_parallel, merge, merge_all, normalize_keys, parse_slices_parallel, parse_slices_parallel_with_progress, read_files_parallel, read_slices_streaming, read_stations_data, read_stations_data_from, scan_slices_parallel, scan_stations_data, slice, slice_sized, validate, check_unchanged, evict_from_page_cache, input_size, is_bzip2, is_s3, is_stdin, is_transcoded, load_file, open_input, regular_files, with_readahead, ErrorLog, ErrorTrap, FileData, KeyNormalization, ParsedSlices, ReadOptions, ReadaheadStats, Retry, WorkerStats, SLICE_SIZE};
+use rust_1brc::{Aggregator, Bump, Cancel, CancelReason, CheckedMinMeanMax, Histogram, Interner, MinMeanMax, StationData, TrackExtremes, TrackFirstLast};
+
+/// Durations of the consecutive stages of a run.
+struct Stages {
+    stages: Vec<(&'static str, Duration)>,
+    last: Instant,
+}
+
+impl Stages {
+    fn start() -> Stages {
+        Stages { stages: Vec::new(), last: Instant::now() }
+    }
+
+    // ends the current stage
+    fn mark(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now - self.last));
+        self.last = now;
+    }
+}
+
+/// Outcome of a single run, used to report the duration and truncated runs.
+struct RunInfo {
+    name: &'static str,
+    duration: Duration,
+    perf: Option<PerfCounts>,
+    stages: Stages,
+    // only filled in by the implementations that track the work of the rayon workers
+    workers: Vec<WorkerStats>,
+    threads: usize,
+    files: usize,
+    bytes_processed: usize,
+    bytes_total: usize,
+    cancelled: Option<CancelReason>,
+    // whether the inputs were evicted from the page cache before the run, `None` without --cold
+    cold: Option<bool>,
+    // with --hash-stats, the tables of the stations
+    hash: Option<HashStats>,
+    // with --readahead, what the readahead thread did
+    readahead: Option<ReadaheadStats>,
+}
+
+impl RunInfo {
+    // ends the stage of the output, which the duration of the run covers too, so that the stages add up to it
+    fn mark_output(&mut self) {
+        self.stages.mark("sort+format");
+        self.duration += self.stages.stages.last().map_or(Duration::ZERO, |&(_, d)| d);
+    }
+}
+
+// a run of one of the implementations, from before the inputs are read until its result is printed
+struct Run<'a> {
+    output: &'a Output,
+    cancel: &'a Cancel,
+    files: usize,
+    perf: Option<PerfCounters>,
+    cold: Option<bool>,
+    start: Instant,
+}
+
+impl<'a> Run<'a> {
+    fn start(paths: &[PathBuf], output: &'a Output, cancel: &'a Cancel) -> Self {
+        let perf = output.perf_counters.then(start_perf_counters).flatten();
+        let cold = prepare_cache(paths, output);
+        Run { output, cancel, files: paths.len(), perf, cold, start: Instant::now() }
+    }
+
+    // the workers are only reported with --worker-stats
+    fn info(self, name: &'static str, stages: Stages, workers: Vec<WorkerStats>, bytes_processed: usize, bytes_total: usize) -> RunInfo {
+        RunInfo {
+            name,
+            duration: self.start.elapsed(),
+            perf: stop_perf_counters(self.perf),
+            stages,
+            workers: if self.output.worker_stats { workers } else { Vec::new() },
+            threads: rayon::current_num_threads(),
+            files: self.files,
+            bytes_processed,
+            bytes_total,
+            cancelled: self.cancel.reason(),
+            cold: self.cold,
+            hash: None,
+            readahead: None,
+        }
+    }
+
+    fn finish<K: AsRef<str> + Hash + Eq, S: Stats>(self, name: &'static str, stages: Stages, workers: Vec<WorkerStats>, m: &HashMap<K, S>, bytes_processed: usize, bytes_total: usize) -> Result<(), Error> {
+        let output = self.output;
+        let mut info = self.info(name, stages, workers, bytes_processed, bytes_total);
+        info.hash = output.hash_stats.then(|| HashStats::of(m));
+        report(m, output, info)
+    }
+}
+
+// the keys are normalized with --normalize, into the `arena`
+fn normalized<'a, A: Aggregator>(aggregator: &A, output: &Output, arena: &'a Bump, m: HashMap<&'a str, A::State>) -> HashMap<&'a str, A::State> {
+    match output.normalize {
+        Some(keys) => normalize_keys(aggregator, &mut Interner::new(arena), m, keys),
+        None => m,
+    }
+}
+
+fn report<K: AsRef<str>, S: Stats>(m: &HashMap<K, S>, output: &Output, mut info: RunInfo) -> Result<(), Error> {
+    print_result(m, output, &mut info)?;
+    print_duration(&info, output);
+    Ok(())
+}
+
+/// In-progress state of the simple reader, periodically saved with `--checkpoint`.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<M> {
+    /// Number of bytes of the input already aggregated into `stations`
+    offset: usize,
+    stations: M,
+}
+
+struct Output {
+    format: Format,
+    // with --collect-errors, the invalid records of the runs, printed after each of them
+    errors: Option<&'static ErrorLog>,
+    // some of the runs had invalid records
+    invalid: AtomicBool,
+    // of `Format::Template`
+    template: Option<Template>,
+    histogram: Option<Histogram>,
+    // written instead of stdout if set
+    path: Option<PathBuf>,
+    // directory of the files with the result of a single station, written instead of the output if set
+    split_output: Option<PathBuf>,
+    // files written in addition to the output, each in its own format
+    tee: Vec<(Format, PathBuf)>,
+    // file of the counts of the stations written in addition to the output
+    counts_output: Option<(CountsFormat, PathBuf)>,
+    // stations dropped from the result, normalized like the station names
+    exclude: HashSet<String>,
+    // with --only-stations, the only stations of the result, normalized like the station names
+    only: Option<&'static HashSet<String>>,
+    // description of the input files for the reports
+    input: String,
+    perf_counters: bool,
+    // merge the stations whose names are equal after the normalization
+    normalize: Option<KeyNormalization>,
+    // print only the first or the last stations in alphabetical order
+    head: Option<usize>,
+    tail: Option<usize>,
+    // also run the implementation aggregating into dense station IDs
+    dense_ids: bool,
+    shared_map: bool,
+    worker_stats: bool,
+    // print the statistics of the hash tables of the stations to stderr
+    hash_stats: bool,
+    // the parallel read prints snapshots of the partial result this often
+    stream_every: Option<Duration>,
+    // how the parallel implementations load and split the files
+    read: ReadOptions,
+    // with --cache-dir, the cached results and the configuration of the aggregation their keys include
+    cache: Option<(ResultsCache, String)>,
+    // the lead of the readahead thread of the parallel mmap read
+    readahead: Option<usize>,
+    collation: Collation,
+    sort_by: SortKey,
+    // print the hottest and the coldest station to stderr after the result
+    extremes: bool,
+    // wrap the JSON output in an object with the unit and the time of the run
+    metadata: bool,
+    // end the NDJSON output with a summary of all the stations
+    summary: bool,
+    // write the total of all the stations in every format
+    total: bool,
+    // number of decimals of the min, mean and max
+    precision: usize,
+    // of the parsed temperatures, the rows are divided back by it
+    scale: Scale,
+    // of `Format::Table`, longer station names are truncated
+    max_station_width: usize,
+    rounding: Rounding,
+    // name, duration and input size of every completed run, for the --repeat statistics and the baselines
+    timings: Mutex<Vec<(&'static str, bool, Duration, usize)>>,
+    // with --compare, the name and the fingerprint of the result of every completed run
+    results: Option<Mutex<Vec<(&'static str, u64)>>>,
+    // with --cold, whether the implementations of the current iteration start with the inputs evicted from the page cache
+    cold: Option<AtomicBool>,
+    // with --serve, the last result, `None` until the first one is printed
+    served: Option<Arc<RwLock<Option<Served>>>>,
+    /// The byte range of --byte-start and --byte-end
+    byte_range: Option<(usize, usize)>,
+    /// The names of the station IDs of --id-map
+    id_map: Option<HashMap<u64, String>>,
+}
+
+// the JSON of a result served by --serve, rendered when the result is printed
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+struct Served {
+    stations: Vec<u8>,
+    // of every station by its name
+    station: HashMap<String, Vec<u8>>,
+}
+
+impl Served {
+    // the JSON of all the stations and that of every row
+    fn new(stations: Vec<u8>, rows: &[Row]) -> Result<Served, Error> {
+        let mut station = HashMap::with_capacity(rows.len());
+        for r in rows {
+            let mut json = Vec::new();
+            format::write_ndjson(&mut json, std::slice::from_ref(r), false)?;
+            station.insert(r.station.to_owned(), json);
+        }
+        Ok(Served { stations, station })
+    }
+}
+
+// the result served by --serve and the thread serving it
+type Server = (Arc<RwLock<Option<Served>>>, thread::JoinHandle<Result<(), Error>>);
+
+// configuration of the simple file read
+struct SimpleReadConfig {
+    read_buffer: usize,
+    checkpoint: Option<PathBuf>,
+    resume: Option<PathBuf>,
+    interval: Duration,
+    // decompress all the files, not only the `.bz2` ones
+    bzip2: bool,
+    encoding: Encoding,
+    // retries of a read failing with a transient error, reported with --verbose
+    retry: u32,
+    verbose: bool,
+}
+
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Paths to the measurements files or directories, `-` for the standard input, or `s3://bucket/key` objects with the `s3` feature,
+    /// multiple inputs are aggregated together
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Descend into the subdirectories of the input directories
+    #[arg(long)]
+    recursive: bool,
+
+    /// Read only the files matching this pattern from the input directories, with `*` and `?` wildcards
+    #[arg(long, value_name = "PATTERN")]
+    glob: Option<String>,
+
+    /// Abort the run after this many seconds and print the partial result
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Output format of the results
+    #[arg(long, value_enum, default_value_t = Format::Brace)]
+    format: Format,
+
+    /// Write a line per station filled from a template instead of the format, like `{station}\t{min}\t{mean}\t{max}\t{count}`,
+    /// with \t, \n and \\ escapes and {{ and }} for the braces
+    #[arg(long, value_name = "TEMPLATE", value_parser = Template::parse, conflicts_with = "format")]
+    template: Option<Template>,
+
+    /// Wrap the JSON output in an object with the unit, the generation time and the collation, the stations are under `stations`
+    #[arg(long)]
+    metadata: bool,
+
+    /// Number of decimals of the min, mean and max, rounded with --round [default: 1, the decimals of --scale]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(0..=9))]
+    precision: Option<u32>,
+
+    /// Widest station name of --format table in characters, longer names are truncated with an ellipsis
+    #[arg(long, value_name = "N", default_value_t = format::DEFAULT_TABLE_STATION_WIDTH, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_station_width: usize,
+
+    /// How the min, mean and max are rounded to the precision
+    #[arg(long, value_enum, default_value_t = RoundMode::HalfUp)]
+    round: RoundMode,
+
+    /// End the NDJSON output with a line with the number of stations and measurements and the overall min, mean and max
+    #[arg(long)]
+    summary: bool,
+
+    /// Also write the min, mean, max and count of all the measurements: a line after the stations of the text formats,
+    /// a `total` field next to the `stations` of JSON and YAML, a row without a station name in CSV
+    #[arg(long)]
+    total: bool,
+
+    /// Print only the first N stations in the order of --collate
+    #[arg(long, value_name = "N", conflicts_with = "tail")]
+    head: Option<usize>,
+
+    /// Print only the last N stations in the order of --collate
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Drop the station with this name from the result, can be repeated
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+
+    /// Drop the stations listed in this file from the result, one name per line
+    #[arg(long, value_name = "PATH")]
+    exclude_stations: Option<PathBuf>,
+
+    /// Only aggregate and write the stations listed in this file, one name per line, the records of the others are
+    /// skipped before their temperatures are parsed. The listed stations without records are written with an N/A
+    /// marker (null in JSON)
+    #[arg(long, value_name = "PATH", conflicts_with = "repl")]
+    only_stations: Option<PathBuf>,
+
+    /// Write the results to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Also write the results in another format to a file, e.g. `--tee json=out.json`, can be repeated
+    #[arg(long, value_name = "FORMAT=PATH", value_parser = parse_tee)]
+    tee: Vec<(Format, PathBuf)>,
+
+    /// Write the result of every station to its own file in this directory, named after the station
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    split_output: Option<PathBuf>,
+
+    /// Also write the number of measurements of every station to this file, the largest first
+    #[arg(long, value_name = "PATH")]
+    counts_output: Option<PathBuf>,
+
+    /// Format of the --counts-output file, one of brace, plain, json, ndjson, yaml, csv or markdown
+    #[arg(long, value_enum, default_value_t = Format::Csv, requires = "counts_output")]
+    counts_format: Format,
+
+    /// Order of the stations in the output, `unicode` ignores the case and the accents
+    #[arg(long, value_enum, default_value_t = CollateMode::Bytes)]
+    collate: CollateMode,
+
+    /// Sort the stations of the output by this statistic in ascending order, the stations with equal values
+    /// in the order of --collate and the stations without records last
+    #[arg(long, value_enum, default_value_t = SortMode::Station)]
+    sort_by: SortMode,
+
+    /// Print the station with the highest max and the station with the lowest min to stderr
+    #[arg(long)]
+    extremes: bool,
+
+    /// Highlight the coldest min and the hottest max in the output
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Compute a histogram of every station with buckets of this width, written by the JSON and CSV formats
+    #[arg(long, value_name = "BUCKET_WIDTH")]
+    histogram: Option<f64>,
+
+    /// Use the branchless temperature parser for well-formed `[-]d[d].d` values
+    #[arg(long)]
+    fast_parse: bool,
+
+    /// Fixed-point scale of the temperatures, a power of ten up to 1000: 100 reads hundredths like `12.34`, 1 whole degrees.
+    /// The temperatures are kept as integers of the scale, which must fit in 16 bits (±327.67 with 100), and printed
+    /// with its decimals unless --precision is given. The branchless parser of --fast-parse only reads tenths
+    #[arg(long, value_name = "FACTOR", default_value_t = 10, value_parser = parse_scale, conflicts_with_all = ["histogram", "metrics", "quality_report"])]
+    scale: u32,
+
+    /// Parse every slice of the parallel read with two cursors, one in each half, advancing in an interleaved loop
+    #[arg(long)]
+    dual_cursor: bool,
+
+    /// Skip a record identical to the line right before it, byte for byte, such as the lines repeated by a producer
+    /// retrying its writes. The same record elsewhere in the input is aggregated again
+    #[arg(long, conflicts_with_all = ["sample_rate", "metrics"])]
+    dedup_consecutive: bool,
+
+    /// Look up the station of every record, without the shortcut for the records of the same station as the previous one
+    #[arg(long)]
+    no_station_cache: bool,
+
+    /// Skip records with an invalid temperature or station name instead of failing
+    #[arg(long)]
+    lenient: bool,
+
+    /// Fail at the first invalid record, the default
+    #[arg(long, conflicts_with_all = ["lenient", "collect_errors"])]
+    fail_fast: bool,
+
+    /// Skip the invalid records and print them to stderr after the run, which then exits with 1
+    #[arg(long, conflicts_with_all = ["lenient", "repl", "count_only", "quality_report"])]
+    collect_errors: bool,
+
+    /// Store the results in this directory and reuse them while the inputs are unchanged (same path, size,
+    /// modification time and first and last megabyte), only the output is written again
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["repl", "dry_run", "count_only", "compare_methods", "follow", "sample_rate", "collect_errors"])]
+    cache_dir: Option<PathBuf>,
+
+    /// Neither read nor write the results of --cache-dir
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Number of the invalid records --collect-errors prints, the others are only counted
+    #[arg(long, value_name = "N", default_value_t = 100, requires = "collect_errors")]
+    max_errors: usize,
+
+    /// Normalize the station names to Unicode NFC, so that different forms of the same name are grouped together
+    #[arg(long)]
+    normalize: bool,
+
+    /// Merge the stations whose names are equal after these steps, e.g. `--normalize-keys trim,casefold`,
+    /// the output uses the normalized names
+    #[arg(long, value_enum, value_name = "STEPS", value_delimiter = ',')]
+    normalize_keys: Vec<KeyStep>,
+
+    /// Also run the parallel implementation aggregating into a flat array indexed by dense station IDs
+    #[arg(long)]
+    dense_ids: bool,
+
+    /// Also run the parallel implementation updating a single map of stations shared by all the workers,
+    /// sharded by locks, instead of merging a map per job
+    #[arg(long)]
+    shared_map: bool,
+
+    /// Only count the lines in parallel, as a baseline of the I/O throughput
+    #[arg(long, conflicts_with = "dry_run")]
+    count_only: bool,
+
+    /// Print the work done by every rayon worker of the parallel read and the skew between them
+    #[arg(long)]
+    worker_stats: bool,
+
+    /// Print the load factor, the grows, the hasher invocations and the most crowded buckets of the hash tables
+    /// of the stations of every implementation to stderr, measured by inserting the final keys again
+    #[arg(long)]
+    hash_stats: bool,
+
+    /// Pin every worker thread of the parallel read to its own core, for more reproducible timings
+    #[arg(long)]
+    pin_threads: bool,
+
+    /// Size of the slices the parallel implementations split the files into, in bytes with an optional K, M or G suffix
+    #[arg(long, value_name = "SIZE", default_value = "64K", value_parser = parse_size)]
+    chunk_size: usize,
+
+    /// Read the whole files into memory instead of memory mapping them in the parallel implementations
+    #[arg(long)]
+    no_mmap: bool,
+
+    /// Fault in the pages of the mapped file from a background thread this far ahead of the parsers of the parallel
+    /// mmap read of a single file, in bytes with an optional K, M or G suffix
+    #[arg(long, value_name = "SIZE", num_args = 0..=1, default_missing_value = "256M", value_parser = parse_size, conflicts_with = "no_mmap")]
+    readahead: Option<usize>,
+
+    /// Pick the fastest chunk size with a few timed passes over a prefix of the input before the run
+    #[arg(long, conflicts_with = "chunk_size")]
+    auto_tune: bool,
+
+    /// Print a snapshot of the stations merged so far every SECONDS during the parallel read
+    #[arg(long, value_name = "SECONDS")]
+    stream_every: Option<u64>,
+
+    /// 0-based index of the station among the `;` separated fields of a record
+    #[arg(long, value_name = "INDEX", default_value_t = 0)]
+    station_col: usize,
+
+    /// 0-based index of the temperature among the `;` separated fields of a record
+    #[arg(long, value_name = "INDEX", default_value_t = 1)]
+    temp_col: usize,
+
+    /// Ignore the fields after the temperature (`Paris;12.3;sensor7`), the station is the first field and the temperature
+    /// the second one instead of the station name ending at the last delimiter
+    #[arg(long)]
+    ignore_trailing_fields: bool,
+
+    /// Names of the numeric columns after the station, `station;temperature;humidity;pressure` records are aggregated
+    /// into the min/mean/max of every column with `--metrics temperature,humidity,pressure`. The values are parsed like
+    /// the temperatures, the records with another number of columns are invalid
+    #[arg(long, value_name = "NAMES", value_delimiter = ',',
+          conflicts_with_all = ["histogram", "checked_sum", "track_extremes", "track_first_last", "ignore_trailing_fields", "normalize", "normalize_keys",
+                                "follow", "repl", "dry_run", "compare_methods", "compare", "sample_rate", "stream_every", "dense_ids", "shared_map", "checkpoint", "resume",
+                                "cache_dir", "quality_report", "template", "tee", "split_output", "counts_output", "total", "metadata", "summary", "extremes"])]
+    metrics: Vec<String>,
+
+    /// Aggregate only a random sample of about this fraction of the bytes, the counts are extrapolated and the result is approximate,
+    /// the min and the max are those of the sampled records and tend to understate the range
+    #[arg(long, value_name = "RATE", value_parser = parse_rate, conflicts_with_all = ["histogram", "checked_sum", "no_mmap", "dense_ids", "shared_map", "stream_every", "checkpoint", "resume"])]
+    sample_rate: Option<f64>,
+
+    /// Seed of the random probe points of --sample-rate, the same seed samples the same records
+    #[arg(long, alias = "seed", value_name = "SEED", default_value_t = 0, requires = "sample_rate")]
+    sample_seed: u64,
+
+    /// Check that the simple and the parallel read produce the same statistics for every station, instead of printing the result
+    #[arg(long, conflicts_with_all = ["count_only", "dry_run", "sample_rate", "checkpoint", "resume"])]
+    compare_methods: bool,
+
+    /// Run every implementation, check that their results agree and print a table of their (median) durations,
+    /// throughputs and speedups, the streaming read is included with --stream-every
+    #[arg(long, conflicts_with_all = ["count_only", "dry_run", "sample_rate", "repl", "compare_methods"])]
+    compare: bool,
+
+    /// Format of the table of --compare
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ReportFormat::Text, requires = "compare")]
+    compare_format: ReportFormat,
+
+    /// Aggregate the files once and answer queries like `get Paris` or `top 5 max` read from stdin
+    #[arg(long, conflicts_with_all = ["count_only", "dry_run"])]
+    repl: bool,
+
+    /// Only parse and validate the records without aggregating them, to measure the parsing throughput
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Also count the valid, blank and malformed lines of the inputs in a pass of their own
+    /// and print the counts with the share of valid records at the end
+    #[arg(long, conflicts_with_all = ["repl", "count_only", "dry_run", "compare_methods"])]
+    quality_report: bool,
+
+    /// Fail instead of silently wrapping around when the sum or the count of a station overflows
+    #[arg(long, conflicts_with = "histogram")]
+    checked_sum: bool,
+
+    /// Record the byte offsets of the records that set the min and the max of every station,
+    /// written as min_offset and max_offset by the JSON, NDJSON, YAML and CSV formats
+    #[arg(long, conflicts_with_all = ["histogram", "checked_sum", "sample_rate", "resume", "repl"])]
+    track_extremes: bool,
+
+    /// Record the first and the last temperature of every station in the order of the file,
+    /// written as first and last by the JSON, NDJSON, YAML and CSV formats
+    #[arg(long, conflicts_with_all = ["histogram", "checked_sum", "track_extremes", "sample_rate", "resume", "repl"])]
+    track_first_last: bool,
+
+    /// Report hardware performance counters of each implementation (Linux, `perf-counters` feature)
+    #[arg(long)]
+    perf_counters: bool,
+
+    /// Buffer size of the simple file read, in bytes with an optional K, M or G suffix
+    #[arg(long, value_name = "SIZE", default_value = "1M", value_parser = parse_size)]
+    read_buffer: usize,
+
+    /// Retry a read of the simple file read failing with a transient error like EIO up to this many times in a row,
+    /// with a backoff doubling from 100ms, before giving up. The memory mapped reads are not retried
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retry: u32,
+
+    /// Run every implementation this many times and print the median, min and max duration of each
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+
+    /// Evict the inputs from the page cache before every implementation of the first run (Linux, best effort),
+    /// the following runs of --repeat are warm and reported separately
+    #[arg(long)]
+    cold: bool,
+
+    /// Save the durations of the implementations to this file, to compare later runs with --compare-baseline
+    #[arg(long, value_name = "PATH")]
+    save_baseline: Option<PathBuf>,
+
+    /// Compare the median durations with a baseline saved by --save-baseline, exits with 1 on a regression
+    #[arg(long, value_name = "PATH")]
+    compare_baseline: Option<PathBuf>,
+
+    /// Slowdown of the median duration in percent above which --compare-baseline reports a regression
+    #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+    max_regression: f64,
+
+    /// Compare with a baseline taken on a different input or thread count
+    #[arg(long)]
+    force: bool,
+
+    /// Print the configuration of the run to stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Decompress all the inputs as bzip2, files with the `.bz2` extension are decompressed without it
+    #[arg(long)]
+    bzip2: bool,
+
+    /// Character encoding of the inputs, transcoded to UTF-8 before they are parsed.
+    /// A byte order mark at the start of an input overrides it
+    #[arg(long, value_enum, default_value_t = InputEncoding::Utf8)]
+    encoding: InputEncoding,
+
+    /// Periodically save the progress of the simple file read to this file
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<PathBuf>,
+
+    /// How often to write the checkpoint, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    checkpoint_interval: u64,
+
+    /// Continue the simple file read from a checkpoint written by `--checkpoint`
+    #[arg(long, value_name = "PATH")]
+    resume: Option<PathBuf>,
+
+    /// Aggregate the file, then keep reading the records appended to it and print the whole result every --interval,
+    /// until Ctrl-C. The aggregation restarts if the file shrinks
+    #[arg(long, conflicts_with_all = ["repl", "count_only", "dry_run", "compare_methods", "compare", "sample_rate", "stream_every", "checkpoint", "resume", "bzip2", "encoding", "quality_report"])]
+    follow: bool,
+
+    /// How often --follow reads the appended records and prints the result, like `10s` or `1m`
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = humantime::parse_duration, requires = "follow")]
+    interval: Duration,
+
+    /// Serve the result over HTTP on this address until Ctrl-C, with the `http` feature: GET /stations returns the JSON
+    /// of all the stations, GET /stations/{name} the statistics of one, and GET /healthz the liveness.
+    /// With --follow, the continuously updated result is served
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["repl", "count_only", "dry_run"])]
+    serve: Option<std::net::SocketAddr>,
+
+    /// Aggregate only the records of the file from this byte offset on, with an optional K, M or G suffix. The offsets are
+    /// snapped forward to the next record like the slices of the parallel read: a record belongs to the range with the
+    /// newline before it, so the adjacent ranges `0..S` and `S..E` of separate processes have every record exactly once
+    #[arg(long, value_name = "OFFSET", value_parser = parse_offset,
+          conflicts_with_all = ["follow", "repl", "count_only", "dry_run", "compare_methods", "compare", "sample_rate", "stream_every", "dense_ids", "shared_map",
+                                "checkpoint", "resume", "cache_dir", "metrics", "track_extremes", "track_first_last", "quality_report", "bzip2", "encoding"])]
+    byte_start: Option<usize>,
+
+    /// Aggregate only the records of the file before this byte offset, snapped forward like --byte-start
+    #[arg(long, value_name = "OFFSET", value_parser = parse_offset,
+          conflicts_with_all = ["follow", "repl", "count_only", "dry_run", "compare_methods", "compare", "sample_rate", "stream_every", "dense_ids", "shared_map",
+                                "checkpoint", "resume", "cache_dir", "metrics", "track_extremes", "track_first_last", "quality_report", "bzip2", "encoding"])]
+    byte_end: Option<usize>,
+
+    /// Read the station fields as integer IDs named by this `id,name` CSV file at the output, e.g. `4217;12.3` with
+    /// `4217,Oslo`. The IDs are cheaper keys than the names, a record whose station is not an ID is invalid, and an ID
+    /// missing in the file is an error, or written as its number with --lenient
+    #[arg(long, value_name = "PATH",
+          conflicts_with_all = ["follow", "repl", "dry_run", "compare_methods", "compare", "sample_rate", "stream_every", "dense_ids", "shared_map",
+                                "checkpoint", "resume", "cache_dir", "metrics", "normalize", "normalize_keys", "byte_start", "byte_end", "bzip2"])]
+    id_map: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare two saved results in the brace, plain or JSON format, exits with 1 if they differ
+    Diff(DiffArgs),
+    /// Generate a measurements file
+    Generate(GenerateArgs),
+    /// Serve `POST /aggregate` over HTTP, answering the measurements of the request body with their JSON statistics
+    #[cfg(feature = "http")]
+    Serve(ServeArgs),
+}
+
+#[cfg(feature = "http")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: std::net::IpAddr,
+
+    /// Largest accepted request body, in bytes with an optional K, M or G suffix
+    #[arg(long, value_name = "SIZE", default_value = "64M", value_parser = parse_size)]
+    max_body: usize,
+}
+
+#[derive(clap::Args)]
+struct GenerateArgs {
+    /// Path of the measurements file to write
+    output: PathBuf,
+
+    /// Number of measurements
+    #[arg(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Number of distinct stations, the built-in station names are numbered if more are requested
+    #[arg(long, default_value_t = generate::BUILTIN_STATIONS)]
+    stations: usize,
+
+    /// Seed of the random generator, the same seed always produces the same files
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// How often each station appears: uniform, or zipf:<s> for the station of rank k appearing in proportion to 1/k^s
+    #[arg(long, value_name = "DIST", default_value = "uniform", value_parser = generate::Distribution::parse)]
+    distribution: generate::Distribution,
+
+    /// Standard deviation of the temperatures of a station around its mean, in degrees
+    #[arg(long, value_name = "V", default_value_t = generate::DEFAULT_STDDEV)]
+    stddev: f64,
+
+    /// Write the measurements sorted by station, the file is generated in memory
+    #[arg(long)]
+    sorted: bool,
+
+    /// Also write the exact expected results in the brace format to this file
+    #[arg(long, value_name = "PATH")]
+    expected: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    old: PathBuf,
+    new: PathBuf,
+
+    /// Ignore differences of the statistics up to this value
+    #[arg(long, default_value_t = 0.0)]
+    tolerance: f64,
+
+    /// Output format of the differences
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// `{station=min/mean/max, ...}` as in the challenge
+    #[value(alias = "text")]
+    Brace,
+    /// One `station=min/mean/max` line per station
+    Plain,
+    Json,
+    /// One JSON object per station and line, written incrementally
+    Ndjson,
+    /// YAML mapping structured like the JSON output
+    Yaml,
+    Csv,
+    Markdown,
+    /// Columns aligned with spaces for terminals, see --max-station-width
+    Table,
+    /// Self-contained HTML report with a sortable table
+    Html,
+    /// Prometheus text exposition format
+    Prometheus,
+    /// JSON array of `[station, min, mean, max]` arrays in the order of --sort-by
+    Array,
+    /// Apache Parquet file, use with --output
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Rows appended to the `runs` and `station_stats` tables of a SQLite database, use with --output
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// The --template line of every station
+    #[value(skip)]
+    Template,
+}
+
+impl Format {
+    // of the files written with --split-output
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Brace | Format::Plain | Format::Table | Format::Template => "txt",
+            Format::Json | Format::Array => "json",
+            Format::Ndjson => "ndjson",
+            Format::Yaml => "yaml",
+            Format::Csv => "csv",
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Prometheus => "prom",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "parquet",
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => "db",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RoundMode {
+    /// Halves toward positive infinity, as in the reference implementation
+    HalfUp,
+    /// Halves to the even neighbour
+    HalfEven,
+    /// Toward zero
+    Truncate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CollateMode {
+    /// Byte order of the names, as in the reference output
+    Bytes,
+    /// Case-insensitive order of the names without their accents
+    Unicode,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortMode {
+    /// The order of --collate
+    Station,
+    Min,
+    Mean,
+    Max,
+    Count,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum KeyStep {
+    /// Remove the leading and the trailing ASCII whitespace
+    Trim,
+    /// Convert to lowercase
+    Casefold,
+    /// Convert to the Unicode normalization form C, like --normalize
+    Nfc,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputEncoding {
+    #[value(name = "utf8")]
+    Utf8,
+    /// ISO-8859-1
+    Latin1,
+    /// Little-endian without a byte order mark
+    #[value(name = "utf16")]
+    Utf16,
+}
+
+// the histograms are refused if they could take more than this for the maximum number of stations
+const MAX_HISTOGRAM_BYTES: usize = 4 << 30;
+const MAX_STATIONS: usize = 10_000;
+
+const EXIT_DIFFERENT: i32 = 1;
+const EXIT_REGRESSION: i32 = 1;
+const EXIT_INVALID: i32 = 1;
+const EXIT_TIMEOUT: i32 = 124;
+const EXIT_INTERRUPTED: i32 = 130;
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Diff(d)) => return diff_results(d),
+        Some(Command::Generate(g)) => return generate_file(g),
+        #[cfg(feature = "http")]
+        Some(Command::Serve(s)) => return serve(s),
+        None => {}
+    }
+
+    match args.color {
+        ColorMode::Auto => anstream::ColorChoice::Auto,
+        ColorMode::Always => anstream::ColorChoice::Always,
+        ColorMode::Never => anstream::ColorChoice::Never,
+    }.write_global();
+
+    if args.pin_threads {
+        pin_threads();
+    }
+
+    let cancel = Arc::new(Cancel::default());
+    if let Some(timeout) = args.timeout {
+        let cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(timeout));
+            cancel.cancel(CancelReason::Timeout);
+        });
+    }
+    {
+        let cancel = Arc::clone(&cancel);
+        // the first Ctrl-C lets the workers drain and prints the partial result, the second one exits immediately
+        ctrlc::set_handler(move || {
+            if !cancel.cancel(CancelReason::Interrupted) {
+                process::exit(EXIT_INTERRUPTED);
+            }
+        }).expect("Failed to install the Ctrl-C handler");
+    }
+
+    if args.station_col == args.temp_col {
+        return Err(Error::new(ErrorKind::InvalidInput, "--station-col and --temp-col must select different fields"));
+    }
+    // a single metric is the temperature of the usual records
+    let metrics = (args.metrics.len() > 1).then_some(args.metrics.as_slice());
+    if let Some(names) = metrics {
+        if names.iter().any(String::is_empty) || names.iter().collect::<HashSet<_>>().len() < names.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "The names of --metrics must be distinct and not empty"));
+        }
+        if args.station_col != 0 || args.temp_col != 1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "--metrics reads the station from the first field, it cannot be combined with --station-col and --temp-col"));
+        }
+        if !matches!(args.format, Format::Brace | Format::Plain | Format::Json | Format::Csv) {
+            return Err(Error::new(ErrorKind::InvalidInput, "--metrics supports the brace, plain, json and csv formats"));
+        }
+    }
+    let keys = KeyNormalization {
+        trim: args.normalize_keys.contains(&KeyStep::Trim),
+        case_fold: args.normalize_keys.contains(&KeyStep::Casefold),
+        nfc: args.normalize || args.normalize_keys.contains(&KeyStep::Nfc),
+    };
+    let normalize = (keys != KeyNormalization::default()).then_some(keys);
+    let exclude = station_names(&args.exclude, args.exclude_stations.as_deref(), normalize)?;
+    // shared with the parsers through the options like the log
+    let only: Option<&'static HashSet<String>> = args.only_stations.as_deref()
+        .map(|path| station_names(&[], Some(path), normalize).map(|names| &*Box::leak(Box::new(names))))
+        .transpose()?;
+    let columns = Columns { station: args.station_col, temp: args.temp_col };
+    // the log outlives the runs, which share it through the options
+    let errors: Option<&'static ErrorLog> = args.collect_errors.then(|| &*Box::leak(Box::new(ErrorLog::new(args.max_errors))));
+    let scale = Scale::new(args.scale).expect("a scale checked by the parser");
+    let options = ParseOptions {
+        fast_parse: args.fast_parse,
+        dual_cursor: args.dual_cursor,
+        no_station_cache: args.no_station_cache,
+        lenient: args.lenient,
+        // the default layout keeps the parser that allows the delimiter in the station names
+        columns: (args.ignore_trailing_fields || columns != Columns { station: 0, temp: 1 }).then_some(columns),
+        errors,
+        scale,
+        dedup_consecutive: args.dedup_consecutive,
+        // the normalized names and the names of the station IDs are not those of the records
+        only: only.filter(|_| normalize.is_none() && args.id_map.is_none()),
+    };
+    if args.verbose {
+        eprintln!("Read buffer: {} bytes", args.read_buffer);
+        eprintln!("Threads: {}", rayon::current_num_threads());
+    }
+
+    let input = args.paths.iter().map(|p| p.display().to_string()).collect::<Vec<String>>().join(", ");
+    let paths = expand_inputs(&args.paths, args.recursive, args.glob.as_deref())?;
+    if args.verbose {
+        eprintln!("Input files: {}", paths.len());
+    }
+
+    if paths.len() > 1 && (args.checkpoint.is_some() || args.resume.is_some()) {
+        return Err(Error::new(ErrorKind::InvalidInput, "--checkpoint and --resume support a single input file only"));
+    }
+    if paths.len() > 1 && args.track_extremes {
+        return Err(Error::new(ErrorKind::InvalidInput, "--track-extremes supports a single input file only"));
+    }
+    // the offsets of the records are only ordered within a file
+    if paths.len() > 1 && args.track_first_last {
+        return Err(Error::new(ErrorKind::InvalidInput, "--track-first-last supports a single input file only"));
+    }
+    if paths.iter().any(|p| is_s3(p)) {
+        if !cfg!(feature = "s3") {
+            return Err(Error::new(ErrorKind::InvalidInput, "s3:// inputs need the s3 feature"));
+        }
+        let unsupported = [("--checkpoint", args.checkpoint.is_some()), ("--resume", args.resume.is_some()), ("--sample-rate", args.sample_rate.is_some())];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not support S3 input", flag)));
+        }
+    }
+    if paths.iter().any(|p| is_stdin(p)) {
+        // the standard input can be read only once, by the simple file read
+        let unsupported = [("--checkpoint", args.checkpoint.is_some()), ("--resume", args.resume.is_some()), ("--count-only", args.count_only),
+                           ("--repl", args.repl), ("--sample-rate", args.sample_rate.is_some()), ("--follow", args.follow),
+                           ("--auto-tune", args.auto_tune), ("--repeat", args.repeat > 1)];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not support the standard input", flag)));
+        }
+        if paths.iter().filter(|p| is_stdin(p)).count() > 1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "the standard input - can be given only once"));
+        }
+    }
+    if paths.iter().any(|p| is_bzip2(p, args.bzip2)) {
+        // only the simple file read decompresses the input
+        let unsupported = [("--checkpoint", args.checkpoint.is_some()), ("--resume", args.resume.is_some()), ("--count-only", args.count_only),
+                           ("--repl", args.repl), ("--sample-rate", args.sample_rate.is_some())];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not support bzip2 compressed input", flag)));
+        }
+    }
+    let byte_range = args.byte_start.is_some() || args.byte_end.is_some();
+    if byte_range {
+        // the offsets are into a single local file as it is on the disk
+        match paths.as_slice() {
+            [path] if !is_stdin(path) && !is_s3(path) && !is_bzip2(path, false) => {}
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "--byte-start and --byte-end support a single uncompressed local file only")),
+        }
+    }
+    if args.id_map.is_some() && paths.iter().any(|p| is_stdin(p) || is_s3(p) || is_bzip2(p, false)) {
+        // the records are parsed by the parallel read only
+        return Err(Error::new(ErrorKind::InvalidInput, "--id-map supports uncompressed local files only"));
+    }
+    let encoding = match args.encoding {
+        InputEncoding::Utf8 => Encoding::Utf8,
+        InputEncoding::Latin1 => Encoding::Latin1,
+        InputEncoding::Utf16 => Encoding::Utf16Le,
+    };
+    if encoding != Encoding::Utf8 {
+        // the checkpoints hold offsets into the file, not into the transcoded input
+        let unsupported = [("--checkpoint", args.checkpoint.is_some()), ("--resume", args.resume.is_some())];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not support --encoding", flag)));
+        }
+    }
+
+    let config = SimpleReadConfig {
+        read_buffer: args.read_buffer,
+        checkpoint: args.checkpoint,
+        resume: args.resume,
+        interval: Duration::from_secs(args.checkpoint_interval),
+        bzip2: args.bzip2,
+        encoding,
+        retry: args.retry,
+        verbose: args.verbose,
+    };
+
+    let read = ReadOptions { slice_size: args.chunk_size, no_mmap: args.no_mmap, encoding };
+    if args.dry_run {
+        if dry_run(&paths, options, &config, read, &cancel)? {
+            process::exit(EXIT_INVALID);
+        }
+        return Ok(());
+    }
+    if args.count_only {
+        return count_only(&paths, read, &cancel);
+    }
+
+    // started before the aggregation, so that the liveness is served while it runs
+    let server = match args.serve {
+        #[cfg(feature = "http")]
+        Some(addr) => Some(serve_results(std::net::TcpListener::bind(addr)?, &cancel)?),
+        #[cfg(not(feature = "http"))]
+        Some(_) => return Err(Error::new(ErrorKind::InvalidInput, "--serve needs the http feature")),
+        None => None,
+    };
+
+    let histogram = args.histogram.map(histogram).transpose()?;
+    let cold = args.cold && cfg!(target_os = "linux");
+    if args.cold && !cold {
+        eprintln!("Warning: --cold is not supported on this platform, the runs use the page cache as it is");
+    }
+    let output = Output {
+        format: if args.template.is_some() { Format::Template } else { args.format },
+        template: args.template,
+        errors,
+        invalid: AtomicBool::new(false),
+        histogram,
+        path: args.output,
+        split_output: args.split_output,
+        tee: args.tee,
+        counts_output: args.counts_output.map(|path| counts_format(args.counts_format).map(|format| (format, path))).transpose()?,
+        exclude,
+        only,
+        input,
+        perf_counters: args.perf_counters,
+        normalize,
+        head: args.head,
+        tail: args.tail,
+        dense_ids: args.dense_ids || args.compare,
+        shared_map: args.shared_map || args.compare,
+        worker_stats: args.worker_stats,
+        hash_stats: args.hash_stats,
+        stream_every: args.stream_every.map(Duration::from_secs),
+        extremes: args.extremes,
+        metadata: args.metadata,
+        summary: args.summary,
+        total: args.total,
+        precision: args.precision.map_or(scale.decimals() as usize, |p| p as usize),
+        scale,
+        max_station_width: args.max_station_width,
+        rounding: match args.round {
+            RoundMode::HalfUp => Rounding::HalfUp,
+            RoundMode::HalfEven => Rounding::HalfEven,
+            RoundMode::Truncate => Rounding::Truncate,
+        },
+        timings: Mutex::new(Vec::new()),
+        results: args.compare.then(|| Mutex::new(Vec::new())),
+        cold: cold.then(|| AtomicBool::new(true)),
+        served: server.as_ref().map(|(served, _)| Arc::clone(served)),
+        byte_range: byte_range.then(|| (args.byte_start.unwrap_or(0), args.byte_end.unwrap_or(usize::MAX))),
+        id_map: args.id_map.as_deref().map(ids::read_id_map).transpose()?,
+        collation: match args.collate {
+            CollateMode::Bytes => Collation::Bytes,
+            CollateMode::Unicode => Collation::Unicode,
+        },
+        sort_by: match args.sort_by {
+            SortMode::Station => SortKey::Station,
+            SortMode::Min => SortKey::Min,
+            SortMode::Mean => SortKey::Mean,
+            SortMode::Max => SortKey::Max,
+            SortMode::Count => SortKey::Count,
+        },
+        read: match args.auto_tune {
+            true => ReadOptions { slice_size: auto_tune(&paths, read, options, &cancel)?, ..read },
+            false => read,
+        },
+        readahead: args.readahead,
+        cache: args.cache_dir.filter(|_| !args.no_cache).map(|dir| {
+            // the options that change the aggregated statistics, not only how they are written
+            let config = format!("histogram={:?} checked_sum={} track_extremes={} track_first_last={} {} normalize={:?} encoding={:?} bzip2={}",
+                                 args.histogram, args.checked_sum, args.track_extremes, args.track_first_last, CacheKey::parse_config(options), normalize, encoding, args.bzip2);
+            (ResultsCache::new(dir), config)
+        }),
+    };
+
+    if args.repl {
+        return match args.checked_sum {
+            true => repl(&paths, &CheckedMinMeanMax, options, &output, &cancel),
+            false => repl(&paths, &MinMeanMax, options, &output, &cancel),
+        };
+    }
+
+    if args.follow {
+        let [path] = paths.as_slice() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "--follow needs a single file"));
+        };
+        match &output.histogram {
+            Some(h) => follow(path, h, options, &config, args.interval, &output, &cancel)?,
+            None if args.checked_sum => follow(path, &CheckedMinMeanMax, options, &config, args.interval, &output, &cancel)?,
+            None if args.track_extremes => follow(path, &TrackExtremes, options, &config, args.interval, &output, &cancel)?,
+            None if args.track_first_last => follow(path, &TrackFirstLast, options, &config, args.interval, &output, &cancel)?,
+            None => follow(path, &MinMeanMax, options, &config, args.interval, &output, &cancel)?,
+        }
+        // stopped by the same Ctrl-C as the follow
+        return wait_for_server(server);
+    }
+
+    if args.compare_methods {
+        return match &output.histogram {
+            Some(h) => compare_methods(&paths, h, options, &config, &output, &cancel),
+            None if args.checked_sum => compare_methods(&paths, &CheckedMinMeanMax, options, &config, &output, &cancel),
+            None if args.track_extremes => compare_methods(&paths, &TrackExtremes, options, &config, &output, &cancel),
+            None if args.track_first_last => compare_methods(&paths, &TrackFirstLast, options, &config, &output, &cancel),
+            None => compare_methods(&paths, &MinMeanMax, options, &config, &output, &cancel),
+        };
+    }
+
+    for i in 0..args.repeat {
+        // only the first iteration is cold, the following ones show the warm timings
+        if let Some(cold) = &output.cold {
+            cold.store(i == 0, Ordering::Relaxed);
+        }
+        if let Some(rate) = args.sample_rate {
+            sampled_read(&paths, rate, args.sample_seed, options, &output)?;
+            continue;
+        }
+        if let Some(names) = metrics {
+            metrics_read(&paths, names, options, &output, &cancel)?;
+            continue;
+        }
+        match &output.histogram {
+            Some(h) => run(&paths, h, options, &config, &output, &cancel)?,
+            None if args.checked_sum => run(&paths, &CheckedMinMeanMax, options, &config, &output, &cancel)?,
+            None if args.track_extremes => run(&paths, &TrackExtremes, options, &config, &output, &cancel)?,
+            None if args.track_first_last => run(&paths, &TrackFirstLast, options, &config, &output, &cancel)?,
+            None => run(&paths, &MinMeanMax, options, &config, &output, &cancel)?,
+        }
+    }
+
+    let quality = args.quality_report.then(|| quality_report(&paths, options.columns, &config, output.read, &cancel)).transpose()?;
+
+    let baseline = Baseline::new(&output, output.timings.lock().unwrap().as_slice());
+    if args.repeat > 1 {
+        for m in &baseline.modes {
+            println!("Repeat {}: median {:?}, min {:?}, max {:?} over {} runs", m.name, m.median(), m.min(), m.max(), m.durations.len());
+        }
+    }
+    if let Some(results) = &output.results {
+        check_results_agree(&results.lock().unwrap())?;
+        let rows = comparison(&baseline);
+        match args.compare_format {
+            ReportFormat::Text => print_comparison(&rows),
+            ReportFormat::Json => println!("{}", serde_json::to_string(&rows)?),
+        }
+    }
+    if let Some(report) = quality {
+        print!("{}", report);
+    }
+    if let Some(path) = &args.save_baseline {
+        fs::write(path, serde_json::to_string_pretty(&baseline)?)?;
+    }
+    if let Some(path) = &args.compare_baseline {
+        let saved: Baseline = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if !compare_baselines(&saved, &baseline, args.max_regression, args.force)? {
+            process::exit(EXIT_REGRESSION);
+        }
+    }
+    if server.is_some() {
+        eprintln!("Serving the result until Ctrl-C");
+    }
+    wait_for_server(server)?;
+    if output.invalid.load(Ordering::Relaxed) {
+        process::exit(EXIT_INVALID);
+    }
+    Ok(())
+}
+
+/// Timings of the implementations, saved with `--save-baseline`.
+#[derive(Serialize, Deserialize)]
+struct Baseline {
+    input: String,
+    bytes: usize,
+    threads: usize,
+    os: String,
+    arch: String,
+    modes: Vec<ModeTimings>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModeTimings {
+    name: String,
+    /// Durations of the runs in seconds
+    durations: Vec<f64>,
+    /// Throughput of the median run, in bytes per second
+    throughput: f64,
+}
+
+impl ModeTimings {
+    fn median(&self) -> Duration {
+        let mut durations = self.durations.clone();
+        durations.sort_by(f64::total_cmp);
+        Duration::from_secs_f64(durations[durations.len() / 2])
+    }
+
+    fn min(&self) -> Duration {
+        Duration::from_secs_f64(self.durations.iter().copied().reduce(f64::min).unwrap_or_default())
+    }
+
+    fn max(&self) -> Duration {
+        Duration::from_secs_f64(self.durations.iter().copied().reduce(f64::max).unwrap_or_default())
+    }
+}
+
+impl Baseline {
+    // groups the timings by implementation, in the order of the runs
+    // the cold runs are a separate mode
+    fn new(output: &Output, timings: &[(&'static str, bool, Duration, usize)]) -> Baseline {
+        let mut modes: Vec<ModeTimings> = Vec::new();
+        for &(name, cold, duration, _) in timings {
+            let name = if cold { format!("{} (cold)", name) } else { name.to_owned() };
+            match modes.iter_mut().find(|m| m.name == name) {
+                Some(m) => m.durations.push(duration.as_secs_f64()),
+                None => modes.push(ModeTimings { name, durations: vec![duration.as_secs_f64()], throughput: 0.0 }),
+            }
+        }
+        let bytes = timings.first().map_or(0, |&(_, _, _, bytes)| bytes);
+        for m in &mut modes {
+            m.throughput = bytes as f64 / m.median().as_secs_f64();
+        }
+        Baseline {
+            input: output.input.clone(),
+            bytes,
+            threads: rayon::current_num_threads(),
+            os: std::env::consts::OS.to_owned(),
+            arch: std::env::consts::ARCH.to_owned(),
+            modes,
+        }
+    }
+}
+
+/// A row of the table of `--compare`.
+#[derive(Serialize)]
+struct Comparison<'a> {
+    implementation: &'a str,
+    /// Median duration in seconds
+    duration: f64,
+    /// In bytes per second
+    throughput: f64,
+    speedup_vs_slowest: f64,
+    speedup_vs_simple: f64,
+}
+
+// the speedups of the implementations of the baseline, the simple file read is the reference if it was run warm
+fn comparison(baseline: &Baseline) -> Vec<Comparison<'_>> {
+    let median = |m: &ModeTimings| m.median().as_secs_f64();
+    let slowest = baseline.modes.iter().map(median).reduce(f64::max).unwrap_or_default();
+    let simple = baseline.modes.iter().find(|m| m.name == "simple file read").or(baseline.modes.first()).map_or(0.0, median);
+    baseline.modes.iter()
+        .map(|m| Comparison {
+            implementation: &m.name,
+            duration: median(m),
+            throughput: m.throughput,
+            speedup_vs_slowest: slowest / median(m),
+            speedup_vs_simple: simple / median(m),
+        })
+        .collect()
+}
+
+fn print_comparison(rows: &[Comparison]) {
+    let width = rows.iter().map(|r| r.implementation.chars().count()).max().unwrap_or(0).max("Implementation".len());
+    println!("{:width$}  {:>12}  {:>12}  {:>10}  {:>10}", "Implementation", "Duration", "Throughput", "vs slowest", "vs simple");
+    for r in rows {
+        println!("{:width$}  {:>12}  {:>7.1} MB/s  {:>9.2}x  {:>9.2}x", r.implementation, format!("{:.2?}", Duration::from_secs_f64(r.duration)),
+                 r.throughput / 1e6, r.speedup_vs_slowest, r.speedup_vs_simple);
+    }
+}
+
+// fails if the result of any run differs from the result of the first one
+fn check_results_agree(results: &[(&'static str, u64)]) -> Result<(), Error> {
+    let Some(&(first, fingerprint)) = results.first() else {
+        return Ok(());
+    };
+    match results.iter().find(|&&(_, f)| f != fingerprint) {
+        Some((name, _)) => Err(Error::new(ErrorKind::InvalidData, format!("The result of the {} differs from the result of the {}", name, first))),
+        None => Ok(()),
+    }
+}
+
+// prints the change of the median of every implementation, returns false if any of them regressed by more than `max_regression` percent
+fn compare_baselines(saved: &Baseline, current: &Baseline, max_regression: f64, force: bool) -> Result<bool, Error> {
+    let fingerprint = |b: &Baseline| format!("input {} of {} bytes, {} threads", b.input, b.bytes, b.threads);
+    if (saved.input != current.input || saved.bytes != current.bytes || saved.threads != current.threads) && !force {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("The baseline was taken on {}, this run on {}, use --force to compare anyway", fingerprint(saved), fingerprint(current))));
+    }
+    let mut passed = true;
+    for m in &current.modes {
+        let Some(base) = saved.modes.iter().find(|b| b.name == m.name) else {
+            println!("Baseline {}: not in the baseline", m.name);
+            continue;
+        };
+        let change = (m.median().as_secs_f64() / base.median().as_secs_f64() - 1.0) * 100.0;
+        let regressed = change > max_regression;
+        println!("Baseline {}: {:?} -> {:?} ({:+.1}%){}", m.name, base.median(), m.median(), change, if regressed { " REGRESSION" } else { "" });
+        passed &= !regressed;
+    }
+    Ok(passed)
+}
+
+const TUNED_SLICE_SIZES: [usize; 6] = [16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20, 16 << 20];
+// every tuning pass reads at most this share of the input and at most `MAX_TUNING_BYTES`,
+// the tuning is skipped if that leaves less than `MIN_TUNING_BYTES`
+const TUNING_SHARE: usize = 32;
+const MAX_TUNING_BYTES: usize = 64 << 20;
+const MIN_TUNING_BYTES: usize = 4 << 20;
+
+// times the parallel read of a prefix of the first file with every slice size, returns the fastest one
+fn auto_tune(paths: &[PathBuf], read: ReadOptions, options: ParseOptions, cancel: &Cancel) -> Result<usize, Error> {
+    let start = Instant::now();
+    let total: u64 = paths.iter().map(|p| input_size(p)).sum::<Result<u64, Error>>()?;
+    let budget = (total as usize / TUNING_SHARE).min(MAX_TUNING_BYTES);
+    let files = load_files(&paths[..paths.len().min(1)], read)?;
+    let Some(data) = files.first().filter(|_| budget >= MIN_TUNING_BYTES) else {
+        println!("Auto-tune: the input is too small, using the default chunk size of {} bytes", SLICE_SIZE);
+        return Ok(SLICE_SIZE);
+    };
+    // the prefix ends with a whole record
+    let prefix = &data[..budget.min(data.len())];
+    let prefix = &prefix[..prefix.iter().rposition(|&b| b == b'\n').map_or(prefix.len(), |i| i + 1)];
+    // pages in the prefix, so that the first size is not penalized by a cold page cache
+    count_lines_parallel(&slice(prefix), cancel);
+
+    let mut fastest: Option<(Duration, usize)> = None;
+    for size in TUNED_SLICE_SIZES {
+        // sizes that would leave some of the workers without a slice are skipped
+        if size * rayon::current_num_threads() > prefix.len() && fastest.is_some() {
+            break;
+        }
+        let pass = Instant::now();
+        let ParsedSlices { maps, .. } = parse_slices_parallel(&slice_sized(prefix, size), &MinMeanMax, options, cancel);
+        black_box(merge_all(&MinMeanMax, maps));
+        let elapsed = pass.elapsed();
+        if fastest.is_none_or(|(d, _)| elapsed < d) {
+            fastest = Some((elapsed, size));
+        }
+    }
+    let size = fastest.map_or(SLICE_SIZE, |(_, size)| size);
+    println!("Auto-tuned chunk size: {} bytes ({} byte passes, tuning took {:?})", size, prefix.len(), start.elapsed());
+    Ok(size)
+}
+
+// replaces the input directories by the regular files inside them matching `glob`, sorted by path
+fn expand_inputs(paths: &[PathBuf], recursive: bool, glob: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut dir_files: Vec<PathBuf> = Vec::new();
+            list_files(path, recursive, glob, &mut dir_files)?;
+            dir_files.sort();
+            files.extend(dir_files);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn list_files(dir: &Path, recursive: bool, glob: Option<&str>, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let with_path = |path: &Path, e: Error| Error::new(e.kind(), format!("{}: {}", path.display(), e));
+    for entry in fs::read_dir(dir).map_err(|e| with_path(dir, e))? {
+        let path = entry.map_err(|e| with_path(dir, e))?.path();
+        // follows the symbolic links
+        let metadata = fs::metadata(&path).map_err(|e| with_path(&path, e))?;
+        if metadata.is_dir() {
+            if recursive {
+                list_files(&path, recursive, glob, files)?;
+            }
+        } else if metadata.is_file() {
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            if glob.is_none_or(|glob| glob_matches(glob.as_bytes(), name.as_bytes())) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+// matches a file name against a pattern with the `*` and `?` wildcards
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        (Some((b'?', rest)), Some((_, name_rest))) => glob_matches(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && glob_matches(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
+// pins every rayon worker to its own core, must be called before the global pool is used
+fn pin_threads() {
+    let Some(cores) = core_affinity::get_core_ids().filter(|cores| !cores.is_empty()) else {
+        eprintln!("Warning: thread affinity is not available, the threads are not pinned");
+        return;
+    };
+    let result = rayon::ThreadPoolBuilder::new()
+        // the cores are shared only if there are more threads than cores (set by RAYON_NUM_THREADS)
+        .start_handler(move |i| {
+            let core = cores[i % cores.len()];
+            if !core_affinity::set_for_current(core) {
+                eprintln!("Warning: failed to pin worker {} to core {}", i, core.id);
+            }
+        })
+        .build_global();
+    if let Err(e) = result {
+        eprintln!("Warning: failed to pin the threads: {}", e);
+    }
+}
+
+// a number of bytes with an optional binary K, M or G suffix
+fn parse_size(s: &str) -> Result<usize, String> {
+    parse_offset(s).and_then(|size| if size > 0 { Ok(size) } else { Err(format!("Invalid size {}", s)) })
+}
+
+// a byte offset, 0 or more bytes with an optional binary K, M or G suffix
+fn parse_offset(s: &str) -> Result<usize, String> {
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'K' | b'k') => (&s[..s.len() - 1], 10),
+        Some(b'M' | b'm') => (&s[..s.len() - 1], 20),
+        Some(b'G' | b'g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n: usize = digits.parse().map_err(|e| format!("Invalid size {}: {}", s, e))?;
+    n.checked_shl(shift).filter(|&size| size >> shift == n).ok_or_else(|| format!("Invalid size {}", s))
+}
+
+// a power of ten up to the largest scale
+fn parse_scale(s: &str) -> Result<u32, String> {
+    let factor: u32 = s.parse().map_err(|e| format!("Invalid scale {}: {}", s, e))?;
+    Scale::new(factor).map(Scale::factor).ok_or_else(|| format!("Invalid scale {}, expected 1, 10, 100 or 1000", s))
+}
+
+// the names given on the command line and listed in the file, normalized like the station names of the result
+fn station_names(names: &[String], file: Option<&Path>, normalize: Option<KeyNormalization>) -> Result<HashSet<String>, Error> {
+    let mut excluded: Vec<String> = names.to_vec();
+    if let Some(path) = file {
+        let list = fs::read_to_string(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        excluded.extend(list.lines().filter(|l| !l.is_empty()).map(str::to_owned));
+    }
+    Ok(excluded.into_iter()
+        .map(|name| match normalize {
+            Some(keys) => keys.apply(&name).into_owned(),
+            None => name,
+        })
+        .collect())
+}
+
+fn parse_tee(s: &str) -> Result<(Format, PathBuf), String> {
+    let (format, path) = s.split_once('=').filter(|(_, path)| !path.is_empty()).ok_or_else(|| format!("Invalid target {}, expected FORMAT=PATH", s))?;
+    Ok((Format::from_str(format, true)?, PathBuf::from(path)))
+}
+
+// a fraction in (0, 1]
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("Invalid rate {}: {}", s, e))?;
+    if rate > 0.0 && rate <= 1.0 { Ok(rate) } else { Err(format!("Invalid rate {}, expected a fraction between 0 and 1", s)) }
+}
+
+// the formats of the results that have a counterpart for the counts
+fn counts_format(format: Format) -> Result<CountsFormat, Error> {
+    match format {
+        Format::Brace => Ok(CountsFormat::Brace),
+        Format::Plain => Ok(CountsFormat::Plain),
+        Format::Json => Ok(CountsFormat::Json),
+        Format::Ndjson => Ok(CountsFormat::Ndjson),
+        Format::Yaml => Ok(CountsFormat::Yaml),
+        Format::Csv => Ok(CountsFormat::Csv),
+        Format::Markdown => Ok(CountsFormat::Markdown),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "The counts can be written in the brace, plain, json, ndjson, yaml, csv or markdown format")),
+    }
+}
+
+fn histogram(bucket_width: f64) -> Result<Histogram, Error> {
+    let tenths = (bucket_width * 10.0).round();
+    if !(1.0..=(Histogram::MAX_TEMP - Histogram::MIN_TEMP) as f64).contains(&tenths) || (tenths - bucket_width * 10.0).abs() > 1e-9 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid histogram bucket width {}, expected a multiple of 0.1 between 0.1 and 199.8", bucket_width)));
+    }
+    let h = Histogram::with_bucket_width(tenths as i32);
+    // every worker can hold a map with all the stations on top of the merged one
+    let workers = rayon::current_num_threads() + 1;
+    let estimate = h.bytes_per_station() * MAX_STATIONS * workers;
+    eprintln!("Histogram memory: {} buckets x 8 bytes = {} bytes per station, up to {} MB for {} stations and {} workers",
+              h.buckets(), h.bytes_per_station(), estimate >> 20, MAX_STATIONS, workers);
+    if estimate > MAX_HISTOGRAM_BYTES {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Histograms could take up to {} MB, use wider buckets", estimate >> 20)));
+    }
+    Ok(h)
+}
+
+fn run<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, config: &SimpleReadConfig, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats + Serialize + DeserializeOwned,
+{
+    if let Some((start, end)) = output.byte_range {
+        range_read(paths, start, end, aggregator, options, output, cancel)?;
+        exit_if_cancelled(cancel);
+        return Ok(());
+    }
+    if let Some(names) = &output.id_map {
+        id_read(paths, names, aggregator, options, output, cancel)?;
+        exit_if_cancelled(cancel);
+        return Ok(());
+    }
+    if cached_result::<A::State>(paths, output)? {
+        return Ok(());
+    }
+    simple_file_read(paths, aggregator, options, config, output, cancel)?;
+    exit_if_cancelled(cancel);
+    if !parallel_supported(paths, config.bzip2)? {
+        return Ok(());
+    }
+
+    // --compare runs the streaming read in addition to the memory mapped one
+    if output.stream_every.is_none() || output.results.is_some() {
+        parallel_memory_mapped(paths, aggregator, options, output, cancel)?;
+        exit_if_cancelled(cancel);
+    }
+    if let Some(interval) = output.stream_every {
+        parallel_streaming(paths, aggregator, options, interval, output, cancel)?;
+        exit_if_cancelled(cancel);
+    }
+
+    if output.dense_ids {
+        parallel_dense_ids(paths, aggregator, options, output, cancel)?;
+        exit_if_cancelled(cancel);
+    }
+
+    if output.shared_map {
+        parallel_shared_map(paths, aggregator, options, output, cancel)?;
+        exit_if_cancelled(cancel);
+    }
+
+    Ok(())
+}
+
+// prints the result of the inputs cached by an earlier run, returns whether there was one
+fn cached_result<S: Stats + DeserializeOwned>(paths: &[PathBuf], output: &Output) -> Result<bool, Error> {
+    let start = Instant::now();
+    let mut stages = Stages::start();
+    let Some((cache, key)) = cache_key(paths, output) else {
+        return Ok(false);
+    };
+    let m: HashMap<String, S> = match cache.load(&key) {
+        Ok(Some(m)) => m,
+        Ok(None) => return Ok(false),
+        Err(e) => {
+            eprintln!("Warning: ignoring the cached result: {}", e);
+            return Ok(false);
+        }
+    };
+    stages.mark("load cache");
+    let mut info = RunInfo {
+        name: "cached result",
+        duration: start.elapsed(),
+        perf: None,
+        stages,
+        workers: Vec::new(),
+        threads: 1,
+        files: paths.len(),
+        bytes_processed: 0,
+        bytes_total: key.inputs.iter().map(|i| i.size as usize).sum(),
+        cancelled: None,
+        cold: None,
+        hash: None,
+        readahead: None,
+    };
+    print_result(&m, output, &mut info)?;
+    print_duration(&info, output);
+    Ok(true)
+}
+
+// the cache and the key of the inputs, `None` without --cache-dir or if some inputs are not local files
+fn cache_key<'a>(paths: &[PathBuf], output: &'a Output) -> Option<(&'a ResultsCache, CacheKey)> {
+    let (cache, config) = output.cache.as_ref()?;
+    if paths.iter().any(|p| is_s3(p) || is_stdin(p)) {
+        return None;
+    }
+    match CacheKey::of(paths, config) {
+        Ok(key) => key.map(|key| (cache, key)),
+        Err(e) => {
+            eprintln!("Warning: the result is not cached: {}", e);
+            None
+        }
+    }
+}
+
+// aggregates the file with the simple read, then the records appended to it every interval, until cancelled
+fn follow<A: Aggregator>(path: &Path, aggregator: &A, options: ParseOptions, config: &SimpleReadConfig, interval: Duration, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    if is_bzip2(path, false) || is_s3(path) {
+        return Err(Error::new(ErrorKind::InvalidInput, "--follow needs an uncompressed local file"));
+    }
+    let with_path = |e: Error| Error::new(e.kind(), format!("{}: {}", path.display(), e));
+    let start = Instant::now();
+    // a truncated file is aggregated again from the start, with a new arena for its station names
+    loop {
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let mut m: HashMap<&str, A::State> = HashMap::new();
+        let mut offset: u64 = 0;
+        loop {
+            // opened again every time, so that a rotated file is picked up
+            let mut file = File::open(path).map_err(with_path)?;
+            let size = file.metadata()?.len();
+            if size < offset {
+                eprintln!("Warning: {} shrank from {} to {} bytes, restarting the aggregation", path.display(), offset, size);
+                break;
+            }
+            let mut stages = Stages::start();
+            // a record that is still being written is left for the next read
+            let end = complete_lines_end(&mut file, offset, size)?;
+            file.seek(SeekFrom::Start(offset))?;
+            // the offsets of the records are counted from the start of the file, not of the appended part
+            let (stations, bytes_read) = read_stations_data_from(BufReader::with_capacity(config.read_buffer, file.take(end - offset)), offset, aggregator, &mut interner, m, options, cancel, |_, _| {});
+            offset += bytes_read as u64;
+            // the normalized keys are kept, the records read next are merged into them
+            m = match output.normalize {
+                Some(keys) => normalize_keys(aggregator, &mut interner, stations, keys),
+                None => stations,
+            };
+            validate(aggregator, &m)?;
+            stages.mark("read+parse");
+
+            let mut info = RunInfo {
+                name: "follow",
+                duration: start.elapsed(),
+                perf: None,
+                stages,
+                workers: Vec::new(),
+                threads: 1,
+                files: 1,
+                bytes_processed: offset as usize,
+                bytes_total: size as usize,
+                cancelled: cancel.reason(),
+                cold: None,
+                hash: output.hash_stats.then(|| HashStats::of(&m)),
+                readahead: None,
+            };
+            print_result(&m, output, &mut info)?;
+            // Ctrl-C ends the run after the final state is printed
+            if cancel.is_cancelled() || !sleep_unless_cancelled(interval, cancel) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// the offset after the last newline between `from` and `size`, `from` if there is none
+fn complete_lines_end<R: Read + Seek>(r: &mut R, from: u64, size: u64) -> Result<u64, Error> {
+    let mut buf = vec![0u8; 64 << 10];
+    let mut end = size;
+    while end > from {
+        let start = end.saturating_sub(buf.len() as u64).max(from);
+        let block = &mut buf[..(end - start) as usize];
+        r.seek(SeekFrom::Start(start))?;
+        r.read_exact(block)?;
+        if let Some(i) = memchr::memrchr(b'\n', block) {
+            return Ok(start + i as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(from)
+}
+
+// returns `false` if the run is cancelled before the duration elapses
+fn sleep_unless_cancelled(duration: Duration, cancel: &Cancel) -> bool {
+    let deadline = Instant::now() + duration;
+    while !cancel.is_cancelled() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+    false
+}
+
+// aggregates the files with the simple and the parallel read and fails at the first station they disagree on
+fn compare_methods<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, config: &SimpleReadConfig, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats + Serialize + DeserializeOwned,
+{
+    if !parallel_supported(paths, config.bzip2)? {
+        return Err(Error::new(ErrorKind::InvalidInput, "--compare-methods needs regular uncompressed files"));
+    }
+    let arena = Bump::new();
+    let mut interner = Interner::new(&arena);
+    let mut simple: HashMap<&str, A::State> = HashMap::new();
+    for path in paths {
+        read_file(path, aggregator, &mut interner, &mut simple, options, config, cancel)?;
+    }
+    let (mut parallel, _) = read_files_parallel(paths, aggregator, &mut interner, output.read, options, cancel)?;
+    exit_if_cancelled(cancel);
+    if let Some(keys) = output.normalize {
+        simple = normalize_keys(aggregator, &mut interner, simple, keys);
+        parallel = normalize_keys(aggregator, &mut interner, parallel, keys);
+    }
+    if let Some((station, s, p)) = first_difference(&simple, &parallel) {
+        let describe = |d: Option<&StationData>| match d {
+            Some(d) => format!("min {}, max {}, sum {}, count {}", d.min_temp, d.max_temp, d.sum_temp, d.n),
+            None => "missing".to_owned(),
+        };
+        return Err(Error::other(format!("The simple and the parallel read differ for station {}: simple {}, parallel {}", station, describe(s), describe(p))));
+    }
+    println!("Compared {} stations: the simple and the parallel read agree", simple.len());
+    Ok(())
 }
 
-const SLICE_SIZE: usize = 2 << 15;
+// the first station in the byte order of the names whose statistics differ, with the statistics of both maps
+fn first_difference<'m, S: Stats>(m1: &'m HashMap<&str, S>, m2: &'m HashMap<&str, S>) -> Option<(&'m str, Option<&'m StationData>, Option<&'m StationData>)> {
+    let mut stations: Vec<&str> = m1.keys().chain(m2.keys().filter(|s| !m1.contains_key(*s))).copied().collect();
+    stations.sort_unstable();
+    stations.into_iter()
+        .map(|s| (s, m1.get(s).map(Stats::data), m2.get(s).map(Stats::data)))
+        .find(|(_, d1, d2)| d1 != d2)
+}
 
-fn main() -> Result<(), Error> {
-    let args: Vec<String> = env::args().collect();
+// aggregates a sample of every file, the counts and the sums are extrapolated to the size of the file
+fn sampled_read(paths: &[PathBuf], rate: f64, seed: u64, options: ParseOptions, output: &Output) -> Result<(), Error> {
+    let cold = prepare_cache(paths, output);
+    let start = Instant::now();
+    let mut stages = Stages::start();
+    // mapped, so that only the pages of the probes are read
+    let files = paths.iter().map(|p| load_file(p, ReadOptions { encoding: output.read.encoding, ..ReadOptions::default() })).collect::<Result<Vec<FileData>, Error>>()?;
+    stages.mark("map");
+    let mut m = HashMap::new();
+    let (mut records, mut probes, mut bytes_processed, mut bytes_total) = (0, 0, 0, 0);
+    for (i, data) in files.iter().enumerate() {
+        let mut s = sample::sample(data, rate, seed.wrapping_add(i as u64), options);
+        let scale = s.scale(data.len());
+        sample::extrapolate(&mut s.stations, scale);
+        merge(&MinMeanMax, &mut m, s.stations);
+        records += s.records;
+        probes += s.probes;
+        bytes_processed += s.bytes;
+        bytes_total += data.len();
+    }
+    let arena = Bump::new();
+    let m = match output.normalize {
+        Some(keys) => normalize_keys(&MinMeanMax, &mut Interner::new(&arena), m, keys),
+        None => m,
+    };
+    validate(&MinMeanMax, &m)?;
+    stages.mark("sample");
+
+    let mut info = RunInfo {
+        name: "sampled read (approximate)",
+        duration: start.elapsed(),
+        perf: None,
+        stages,
+        workers: Vec::new(),
+        threads: 1,
+        files: paths.len(),
+        bytes_processed,
+        bytes_total,
+        cancelled: None,
+        cold,
+        hash: output.hash_stats.then(|| HashStats::of(&m)),
+        readahead: None,
+    };
+    print_result(&m, output, &mut info)?;
+    print_duration(&info, output);
+    println!("Sample {}: {} records from {} probes, {} of {} bytes ({:.2}%), the counts are extrapolated, min and max are as observed",
+             info.name, records, probes, bytes_processed, bytes_total, bytes_processed as f64 * 100.0 / bytes_total.max(1) as f64);
+    Ok(())
+}
 
-    simple_file_read(&args[1])?;
+// aggregates the records with several metrics with the parallel read, a file at a time
+fn metrics_read(paths: &[PathBuf], names: &[String], options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error> {
+    if !parallel_supported(paths, false)? {
+        return Err(Error::new(ErrorKind::InvalidInput, "--metrics needs uncompressed regular files"));
+    }
+    let cold = prepare_cache(paths, output);
+    let start = Instant::now();
+    let mut stages = Stages::start();
+    let files = load_files(paths, output.read)?;
+    stages.mark(load_stage(output.read));
+    let mut m: HashMap<&str, StationMetrics> = HashMap::new();
+    let mut bytes_processed = 0;
+    for data in &files {
+        let (stations, bytes) = read_metrics_parallel(&slice_sized(data, output.read.slice_size), names.len(), options, cancel);
+        merge_metrics(&mut m, stations);
+        bytes_processed += bytes;
+    }
+    check_all_unchanged(paths, &files)?;
+    stages.mark("parse+merge");
 
-    parallel_memory_mapped(&args[1])?;
+    let mut info = RunInfo {
+        name: parallel_name(output.read, "metrics mmap read", "metrics read (no mmap)"),
+        duration: start.elapsed(),
+        perf: None,
+        stages,
+        workers: Vec::new(),
+        threads: rayon::current_num_threads(),
+        files: paths.len(),
+        bytes_processed,
+        bytes_total: files.iter().map(|data| data.len()).sum(),
+        cancelled: cancel.reason(),
+        cold,
+        hash: None,
+        readahead: None,
+    };
+    print_metrics(&m, names, output, &mut info)?;
+    print_duration(&info, output);
+    exit_if_cancelled(cancel);
+    Ok(())
+}
 
+// the metrics counterpart of `print_result`, with the outputs it supports
+fn print_metrics(m: &HashMap<&str, StationMetrics>, names: &[String], output: &Output, info: &mut RunInfo) -> Result<(), Error> {
+    info.stages.last = Instant::now();
+    let mut rows = format::metrics_rows(m);
+    rows.retain(|r| !output.exclude.contains(r.station) && output.only.as_ref().is_none_or(|only| only.contains(r.station)));
+    for r in rows.iter_mut().flat_map(|r| &mut r.metrics) {
+        r.precision = output.precision;
+        r.rounding = output.rounding;
+    }
+    if let Some(n) = output.head {
+        rows.truncate(n);
+    }
+    if let Some(n) = output.tail {
+        rows.drain(..rows.len().saturating_sub(n));
+    }
+    let write = |mut w: &mut dyn Write| match output.format {
+        Format::Plain => format::write_metrics_plain(&mut w, names, &rows),
+        Format::Json => format::write_metrics_json(&mut w, names, &rows),
+        Format::Csv => format::write_metrics_csv(&mut w, names, &rows),
+        _ => format::write_metrics_brace(&mut w, names, &rows),
+    };
+    match &output.path {
+        Some(path) => {
+            let mut w = BufWriter::new(File::create(path)?);
+            write(&mut w)?;
+            w.flush()?;
+        }
+        None => {
+            let mut w = io::stdout().lock();
+            write(&mut w)?;
+            w.flush()?;
+        }
+    }
+    info.mark_output();
+    if output.errors.is_some_and(|log| report_errors(log, info.name)) {
+        output.invalid.store(true, Ordering::Relaxed);
+    }
     Ok(())
 }
 
-fn simple_file_read<'a, P: AsRef<Path>>(path: P) -> Result<(), Error> {
+// classifies the lines of the files, in parallel unless they are streamed
+fn quality_report(paths: &[PathBuf], columns: Option<Columns>, config: &SimpleReadConfig, read: ReadOptions, cancel: &Cancel) -> Result<QualityReport, Error> {
+    let mut report = QualityReport::default();
+    for path in paths {
+        if is_bzip2(path, config.bzip2) || is_s3(path) || is_stdin(path) || is_transcoded(path, config.encoding)? {
+            let mut input = ErrorTrap::new(open_input(path, config.bzip2, config.encoding)?);
+            report.merge(QualityReport::of_reader(BufReader::with_capacity(config.read_buffer, &mut input), columns, cancel));
+            input.finish().map_err(|e| if is_s3(path) { e } else { Error::new(e.kind(), format!("{}: {}", path.display(), e)) })?;
+        } else {
+            let data = load_file(path, read)?;
+            report.merge(QualityReport::of_slices(&slice_sized(&data, read.slice_size), columns, cancel));
+        }
+        exit_if_cancelled(cancel);
+    }
+    Ok(report)
+}
+
+// scans the files with both implementations, without aggregating
+// returns whether invalid records were collected
+fn dry_run(paths: &[PathBuf], options: ParseOptions, config: &SimpleReadConfig, read: ReadOptions, cancel: &Cancel) -> Result<bool, Error> {
+    let start = Instant::now();
+    let (mut records, mut bytes) = (0, 0);
+    for path in paths {
+        let mut input = ErrorTrap::new(open_input(path, config.bzip2, config.encoding)?);
+        let (r, b) = scan_stations_data(BufReader::with_capacity(config.read_buffer, &mut input), options, cancel);
+        input.finish().map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        records += r;
+        bytes += b;
+        if cancel.is_cancelled() {
+            break;
+        }
+    }
+    print_dry_run("simple file read", start.elapsed(), records, bytes);
+    let mut invalid = options.errors.is_some_and(|log| report_errors(log, "simple file read"));
+    exit_if_cancelled(cancel);
+    if !parallel_supported(paths, config.bzip2)? {
+        return Ok(invalid);
+    }
+
     let start = Instant::now();
+    let (mut records, mut bytes) = (0, 0);
+    for path in paths {
+        let data = load_file(path, read)?;
+        let (r, b) = scan_slices_parallel(&slice_sized(&data, read.slice_size), options, cancel);
+        records += r;
+        bytes += b;
+    }
+    let name = parallel_name(read, "parallel mmap read", "parallel read (no mmap)");
+    print_dry_run(name, start.elapsed(), records, bytes);
+    invalid |= options.errors.is_some_and(|log| report_errors(log, name));
+    exit_if_cancelled(cancel);
+    Ok(invalid)
+}
+
+// aggregates the files once and answers the queries read from stdin until EOF or `quit`
+fn repl<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let arena = Bump::new();
+    let mut interner = Interner::new(&arena);
+    let (mut m, _) = read_files_parallel(paths, aggregator, &mut interner, output.read, options, cancel)?;
+    if let Some(keys) = output.normalize {
+        m = normalize_keys(aggregator, &mut interner, m, keys);
+    }
+    validate(aggregator, &m)?;
+    let stations = m.len();
+    m.retain(|station, _| !output.exclude.contains(*station));
+    let mut rows = format::rows(&m);
+    for r in &mut rows {
+        r.precision = output.precision;
+        r.rounding = output.rounding;
+        r.scale = output.scale;
+    }
+    eprintln!("Loaded {} stations ({} excluded), type `help` for the commands", rows.len(), stations - rows.len());
+    answer_queries(io::stdin().lock(), &mut anstream::stdout(), &rows)
+}
+
+const QUERIES_HELP: &str = "Commands: get STATION, top N [min|mean|max|count], bottom N [min|mean|max|count], count, quit";
+
+fn answer_queries<R: BufRead, W: Write>(input: R, w: &mut W, rows: &[Row]) -> Result<(), Error> {
+    for line in input.lines() {
+        let line = line?;
+        let (command, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            "quit" | "exit" => break,
+            "help" => writeln!(w, "{}", QUERIES_HELP)?,
+            "count" => {
+                let measurements: u64 = rows.iter().map(|r| r.data.count() as u64).sum();
+                writeln!(w, "{} stations, {} measurements", rows.len(), measurements)?;
+            }
+            // the rows are sorted by station
+            "get" => match rows.binary_search_by(|r| r.station.cmp(arg)) {
+                Ok(i) => format::write_plain(w, &rows[i..=i])?,
+                Err(_) => writeln!(w, "Unknown station {}", arg)?,
+            },
+            "top" | "bottom" => match ranked(rows, arg, command == "top") {
+                Some(ranked) => format::write_plain(w, &ranked)?,
+                None => writeln!(w, "Usage: {} N [min|mean|max|count]", command)?,
+            },
+            _ => writeln!(w, "Unknown command {}. {}", command, QUERIES_HELP)?,
+        }
+        w.flush()?;
+    }
+    Ok(())
+}
+
+// the first N rows by the statistic, in descending order for `top`, stations with equal values stay alphabetical
+fn ranked<'a>(rows: &[Row<'a>], arg: &str, descending: bool) -> Option<Vec<Row<'a>>> {
+    let (n, field) = arg.split_once(' ').map_or((arg, "max"), |(n, field)| (n, field.trim()));
+    let n: usize = n.parse().ok()?;
+    let key: fn(&Row<'a>) -> f64 = match field {
+        "min" => Row::min,
+        "mean" => Row::mean,
+        "max" => Row::max,
+        "count" => |r| r.data.count() as f64,
+        _ => return None,
+    };
+    let mut ranked: Vec<Row> = rows.to_vec();
+    ranked.sort_by(|r1, r2| {
+        let order = key(r1).total_cmp(&key(r2));
+        if descending { order.reverse() } else { order }
+    });
+    ranked.truncate(n);
+    Some(ranked)
+}
+
+fn generate_file(args: &GenerateArgs) -> Result<(), Error> {
+    if !(1..=MAX_STATIONS).contains(&args.stations) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("The number of stations must be between 1 and {}", MAX_STATIONS)));
+    }
+    if !(args.stddev.is_finite() && args.stddev >= 0.0) {
+        return Err(Error::new(ErrorKind::InvalidInput, "The standard deviation must be a number of at least 0"));
+    }
+    let options = generate::GenerateOptions { distribution: args.distribution, stddev: args.stddev };
+    let mut w = BufWriter::new(File::create(&args.output)?);
+    let expected = match args.sorted {
+        true => generate::generate_sorted_with(&mut w, args.rows, args.stations, args.seed, options)?,
+        false => generate::generate_with(&mut w, args.rows, args.stations, args.seed, options)?,
+    };
+    w.flush()?;
+    if let Some(path) = &args.expected {
+        let mut w = BufWriter::new(File::create(path)?);
+        generate::write_expected(&mut w, &expected)?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
+// serves the aggregations until Ctrl-C
+#[cfg(feature = "http")]
+fn serve(args: &ServeArgs) -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind((args.bind, args.port))?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let cancel = Arc::new(Cancel::default());
+    {
+        let cancel = Arc::clone(&cancel);
+        ctrlc::set_handler(move || {
+            cancel.cancel(CancelReason::Interrupted);
+        }).expect("Failed to install the Ctrl-C handler");
+    }
+    // the requests are served one at a time, the log is emptied by every aggregation
+    let errors: &'static ErrorLog = Box::leak(Box::new(ErrorLog::new(1)));
+    rust_1brc::http::serve(&listener, args.max_body, |request| handle_request(request, errors), &cancel)
+}
 
-    let file = File::open(path)?;
-    let m = read_stations_data(BufReader::new(file));
+#[cfg(feature = "http")]
+fn handle_request(request: &rust_1brc::http::Request, errors: &'static ErrorLog) -> rust_1brc::http::Response {
+    use rust_1brc::http::Response;
+    let path = request.path.split_once('?').map_or(request.path.as_str(), |(path, _)| path);
+    match (request.method.as_str(), path) {
+        ("POST", "/aggregate") => {}
+        (_, "/aggregate") => return Response::text(405, "Only POST is supported"),
+        _ => return Response::text(404, format!("Not found: {}", path)),
+    }
+    let options = ParseOptions { errors: Some(errors), ..Default::default() };
+    let (m, _) = rust_1brc::read::read_slices_parallel(&slice(&request.body), &MinMeanMax, options, &Cancel::default());
+    let (invalid, count) = errors.take();
+    if let Some(first) = invalid.first() {
+        return Response::text(400, format!("Invalid records: {}, the first one: {}", count, first));
+    }
+    if let Err(e) = validate(&MinMeanMax, &m) {
+        return Response::text(400, e.to_string());
+    }
+    let mut body = Vec::new();
+    match format::write_json(&mut body, &format::rows(&m)) {
+        Ok(()) => Response::json(200, body),
+        Err(e) => Response::text(500, e.to_string()),
+    }
+}
+
+// the requests of --serve have no body
+#[cfg(feature = "http")]
+const SERVE_MAX_BODY: usize = 64 << 10;
+
+// serves the results printed to the returned state on a thread until cancelled
+#[cfg(feature = "http")]
+fn serve_results(listener: std::net::TcpListener, cancel: &Arc<Cancel>) -> Result<Server, Error> {
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let served = Arc::new(RwLock::new(None));
+    let server = {
+        let (served, cancel) = (Arc::clone(&served), Arc::clone(cancel));
+        thread::spawn(move || rust_1brc::http::serve(&listener, SERVE_MAX_BODY, |request| results_response(request, &served), &cancel))
+    };
+    Ok((served, server))
+}
+
+#[cfg(feature = "http")]
+fn results_response(request: &rust_1brc::http::Request, served: &RwLock<Option<Served>>) -> rust_1brc::http::Response {
+    use rust_1brc::http::{percent_decode, Response};
+    let path = request.path.split_once('?').map_or(request.path.as_str(), |(path, _)| path);
+    if path != "/healthz" && path != "/stations" && !path.starts_with("/stations/") {
+        return Response::text(404, format!("Not found: {}", path));
+    }
+    if request.method != "GET" {
+        return Response::text(405, "Only GET is supported");
+    }
+    if path == "/healthz" {
+        return Response::text(200, "OK");
+    }
+    let served = served.read().unwrap();
+    let Some(served) = served.as_ref() else {
+        return Response::text(503, "The aggregation has not finished yet");
+    };
+    match path.strip_prefix("/stations/") {
+        None => Response::json(200, served.stations.clone()),
+        Some(name) => match percent_decode(name) {
+            None => Response::text(400, format!("Invalid station name: {}", name)),
+            Some(name) => match served.station.get(&name) {
+                Some(json) => Response::json(200, json.clone()),
+                None => Response::text(404, format!("Unknown station: {}", name)),
+            },
+        },
+    }
+}
+
+// waits for the server of --serve to be stopped by Ctrl-C
+fn wait_for_server(server: Option<Server>) -> Result<(), Error> {
+    let Some((_, server)) = server else {
+        return Ok(());
+    };
+    server.join().expect("The server thread panicked")
+}
 
+fn diff_results(args: &DiffArgs) -> Result<(), Error> {
+    let read = |path: &Path| fs::read_to_string(path)
+        .and_then(|s| diff::parse_results(&s))
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)));
+    let d = diff::diff(&read(&args.old)?, &read(&args.new)?, args.tolerance);
+    match args.format {
+        ReportFormat::Text => print_diff(&d, args),
+        ReportFormat::Json => println!("{}", serde_json::to_string(&d)?),
+    }
+    if !d.is_empty() {
+        process::exit(EXIT_DIFFERENT);
+    }
+    Ok(())
+}
+
+fn print_diff(d: &ResultsDiff, args: &DiffArgs) {
+    for (stations, path) in [(&d.only_old, &args.old), (&d.only_new, &args.new)] {
+        if !stations.is_empty() {
+            println!("Only in {}: {}", path.display(), stations.join(", "));
+        }
+    }
+    for (station, fields) in &d.changed {
+        let changes: Vec<String> = fields.iter()
+            .map(|f| {
+                // the counts are integers, the temperatures have one decimal
+                let precision = if f.field == "count" { 0 } else { 1 };
+                format!("{} {:.*} -> {:.*} ({:+.*})", f.field, precision, f.old, precision, f.new, precision, f.new - f.old)
+            })
+            .collect();
+        println!("{}: {}", station, changes.join(", "));
+    }
+    if d.is_empty() {
+        println!("No differences");
+    }
+}
+
+fn count_only(paths: &[PathBuf], read: ReadOptions, cancel: &Cancel) -> Result<(), Error> {
+    let start = Instant::now();
+    let (mut lines, mut bytes) = (0, 0);
+    for path in paths {
+        let data = load_file(path, read)?;
+        let (l, b) = count_lines_parallel(&slice_sized(&data, read.slice_size), cancel);
+        lines += l;
+        bytes += b;
+    }
     let duration = start.elapsed();
-    print_result(&m);
-    println!("Duration simple file read: {:?}", duration);
+    println!("Duration parallel line count ({} rows, {} bytes): {:?}, {:.2} GB/s", lines, bytes, duration, bytes as f64 / duration.as_secs_f64() / 1e9);
+    exit_if_cancelled(cancel);
     Ok(())
 }
 
-fn read_stations_data<P: BufRead>(reader: P) -> HashMap<String, StationData> {
-    let mut m: HashMap<String, StationData> = HashMap::new();
-    for line in reader.lines() {
-        if let Ok(l) = line {
-            let parts: Vec<&str> = l.split(';').collect();
-            if parts.len() == 2 {
-                let station: String = parts[0].to_owned();
-                let temp: f64 = parts[1].parse().expect(&format!("Invalid temperature, ignoring: {}", parts[1]));
-                m.entry(station).and_modify(|e| {
-                    e.max_temp = temp.max(e.max_temp);
-                    e.min_temp = temp.min(e.min_temp);
-                    e.sum_temp += temp;
-                    e.n += 1;
+fn print_dry_run(name: &str, duration: Duration, records: usize, bytes: usize) {
+    println!("Duration {} (dry run, {} rows, {} bytes): {:?}, {:.0} rows/s", name, records, bytes, duration, records as f64 / duration.as_secs_f64());
+}
+
+fn simple_file_read<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, config: &SimpleReadConfig, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats + Serialize + DeserializeOwned,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    let cache = cache_key(paths, output);
+    let arena = Bump::new();
+    let mut interner = Interner::new(&arena);
+    let mut m: HashMap<&str, A::State> = HashMap::new();
+    let mut bytes_processed: usize = 0;
+    let mut bytes_total: usize = 0;
+    let mut files: usize = 0;
+    // the files are read one after another
+    for path in paths {
+        let (file_processed, file_total) = read_file(path, aggregator, &mut interner, &mut m, options, config, cancel)?;
+        bytes_processed += file_processed;
+        bytes_total += file_total;
+        files += 1;
+        if cancel.is_cancelled() {
+            break;
+        }
+    }
+    if let Some(keys) = output.normalize {
+        m = normalize_keys(aggregator, &mut interner, m, keys);
+    }
+    validate(aggregator, &m)?;
+    stages.mark("read+parse");
+    if let Some((cache, key)) = cache.filter(|_| !cancel.is_cancelled()) {
+        // the inputs must not have changed during the read
+        let stored = match CacheKey::of(paths, &key.config) {
+            Ok(Some(after)) if after == key => cache.store(&key, &m),
+            Ok(_) => Err(Error::other("the inputs changed during the read")),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = stored {
+            eprintln!("Warning: the result is not cached: {}", e);
+        }
+        stages.mark("store cache");
+    }
+
+    // the files after a cancelled one are not read
+    let run = Run { files, ..run };
+    let mut info = run.info("simple file read", stages, Vec::new(), bytes_processed, bytes_total);
+    info.threads = 1;
+    info.hash = output.hash_stats.then(|| HashStats::of(&m));
+    report(&m, output, info)
+}
+
+// aggregates the file into `m`, returns the number of bytes processed and the size of the file
+fn read_file<'a, A: Aggregator>(path: &Path, aggregator: &A, interner: &mut Interner<'a>, m: &mut HashMap<&'a str, A::State>, options: ParseOptions, config: &SimpleReadConfig, cancel: &Cancel) -> Result<(usize, usize), Error>
+where
+    A::State: Serialize + DeserializeOwned,
+{
+    let with_path = |e: Error| Error::new(e.kind(), format!("{}: {}", path.display(), e));
+    if is_bzip2(path, config.bzip2) || is_s3(path) || is_stdin(path) || is_transcoded(path, config.encoding)? {
+        // the size of the decompressed or transcoded data is only known after the read, the objects are streamed
+        let mut input = ErrorTrap::new(retrying(open_input(path, config.bzip2, config.encoding)?, path, config));
+        let (stations, bytes_read) = read_stations_data(BufReader::with_capacity(config.read_buffer, &mut input), aggregator, interner, std::mem::take(m), options, cancel, |_, _| {});
+        *m = stations;
+        // the errors of the objects already include the URL
+        input.finish().map_err(|e| if is_s3(path) { e } else { with_path(e) })?;
+        return Ok((bytes_read, bytes_read));
+    }
+    let mut file = File::open(path).map_err(with_path)?;
+    let bytes_total = file.metadata()?.len() as usize;
+    let (stations, offset) = match &config.resume {
+        Some(resume) => {
+            let c: Checkpoint<HashMap<&str, A::State>> = read_checkpoint(resume, interner)?;
+            if c.offset > bytes_total {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Checkpoint offset {} is past the end of the input", c.offset)));
+            }
+            file.seek(SeekFrom::Start(c.offset as u64))?;
+            (c.stations, c.offset)
+        }
+        None => (std::mem::take(m), 0),
+    };
+
+    let mut last_checkpoint = Instant::now();
+    let mut input = ErrorTrap::new(retrying(file, path, config));
+    let (stations, bytes_read) = read_stations_data(BufReader::with_capacity(config.read_buffer, &mut input), aggregator, interner, stations, options, cancel, |m, bytes_read| {
+        if let Some(path) = &config.checkpoint {
+            if last_checkpoint.elapsed() >= config.interval {
+                if let Err(e) = write_checkpoint(path, offset + bytes_read, m) {
+                    eprintln!("Failed to write checkpoint {}: {}", path.display(), e);
                 }
-                ).or_insert(StationData {
-                    min_temp: temp,
-                    max_temp: temp,
-                    sum_temp: temp,
-                    n: 1,
-                });
+                last_checkpoint = Instant::now();
             }
         }
+    });
+    *m = stations;
+    input.finish().map_err(with_path)?;
+    let bytes_processed = offset + bytes_read;
+    if let Some(path) = &config.checkpoint {
+        write_checkpoint(path, bytes_processed, m)?;
     }
-    m
+    Ok((bytes_processed, bytes_total))
 }
 
-fn parallel_memory_mapped<P: AsRef<Path>>(path: P) -> Result<(), Error> {
-    let start = Instant::now();
+// how long the simple file read waits before the first retry of a read, doubled for every next one
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
 
-    let file = File::open(path)?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let slices = slice(&mmap[..]);
-    let m = slices
-        .par_iter()
-        .map(|slice| read_stations_data_slice(*slice))
-        .reduce(|| HashMap::new(),
-                |mut m1, m2| {
-                    for (station, station_data) in m2.into_iter() {
-                        m1.entry(station).and_modify(|e| {
-                            e.max_temp = station_data.max_temp.max(e.max_temp);
-                            e.min_temp = station_data.min_temp.min(e.min_temp);
-                            e.sum_temp += station_data.sum_temp;
-                            e.n += station_data.n;
-                        }
-                        ).or_insert(station_data);
-                    }
-                    m1
-                },
-        );
+// the input retrying the reads failing with a transient error up to --retry times
+fn retrying<'a, R: Read>(input: R, path: &'a Path, config: &SimpleReadConfig) -> Retry<R, impl FnMut(&Error, u32, Duration) + 'a> {
+    let (retries, verbose) = (config.retry, config.verbose);
+    Retry::new(input, config.retry, RETRY_BACKOFF, move |e: &Error, retry, backoff| if verbose {
+        eprintln!("Retrying the read of {} in {:?} ({} of {}): {}", path.display(), backoff, retry, retries, e);
+    })
+}
 
-    let duration = start.elapsed();
-    print_result(&m);
-    println!("Duration parallel mmap read: {:?}", duration);
-    Ok(())
+// the checkpoints start with the magic bytes and the version of the format as a big-endian u32, followed by
+// the `Checkpoint` as JSON, which does not depend on the byte order or the word size of the machine
+const CHECKPOINT_MAGIC: &[u8; 8] = b"1BRCCKPT";
+const CHECKPOINT_VERSION: u32 = 1;
+
+// the station names are copied into the `interner`
+fn read_checkpoint<'a, S: DeserializeOwned>(path: &Path, interner: &mut Interner<'a>) -> Result<Checkpoint<HashMap<&'a str, S>>, Error> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    let mut r = BufReader::new(File::open(path)?);
+    let mut header = [0; 12];
+    r.read_exact(&mut header).map_err(|_| invalid("Not a checkpoint, the file is too short".to_owned()))?;
+    let (magic, version) = header.split_at(CHECKPOINT_MAGIC.len());
+    if magic != CHECKPOINT_MAGIC {
+        return Err(invalid("Not a checkpoint, the header is missing".to_owned()));
+    }
+    let version = u32::from_be_bytes(version.try_into().unwrap());
+    if version != CHECKPOINT_VERSION {
+        return Err(invalid(format!("Checkpoint format version {} is not supported, expected version {}", version, CHECKPOINT_VERSION)));
+    }
+    let c: Checkpoint<HashMap<String, S>> = serde_json::from_reader(r)?;
+    let stations = c.stations.into_iter().map(|(station, state)| (interner.intern(&station), state)).collect();
+    Ok(Checkpoint { offset: c.offset, stations })
 }
 
-fn slice(data: &[u8]) -> Vec<&[u8]> {
-    let mut slices: Vec<&[u8]> = Vec::new();
-    let mut slice_start: usize = 0;
-    let len = data.len();
-    while slice_start < len {
-        let mut slice_end: usize = slice_start + SLICE_SIZE;
-        while slice_end < len && &data[slice_end] != &b'\n' {
-            slice_end += 1;
+fn write_checkpoint<S: Serialize>(path: &Path, offset: usize, m: &HashMap<&str, S>) -> Result<(), Error> {
+    // write to a temporary file first so that an interrupted write never corrupts the previous checkpoint
+    let tmp = path.with_extension("tmp");
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    w.write_all(CHECKPOINT_MAGIC)?;
+    w.write_all(&CHECKPOINT_VERSION.to_be_bytes())?;
+    serde_json::to_writer(&mut w, &Checkpoint { offset, stations: m })?;
+    w.flush()?;
+    fs::rename(&tmp, path)
+}
+
+fn parallel_memory_mapped<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    if let [path] = paths {
+        let data = load_file(path, output.read)?;
+        stages.mark(load_stage(output.read));
+        let slices = slice_sized(&data, output.read.slice_size);
+        stages.mark("slice");
+        // the pages of the data read into memory are already there
+        let (ParsedSlices { maps, bytes_processed, workers }, readahead) = match output.readahead.filter(|_| matches!(data, FileData::Mapped(_))) {
+            Some(lead) => {
+                let (parsed, stats) = with_readahead(&data, lead, |consumed| parse_slices_parallel_with_progress(&slices, aggregator, options, cancel, consumed));
+                (parsed, Some(stats))
+            }
+            None => (parse_slices_parallel(&slices, aggregator, options, cancel), None),
+        };
+        stages.mark("parse");
+        check_unchanged(path, &data)?;
+        // the tables of the workers are measured before they are merged, in a stage of its own
+        let hash = output.hash_stats.then(|| {
+            let stats = maps.iter().map(HashStats::of).reduce(|mut s1, s2| {
+                s1.merge(s2);
+                s1
+            });
+            stages.mark("hash stats");
+            stats.unwrap_or_default()
+        });
+        let m = merge_all(aggregator, maps);
+        let arena = Bump::new();
+        let m = normalized(aggregator, output, &arena, m);
+        validate(aggregator, &m)?;
+        stages.mark("merge");
+
+        let mut info = run.info(parallel_name(output.read, "parallel mmap read", "parallel read (no mmap)"), stages, workers, bytes_processed, data.len());
+        info.hash = hash;
+        info.readahead = readahead;
+        report(&m, output, info)
+    } else {
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let (mut m, bytes_processed) = read_files_parallel(paths, aggregator, &mut interner, output.read, options, cancel)?;
+        if let Some(keys) = output.normalize {
+            m = normalize_keys(aggregator, &mut interner, m, keys);
         }
-        if slice_end < len {
-            slices.push(&data[slice_start..slice_end]);
-        } else {
-            slices.push(&data[slice_start..len]);
+        validate(aggregator, &m)?;
+        // the files are mapped, parsed and merged concurrently
+        stages.mark("read+parse+merge");
+
+        let bytes_total = paths.iter().map(|p| input_size(p).map(|size| size as usize)).sum::<Result<usize, Error>>()?;
+        run.finish(parallel_name(output.read, "parallel mmap read", "parallel read (no mmap)"), stages, Vec::new(), &m, bytes_processed, bytes_total)
+    }
+}
+
+// aggregates the records of the byte range of the single file in parallel
+fn range_read<A: Aggregator>(paths: &[PathBuf], start: usize, end: usize, aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    let path = &paths[0];
+    let data = load_file(path, output.read)?;
+    stages.mark(load_stage(output.read));
+    let range = byte_range(&data, start, end);
+    let slices = slice_sized(range, output.read.slice_size);
+    stages.mark("slice");
+    let ParsedSlices { maps, bytes_processed, workers } = parse_slices_parallel(&slices, aggregator, options, cancel);
+    stages.mark("parse");
+    check_unchanged(path, &data)?;
+    let m = merge_all(aggregator, maps);
+    let arena = Bump::new();
+    let m = normalized(aggregator, output, &arena, m);
+    validate(aggregator, &m)?;
+    stages.mark("merge");
+
+    run.finish(parallel_name(output.read, "byte range mmap read", "byte range read (no mmap)"), stages, workers, &m, bytes_processed, range.len())
+}
+
+// the time taken is added to the stages of the run
+fn parallel_streaming<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, interval: Duration, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    let files = load_files(paths, output.read)?;
+    stages.mark(load_stage(output.read));
+    let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, output.read.slice_size)).collect();
+    stages.mark("slice");
+    let bytes_total: usize = files.iter().map(|data| data.len()).sum();
+    let (m, bytes_processed) = read_slices_streaming(&slices, aggregator, options, cancel, interval, |m, bytes_processed| {
+        let mut stdout = anstream::stdout().lock();
+        // the final result is printed in full, snapshots are best effort
+        let _ = write!(stdout, "Snapshot after {:?} ({} of {} bytes): ", run.start.elapsed(), bytes_processed, bytes_total)
+            .and_then(|_| format::write_brace(&mut stdout, &format::rows(m), false));
+    });
+    check_all_unchanged(paths, &files)?;
+    let arena = Bump::new();
+    let m = normalized(aggregator, output, &arena, m);
+    validate(aggregator, &m)?;
+    stages.mark("parse+merge");
+
+    run.finish(parallel_name(output.read, "parallel mmap read (streaming)", "parallel read (no mmap, streaming)"), stages, Vec::new(), &m, bytes_processed, bytes_total)
+}
+
+fn load_files(paths: &[PathBuf], read: ReadOptions) -> Result<Vec<FileData>, Error> {
+    paths.iter().map(|path| load_file(path, read)).collect()
+}
+
+fn check_all_unchanged(paths: &[PathBuf], files: &[FileData]) -> Result<(), Error> {
+    paths.iter().zip(files).try_for_each(|(path, data)| check_unchanged(path, data))
+}
+
+// evicts the inputs from the page cache if the current iteration is cold, returns whether it is, `None` without --cold
+fn prepare_cache(paths: &[PathBuf], output: &Output) -> Option<bool> {
+    let cold = output.cold.as_ref()?.load(Ordering::Relaxed);
+    if cold {
+        // pipes have no cached pages to drop
+        for path in paths.iter().filter(|p| fs::metadata(p).is_ok_and(|m| m.is_file())) {
+            if let Err(e) = evict_from_page_cache(path) {
+                eprintln!("Warning: failed to evict {} from the page cache: {}", path.display(), e);
+            }
         }
-        slice_start = slice_end + 1;
     }
-    slices
+    Some(cold)
+}
+
+// pipes and other files that are not regular can be read only once, by the simple file read
+fn parallel_supported(paths: &[PathBuf], bzip2: bool) -> Result<bool, Error> {
+    if paths.iter().any(|p| is_bzip2(p, bzip2)) {
+        eprintln!("Info: the input is compressed, the parallel implementations are skipped");
+        return Ok(false);
+    }
+    let supported = regular_files(paths)?;
+    if !supported {
+        eprintln!("Info: the input is not a regular file, the parallel implementations are skipped");
+    }
+    Ok(supported)
 }
 
-fn read_stations_data_slice(data: &[u8]) -> HashMap<&str, StationData> {
-    let mut m: HashMap<&str, StationData> = HashMap::new();
-    let mut i: usize = 0;
-    let len: usize = data.len();
+// the parallel implementations are named after how they load the files
+fn parallel_name(read: ReadOptions, mmap_name: &'static str, read_name: &'static str) -> &'static str {
+    if read.no_mmap { read_name } else { mmap_name }
+}
+
+fn load_stage(read: ReadOptions) -> &'static str {
+    if read.no_mmap { "open+read" } else { "open+mmap" }
+}
+
+fn parallel_dense_ids<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    let files = load_files(paths, output.read)?;
+    stages.mark(load_stage(output.read));
+    // the slices of all the files are aggregated together
+    let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, output.read.slice_size)).collect();
+    stages.mark("slice");
+    let (m, bytes_processed) = read_slices_dense(&slices, aggregator, options, cancel);
+    check_all_unchanged(paths, &files)?;
+    let arena = Bump::new();
+    let m = normalized(aggregator, output, &arena, m);
+    validate(aggregator, &m)?;
+    stages.mark("parse+merge");
+
+    run.finish(parallel_name(output.read, "parallel mmap read (dense IDs)", "parallel read (no mmap, dense IDs)"), stages, Vec::new(), &m, bytes_processed, files.iter().map(|data| data.len()).sum())
+}
+
+fn parallel_shared_map<A: Aggregator>(paths: &[PathBuf], aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
 
-    let mut station_start: usize = 0;
-    let mut station_end: usize = 0;
-    let mut temp_start: usize = 0;
-    while i < len {
-        if &data[i] == &b'\n' {
-            process_record(data, &mut m, station_start, station_end, temp_start, i);
-            station_start = i + 1;
-        } else if &data[i] == &b';' {
-            station_end = i;
-            temp_start = i + 1;
+    let files = load_files(paths, output.read)?;
+    stages.mark(load_stage(output.read));
+    // the slices of all the files are aggregated together
+    let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, output.read.slice_size)).collect();
+    stages.mark("slice");
+    let (m, bytes_processed) = read_slices_shared(&slices, aggregator, options, cancel);
+    check_all_unchanged(paths, &files)?;
+    let arena = Bump::new();
+    let m = normalized(aggregator, output, &arena, m);
+    validate(aggregator, &m)?;
+    // there is nothing to merge, the workers update the same map
+    stages.mark("parse");
+
+    run.finish(parallel_name(output.read, "parallel mmap read (shared map)", "parallel read (no mmap, shared map)"), stages, Vec::new(), &m, bytes_processed, files.iter().map(|data| data.len()).sum())
+}
+
+// aggregates the records keyed by integer station IDs in parallel and names them at the end
+fn id_read<A: Aggregator>(paths: &[PathBuf], names: &HashMap<u64, String>, aggregator: &A, options: ParseOptions, output: &Output, cancel: &Cancel) -> Result<(), Error>
+where
+    A::State: Stats,
+{
+    let run = Run::start(paths, output, cancel);
+    let mut stages = Stages::start();
+
+    let files = load_files(paths, output.read)?;
+    stages.mark(load_stage(output.read));
+    // the slices of all the files are aggregated together
+    let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, output.read.slice_size)).collect();
+    stages.mark("slice");
+    let (stations, bytes_processed) = read_slices_ids(&slices, aggregator, options, cancel);
+    check_all_unchanged(paths, &files)?;
+    stages.mark("parse+merge");
+    // the unknown IDs are invalid unless lenient
+    let m = stations.into_named(names, options.lenient)?;
+    validate(aggregator, &m)?;
+    stages.mark("name");
+
+    run.finish(parallel_name(output.read, "parallel mmap read (station IDs)", "parallel read (no mmap, station IDs)"), stages, Vec::new(), &m, bytes_processed, files.iter().map(|data| data.len()).sum())
+}
+
+fn print_result<K: AsRef<str>, S: Stats>(m: &HashMap<K, S>, output: &Output, info: &mut RunInfo) -> Result<(), Error> {
+    info.stages.last = Instant::now();
+    // the excluded stations are dropped before anything is ranked or written
+    let stations = m.len();
+    let mut m: HashMap<&str, &S> = m.iter()
+        .map(|(station, stats)| (station.as_ref(), stats))
+        .filter(|(station, _)| !output.exclude.contains(*station))
+        .collect();
+    let excluded = stations - m.len();
+    let mut rows = match output.only {
+        Some(only) => {
+            m.retain(|station, _| only.contains(*station));
+            let mut rows = format::rows(&m);
+            // the listed stations are written even without records
+            rows.extend(only.iter()
+                .filter(|station| !m.contains_key(station.as_str()) && !output.exclude.contains(*station))
+                .map(|station| Row::no_data(station)));
+            rows.sort_unstable_by(|r1, r2| r1.station.cmp(r2.station));
+            rows
         }
-        i += 1;
+        None => format::rows(&m),
+    };
+    for r in &mut rows {
+        r.precision = output.precision;
+        r.rounding = output.rounding;
+        r.scale = output.scale;
     }
-    // process the last record if the file does not end with a newline
-    if &data[len - 1] != &b'\n' {
-        process_record(data, &mut m, station_start, station_end, temp_start, len);
+    if let Some(results) = &output.results {
+        results.lock().unwrap().push((info.name, fingerprint(&rows)));
     }
-    m
+    if output.collation != Collation::Bytes {
+        format::collate(&mut rows, output.collation);
+    }
+    format::sort_rows(&mut rows, output.sort_by);
+    // of all the stations of the result, whatever part of it is written
+    if let Some((format, path)) = &output.counts_output {
+        let mut w = BufWriter::new(File::create(path)?);
+        format::write_counts(&mut w, &format::counts(&rows), *format)?;
+        w.flush()?;
+    }
+    if let Some(served) = &output.served {
+        let mut stations = Vec::new();
+        write_rows(&mut stations, &rows, Format::Json, output, info, false)?;
+        *served.write().unwrap() = Some(Served::new(stations, &rows)?);
+    }
+    if let Some(n) = output.head {
+        rows.truncate(n);
+    }
+    if let Some(n) = output.tail {
+        rows.drain(..rows.len().saturating_sub(n));
+    }
+    match &output.split_output {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            for (row, name) in rows.iter().zip(format::file_names(&rows)) {
+                let path = dir.join(name).with_extension(output.format.extension());
+                write_file(&path, std::slice::from_ref(row), output.format, output, info)?;
+            }
+        }
+        None => match &output.path {
+            Some(path) => write_file(path, &rows, output.format, output, info)?,
+            None => {
+                // anstream strips the colors when they are disabled or stdout is not a terminal
+                let mut w = anstream::stdout();
+                write_rows(&mut w, &rows, output.format, output, info, true)?;
+                w.flush()?;
+            }
+        },
+    }
+    // the same rows again, for the consumers of the other formats
+    for (format, path) in &output.tee {
+        write_file(path, &rows, *format, output, info)?;
+    }
+    if !output.exclude.is_empty() {
+        eprintln!("Excluded {}: {} stations", info.name, excluded);
+    }
+    if output.extremes {
+        if let Some(e) = format::extremes(&m, output.scale) {
+            eprintln!("Extremes {}: hottest {} (max {:.p$}), coldest {} (min {:.p$})", info.name, e.hottest, e.max, e.coldest, e.min, p = output.scale.decimals() as usize);
+        }
+    }
+    info.mark_output();
+    if output.errors.is_some_and(|log| report_errors(log, info.name)) {
+        output.invalid.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// prints the invalid records collected since the last report to stderr, returns whether there were any
+fn report_errors(log: &ErrorLog, name: &str) -> bool {
+    let (errors, count) = log.take();
+    if count == 0 {
+        return false;
+    }
+    match count > errors.len() {
+        true => eprintln!("Invalid records {}: {}, the first {} found:", name, count, errors.len()),
+        false => eprintln!("Invalid records {}: {}", name, count),
+    }
+    for e in errors {
+        eprintln!("{}", e);
+    }
+    true
+}
+
+// hash of the statistics of the rows, equal for equal results
+fn fingerprint(rows: &[Row]) -> u64 {
+    let mut h = DefaultHasher::new();
+    for r in rows {
+        (r.station, r.data, r.histogram, r.offsets, r.first_last).hash(&mut h);
+    }
+    h.finish()
+}
+
+// writes the rows to a new file, the runs are appended to SQLite databases instead
+fn write_file(path: &Path, rows: &[Row], format: Format, output: &Output, info: &RunInfo) -> Result<(), Error> {
+    #[cfg(feature = "sqlite")]
+    if let Format::Sqlite = format {
+        let generated_at = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let total = output.total.then(|| Total::of(rows)).flatten();
+        format::write_sqlite(path, rows, &run_meta(output, info, &generated_at), total.as_ref())?;
+        return Ok(());
+    }
+    let mut w = BufWriter::new(File::create(path)?);
+    write_rows(&mut w, rows, format, output, info, false)?;
+    w.flush()
 }
 
-fn process_record<'a>(data: &'a [u8], m: &mut HashMap<&'a str, StationData>, station_start: usize, station_end: usize, temp_start: usize, temp_end: usize) {
-    let station: &str = std::str::from_utf8(&data[station_start..station_end]).expect("Invalid UTF-8 sequence");
-    // TODO use a custom temperature parser
-    let temp: &str = std::str::from_utf8(&data[temp_start..temp_end]).expect("Invalid UTF-8 sequence");
-    let temp: f64 = temp.parse().expect(temp);
-    m.entry(station).and_modify(|e| {
-        if temp > e.max_temp {
-            e.max_temp = temp;
+// writes the rows in the format
+fn write_rows<W: Write + Send>(w: &mut W, rows: &[Row], format: Format, output: &Output, info: &RunInfo, highlight: bool) -> Result<(), Error> {
+    let total = output.total.then(|| Total::of(rows)).flatten();
+    let total = total.as_ref();
+    match format {
+        Format::Brace => format::write_brace(w, rows, highlight)?,
+        Format::Plain => format::write_plain(w, rows)?,
+        Format::Json if output.metadata => {
+            let generated_at = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+            // the temperatures are always in degrees Celsius
+            let meta = JsonMeta { unit: "celsius", generated_at: &generated_at, collation: output.collation.name() };
+            format::write_json_document(w, rows, Some(&meta), total)?
         }
-        if temp < e.min_temp {
-            e.min_temp = temp;
+        Format::Json if total.is_some() => format::write_json_document(w, rows, None, total)?,
+        Format::Json => format::write_json(w, rows)?,
+        // the summary line is the total of NDJSON
+        Format::Ndjson => format::write_ndjson(w, rows, output.summary || output.total)?,
+        Format::Yaml => format::write_yaml(w, rows, total)?,
+        Format::Csv => format::write_csv(w, rows, output.histogram.as_ref(), total)?,
+        Format::Markdown => format::write_markdown(w, rows)?,
+        Format::Table => format::write_table(w, rows, output.max_station_width)?,
+        Format::Array => format::write_json_array(w, rows)?,
+        Format::Template => format::write_template(w, rows, output.template.as_ref().expect("a template"))?,
+        // the report has a summary table of its own
+        Format::Html => {
+            let generated_at = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+            format::write_html(w, rows, &run_meta(output, info, &generated_at))?
         }
-        e.sum_temp += temp;
-        e.n += 1;
+        Format::Prometheus => format::write_prometheus(w, rows, info.duration, total)?,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => format::write_parquet(w, rows, total)?,
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => return Err(Error::new(ErrorKind::InvalidInput, "SQLite results can only be written to a database with --output or --tee")),
+    }
+    if let (Format::Brace | Format::Plain | Format::Markdown | Format::Table, Some(total)) = (format, total) {
+        if let Format::Markdown = format {
+            writeln!(w)?;
+        }
+        format::write_total_line(w, total)?;
+    }
+    Ok(())
+}
+
+fn run_meta<'a>(output: &'a Output, info: &RunInfo, generated_at: &'a str) -> RunMeta<'a> {
+    RunMeta {
+        input: &output.input,
+        bytes: info.bytes_total as u64,
+        duration: info.duration,
+        threads: info.threads,
+        implementation: info.name,
+        generated_at,
+    }
+}
+
+// the remaining implementations are not run once cancelled
+fn exit_if_cancelled(cancel: &Cancel) {
+    if let Some(reason) = cancel.reason() {
+        process::exit(exit_code(reason));
     }
-    ).or_insert(StationData {
-        min_temp: temp,
-        max_temp: temp,
-        sum_temp: temp,
-        n: 1,
-    });
 }
 
-fn print_result<P: AsRef<str> + Display>(m: &HashMap<P, StationData>) {
-    let list: Vec<String> = m.iter()
-        .map(|(station, station_data)| format!("{}={:.1}/{:.1}/{:.1}", station, station_data.min_temp, station_data.sum_temp / station_data.n as f64, station_data.max_temp))
+fn exit_code(reason: CancelReason) -> i32 {
+    match reason {
+        CancelReason::Timeout => EXIT_TIMEOUT,
+        CancelReason::Interrupted => EXIT_INTERRUPTED,
+    }
+}
+
+fn print_duration(info: &RunInfo, output: &Output) {
+    if info.cancelled.is_none() {
+        output.timings.lock().unwrap().push((info.name, info.cold == Some(true), info.duration, info.bytes_total));
+    }
+    if let Some(reason) = info.cancelled {
+        let reason = match reason {
+            CancelReason::Timeout => "timed out",
+            CancelReason::Interrupted => "interrupted",
+        };
+        println!("Duration {} (PARTIAL result, {} after processing {} of {} bytes): {:?}", info.name, reason, info.bytes_processed, info.bytes_total, info.duration);
+    } else {
+        let cache = match info.cold {
+            Some(true) => " (cold cache)",
+            Some(false) => " (warm cache)",
+            None => "",
+        };
+        let readahead = if info.readahead.is_some() { " (readahead)" } else { "" };
+        println!("Duration {}{}{}: {:?}", info.name, cache, readahead, info.duration);
+    }
+    if let Some(r) = info.readahead {
+        println!("Readahead {}: {} bytes ahead of the parsers, {} pages touched", info.name, r.lead, r.pages);
+    }
+    if info.files != 1 {
+        println!("Files {}: {}", info.name, info.files);
+    }
+    let stages: Vec<String> = info.stages.stages.iter()
+        .map(|(name, d)| format!("{} {:?} ({:.1}%)", name, d, d.as_secs_f64() * 100.0 / info.duration.as_secs_f64()))
         .collect();
-    println!("{{{}}}", list.join(", "));
+    println!("Stages {}: {}", info.name, stages.join(", "));
+    for (i, w) in info.workers.iter().enumerate() {
+        println!("Worker {} {}: {} slices, {} bytes, {} rows, parse {:?}", i, info.name, w.slices, w.bytes, w.rows, w.parse_time);
+    }
+    let busy = info.workers.iter().map(|w| w.parse_time);
+    if let (Some(fastest), Some(slowest)) = (busy.clone().min(), busy.max()) {
+        println!("Worker skew {}: fastest {:?}, slowest {:?} (+{:.1}%)", info.name, fastest, slowest,
+                 (slowest.as_secs_f64() / fastest.as_secs_f64() - 1.0) * 100.0);
+    }
+    if let Some(perf) = info.perf {
+        println!("Perf counters {}: {}", info.name, perf);
+    }
+    // on stderr, so that the result on stdout stays the same
+    if let Some(h) = &info.hash {
+        eprintln!("Hash stats {}: {} maps, {} entries in {} buckets (load factor {:.2}), {} grows, {} hashes, {} collisions",
+                  info.name, h.maps, h.entries, h.buckets, h.load_factor(), h.grows, h.hashes, h.collisions);
+        for stations in &h.top_buckets {
+            eprintln!("Hash stats {}: bucket of {} stations: {}", info.name, stations.len(), stations.join(", "));
+        }
+    }
+}
+
+// the counters are optional, a warning is printed when they are unavailable
+fn start_perf_counters() -> Option<PerfCounters> {
+    PerfCounters::start().map_err(|e| eprintln!("Warning: performance counters are unavailable: {}", e)).ok()
+}
+
+fn stop_perf_counters(perf: Option<PerfCounters>) -> Option<PerfCounts> {
+    perf?.stop().map_err(|e| eprintln!("Warning: failed to read the performance counters: {}", e)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn aggregate_requests() {
+        use rust_1brc::http::Request;
+        let errors: &'static ErrorLog = Box::leak(Box::new(ErrorLog::new(1)));
+        let request = |method: &str, path: &str, body: &[u8]| Request { method: method.to_owned(), path: path.to_owned(), body: body.to_vec() };
+        let response = handle_request(&request("POST", "/aggregate", b"Oslo;-3.4\nRome;15.0\nOslo;12.5"), errors);
+        assert_eq!((response.status, response.content_type), (200, "application/json"));
+        assert_eq!(String::from_utf8(response.body).unwrap(),
+                   "{\"Oslo\":{\"min\":-3.4,\"mean\":4.6,\"max\":12.5,\"count\":2},\"Rome\":{\"min\":15.0,\"mean\":15.0,\"max\":15.0,\"count\":1}}\n");
+        let response = handle_request(&request("POST", "/aggregate?strict", b"Oslo;-3.4\nRome;hot\n"), errors);
+        assert_eq!(response.status, 400);
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.starts_with("Invalid records: 1, the first one: Invalid temperature hot at line 2"), "{}", body);
+        // the log is empty again for the next request
+        assert_eq!(handle_request(&request("POST", "/aggregate", b""), errors).body, b"{}\n");
+        assert_eq!(handle_request(&request("GET", "/aggregate", b""), errors).status, 405);
+        assert_eq!(handle_request(&request("POST", "/", b""), errors).status, 404);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn results_are_served() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancel = Arc::new(Cancel::default());
+        let (served, server) = serve_results(listener, &cancel).unwrap();
+        // the status and the body of the response
+        let get = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            (head.split(' ').nth(1).unwrap().parse::<u16>().unwrap(), body.to_owned())
+        };
+        assert_eq!(get("/healthz"), (200, "OK\n".to_owned()));
+        assert_eq!(get("/stations").0, 503);
+
+        let m: HashMap<&str, StationData> = [("Oslo", -40), ("São Paulo", 199)].into_iter().map(|(station, temp)| (station, StationData::new(temp))).collect();
+        let rows = format::rows(&m);
+        let mut stations = Vec::new();
+        format::write_json(&mut stations, &rows).unwrap();
+        *served.write().unwrap() = Some(Served::new(stations, &rows).unwrap());
+        assert_eq!(get("/stations"), (200, "{\"Oslo\":{\"min\":-4.0,\"mean\":-4.0,\"max\":-4.0,\"count\":1},\"São Paulo\":{\"min\":19.9,\"mean\":19.9,\"max\":19.9,\"count\":1}}\n".to_owned()));
+        assert_eq!(get("/stations/S%C3%A3o%20Paulo?pretty"), (200, "{\"station\":\"São Paulo\",\"min\":19.9,\"mean\":19.9,\"max\":19.9,\"count\":1}\n".to_owned()));
+        assert_eq!(get("/stations/Paris").0, 404);
+        assert_eq!(get("/stations/%zz").0, 400);
+        assert_eq!(get("/results").0, 404);
+
+        cancel.cancel(CancelReason::Interrupted);
+        server.join().unwrap().unwrap();
+        assert!(std::net::TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn queries() {
+        let m: HashMap<&str, StationData> = [("Oslo", -40), ("Paris", 120), ("Rome", 150)]
+            .into_iter()
+            .map(|(station, temp)| (station, StationData::new(temp)))
+            .collect();
+        let input = "get Paris\nget Berlin\n\ntop 2\nbottom 1 mean\ntop x\ncount\nquit\nget Oslo\n";
+        let mut out: Vec<u8> = Vec::new();
+        answer_queries(input.as_bytes(), &mut out, &format::rows(&m)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Paris=12.0/12.0/12.0\nUnknown station Berlin\nRome=15.0/15.0/15.0\nParis=12.0/12.0/12.0\n\
+            Oslo=-4.0/-4.0/-4.0\nUsage: top N [min|mean|max|count]\n3 stations, 3 measurements\n");
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches(b"*.txt", b"2024-01-01.txt"));
+        assert!(glob_matches(b"??.txt", b"01.txt"));
+        assert!(glob_matches(b"*", b""));
+        assert!(!glob_matches(b"*.txt", b"measurements.txt.gz"));
+        assert!(!glob_matches(b"?.txt", b".txt"));
+    }
+
+    #[test]
+    fn directories_are_expanded() {
+        let dir = std::env::temp_dir().join(format!("rust-1brc-dir-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        for name in ["b.txt", "a.txt", "c.csv", "sub/d.txt"] {
+            fs::write(dir.join(name), "Hamburg;12.0\n").unwrap();
+        }
+        let flat = expand_inputs(std::slice::from_ref(&dir), false, Some("*.txt"));
+        let recursive = expand_inputs(&[dir.clone(), dir.join("c.csv")], true, Some("*.txt"));
+        let empty = expand_inputs(&[dir.join("empty")], true, None);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(flat.unwrap(), vec![dir.join("a.txt"), dir.join("b.txt")]);
+        assert_eq!(recursive.unwrap(), vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("sub/d.txt"), dir.join("c.csv")]);
+        assert!(empty.unwrap().is_empty());
+    }
+
+    #[test]
+    fn baseline_regressions() {
+        let baseline = |threads: usize, millis: [f64; 3]| Baseline {
+            input: "measurements.txt".to_owned(),
+            bytes: 1000,
+            threads,
+            os: "linux".to_owned(),
+            arch: "x86_64".to_owned(),
+            modes: vec![ModeTimings { name: "parallel mmap read".to_owned(), durations: millis.map(|ms| ms / 1000.0).to_vec(), throughput: 0.0 }],
+        };
+        let saved = baseline(4, [100.0, 90.0, 300.0]);
+        assert_eq!(saved.modes[0].median(), Duration::from_millis(100));
+        assert!(compare_baselines(&saved, &baseline(4, [105.0, 500.0, 104.0]), 10.0, false).unwrap());
+        assert!(!compare_baselines(&saved, &baseline(4, [120.0, 115.0, 90.0]), 10.0, false).unwrap());
+        assert!(compare_baselines(&saved, &baseline(8, [100.0, 100.0, 100.0]), 10.0, false).is_err());
+        assert!(compare_baselines(&saved, &baseline(8, [100.0, 100.0, 100.0]), 10.0, true).unwrap());
+    }
+
+    #[test]
+    fn methods_differ_at_the_first_station() {
+        let m1: HashMap<&str, StationData> = HashMap::from([("Oslo", StationData::new(10)), ("Abha", StationData::new(200)), ("Rome", StationData::new(5))]);
+        let mut m2 = m1.clone();
+        assert_eq!(first_difference(&m1, &m2), None);
+        m2.remove("Rome");
+        m2.get_mut("Oslo").unwrap().n = 2;
+        assert_eq!(first_difference(&m1, &m2), Some(("Oslo", Some(&StationData::new(10)), Some(&StationData { n: 2, ..StationData::new(10) }))));
+        m2.insert("Bern", StationData::new(0));
+        assert_eq!(first_difference(&m1, &m2).map(|(s, _, d)| (s, d.is_some())), Some(("Bern", true)));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("8192"), Ok(8192));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("1M"), Ok(1 << 20));
+        assert_eq!(parse_size("2g"), Ok(2 << 30));
+        for s in ["", "0", "M", "1.5M", "-1K"] {
+            assert!(parse_size(s).is_err(), "{}", s);
+        }
+        assert_eq!(parse_offset("0"), Ok(0));
+        assert_eq!(parse_offset("3K"), Ok(3 << 10));
+    }
+
+    #[test]
+    fn sample_rates() {
+        assert_eq!((parse_rate("0.01"), parse_rate("1")), (Ok(0.01), Ok(1.0)));
+        for s in ["0", "-0.5", "1.5", "NaN", "x"] {
+            assert!(parse_rate(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn excluded_names_are_normalized_like_the_stations() {
+        let path = std::env::temp_dir().join(format!("rust-1brc-exclude-{}.txt", std::process::id()));
+        fs::write(&path, "Placeholder 1\r\n\nHAMBURG \n").unwrap();
+        let names = ["Oslo".to_owned()];
+        let exact = station_names(&names, Some(&path), None).unwrap();
+        assert_eq!(exact, HashSet::from(["Oslo", "Placeholder 1", "HAMBURG "].map(str::to_owned)));
+        let keys = KeyNormalization { trim: true, case_fold: true, nfc: false };
+        let normalized = station_names(&names, Some(&path), Some(keys)).unwrap();
+        assert_eq!(normalized, HashSet::from(["oslo", "placeholder 1", "hamburg"].map(str::to_owned)));
+        fs::remove_file(&path).unwrap();
+        assert!(station_names(&names, Some(&path), None).is_err());
+    }
+
+    #[test]
+    fn comparison_table() {
+        let modes = [("simple file read", 2.0), ("parallel mmap read", 0.5), ("parallel mmap read (dense IDs)", 4.0)]
+            .map(|(name, d)| ModeTimings { name: name.to_owned(), durations: vec![d], throughput: 100.0 / d });
+        let baseline = Baseline { input: String::new(), bytes: 100, threads: 4, os: String::new(), arch: String::new(), modes: modes.into() };
+        let rows = comparison(&baseline);
+        let speedups: Vec<(f64, f64)> = rows.iter().map(|r| (r.speedup_vs_slowest, r.speedup_vs_simple)).collect();
+        assert_eq!(speedups, [(2.0, 1.0), (8.0, 4.0), (1.0, 0.5)]);
+
+        assert!(check_results_agree(&[("simple file read", 1), ("parallel mmap read", 1)]).is_ok());
+        let e = check_results_agree(&[("simple file read", 1), ("parallel mmap read", 1), ("parallel mmap read (dense IDs)", 2)]).unwrap_err();
+        assert_eq!(e.to_string(), "The result of the parallel mmap read (dense IDs) differs from the result of the simple file read");
+    }
+
+    #[test]
+    fn followed_reads_end_after_the_last_newline() {
+        let mut data = io::Cursor::new(b"Oslo;1.0\nRome;2.0\nLi".to_vec());
+        assert_eq!(complete_lines_end(&mut data, 0, 20).unwrap(), 18);
+        assert_eq!(complete_lines_end(&mut data, 18, 20).unwrap(), 18);
+        assert_eq!(complete_lines_end(&mut data, 0, 9).unwrap(), 9);
+        assert_eq!(complete_lines_end(&mut data, 0, 8).unwrap(), 0);
+        // the newline is further back than a block
+        let mut data = io::Cursor::new([b"Oslo;1.0\n".as_slice(), &[b'x'; 100 << 10]].concat());
+        let size = data.get_ref().len() as u64;
+        assert_eq!(complete_lines_end(&mut data, 0, size).unwrap(), 9);
+    }
+
+    #[test]
+    fn checkpoint_header_is_validated() {
+        let path = std::env::temp_dir().join(format!("rust-1brc-checkpoint-header-{}.ckpt", process::id()));
+        let m = HashMap::from([("Hamburg", StationData::new(120)), ("Bulawayo", StationData::new(89))]);
+        write_checkpoint(&path, 42, &m).unwrap();
+        let arena = Bump::new();
+        let c = read_checkpoint::<StationData>(&path, &mut Interner::new(&arena)).unwrap();
+        assert_eq!((c.offset, c.stations), (42, m));
+
+        let mut bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[..12], b"1BRCCKPT\0\0\0\x01");
+        bytes[11] = 2;
+        fs::write(&path, &bytes).unwrap();
+        let e = read_checkpoint::<StationData>(&path, &mut Interner::new(&arena)).err().unwrap();
+        assert!(e.to_string().ends_with("Checkpoint format version 2 is not supported, expected version 1"), "{}", e);
+        fs::write(&path, &bytes[12..]).unwrap();
+        let e = read_checkpoint::<StationData>(&path, &mut Interner::new(&arena)).err().unwrap();
+        assert!(e.to_string().ends_with("Not a checkpoint, the header is missing"), "{}", e);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tee_targets() {
+        assert!(matches!(parse_tee("json=out.json"), Ok((Format::Json, p)) if p == Path::new("out.json")));
+        assert!(matches!(parse_tee("TEXT=a=b.txt"), Ok((Format::Brace, p)) if p == Path::new("a=b.txt")));
+        for s in ["json", "json=", "xml=out.xml"] {
+            assert!(parse_tee(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn resume_from_checkpoint_matches_full_run() {
+        let data = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\nPalembang;38.8\n".repeat(10_000);
+        let options = ParseOptions::default();
+        let cancel = Cancel::default();
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let (full, _) = read_stations_data(data.as_bytes(), &MinMeanMax, &mut interner, HashMap::new(), options, &cancel, |_, _| {});
+
+        let path = std::env::temp_dir().join(format!("rust-1brc-checkpoint-{}.json", process::id()));
+        let mut saved = false;
+        let _ = read_stations_data(data.as_bytes(), &MinMeanMax, &mut interner, HashMap::new(), options, &cancel, |m, offset| {
+            if offset > data.len() / 3 && !saved {
+                write_checkpoint(&path, offset, m).unwrap();
+                saved = true;
+            }
+        });
+        let resumed_arena = Bump::new();
+        let mut resumed_interner = Interner::new(&resumed_arena);
+        let c = read_checkpoint::<StationData>(&path, &mut resumed_interner).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(c.offset > 0 && c.offset < data.len());
+        let (resumed, bytes_read) = read_stations_data(&data.as_bytes()[c.offset..], &MinMeanMax, &mut resumed_interner, c.stations, options, &cancel, |_, _| {});
+        assert_eq!(c.offset + bytes_read, data.len());
+
+        assert_eq!(full.len(), resumed.len());
+        for (station, d) in &full {
+            let r = &resumed[station];
+            assert_eq!((d.min_temp, d.max_temp, d.sum_temp, d.n), (r.min_temp, r.max_temp, r.sum_temp, r.n));
+        }
+    }
 }
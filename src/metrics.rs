@@ -0,0 +1,165 @@
+//! Records with several numeric columns after the station, like `station;temperature;humidity;pressure`,
+//! aggregated into the min/mean/max of every column in one pass.
+//!
+//! The values are parsed like the temperatures, in tenths with one or no decimal and within the range of an `i16`.
+//! Records with a single value are the `station;temperature` records the readers of [`crate::read`] aggregate faster.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use memchr::memchr;
+use rayon::prelude::*;
+
+use crate::parse::{parse, ParseOptions};
+use crate::read::{invalid_record, lines_before, Invalid};
+use crate::{Aggregator, Cancel, MinMeanMax, StationData};
+
+/// Statistics of the metrics of a station, in the order of the columns.
+pub type StationMetrics = Vec<StationData>;
+
+/// Aggregates the records of the data, which have `metrics` values after the station.
+///
+/// The records with another number of values are invalid, reported or skipped like the invalid temperatures,
+/// see [`ParseOptions`]. The station names cannot contain the delimiter.
+pub fn read_metrics_slice(data: &[u8], metrics: usize, options: ParseOptions) -> HashMap<&str, StationMetrics> {
+    let mut m = HashMap::new();
+    aggregate_metrics(data, metrics, &|| 0, &mut m, options);
+    m
+}
+
+/// Aggregates the slices in parallel like [`read_metrics_slice`], returns the merged map and the number of bytes processed.
+///
+/// Slices that have not been started when `cancel` is triggered are skipped.
+pub fn read_metrics_parallel<'a>(slices: &[&'a [u8]], metrics: usize, options: ParseOptions, cancel: &Cancel) -> (HashMap<&'a str, StationMetrics>, usize) {
+    slices
+        .par_iter()
+        .enumerate()
+        .fold(|| (HashMap::new(), 0), |(mut m, bytes), (i, slice)| {
+            if cancel.is_cancelled() {
+                return (m, bytes);
+            }
+            aggregate_metrics(slice, metrics, &|| lines_before(slices, i), &mut m, options);
+            (m, bytes + slice.len())
+        })
+        .reduce(|| (HashMap::new(), 0), |(mut m1, bytes1), (m2, bytes2)| {
+            merge_metrics(&mut m1, m2);
+            (m1, bytes1 + bytes2)
+        })
+}
+
+/// Merges the statistics of `m2` into `m1`, metric by metric.
+pub fn merge_metrics<'a>(m1: &mut HashMap<&'a str, StationMetrics>, m2: HashMap<&'a str, StationMetrics>) {
+    for (station, metrics) in m2 {
+        match m1.entry(station) {
+            Entry::Occupied(mut e) => {
+                for (s, other) in e.get_mut().iter_mut().zip(metrics) {
+                    MinMeanMax.merge(s, other);
+                }
+            }
+            Entry::Vacant(e) => {
+                e.insert(metrics);
+            }
+        }
+    }
+}
+
+fn aggregate_metrics<'a>(data: &'a [u8], metrics: usize, lines_before: &dyn Fn() -> usize, m: &mut HashMap<&'a str, StationMetrics>, options: ParseOptions) {
+    let mut values = vec![0; metrics];
+    let mut start: usize = 0;
+    while start < data.len() {
+        let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+        match parse_metrics(&data[start..end], &mut values, options) {
+            Ok(Some(station)) => match m.get_mut(station) {
+                Some(stats) => {
+                    for (s, &v) in stats.iter_mut().zip(&values) {
+                        MinMeanMax.observe(s, v);
+                    }
+                }
+                None => {
+                    m.insert(station, values.iter().map(|&v| MinMeanMax.init(v)).collect());
+                }
+            },
+            Ok(None) => {}
+            Err(e) => invalid_record(data, e, lines_before, options),
+        }
+        start = end + 1;
+    }
+}
+
+// the station of the record with its values stored in `values`,
+// `None` for blank lines, records without a station name and invalid records with `options.lenient`
+fn parse_metrics<'a>(l: &'a [u8], values: &mut [i32], options: ParseOptions) -> Result<Option<&'a str>, Invalid> {
+    let l: &[u8] = l.strip_suffix(b"\r").unwrap_or(l);
+    if l.is_empty() {
+        return Ok(None);
+    }
+    let mut fields = l.split(|&b| b == b';');
+    let station = fields.next().unwrap_or_default();
+    let mut n: usize = 0;
+    for field in fields {
+        if n == values.len() {
+            n += 1;
+            break;
+        }
+        match parse(field, options) {
+            Some(v) => values[n] = v,
+            None if options.lenient => return Ok(None),
+            None => return Err(Invalid::new(format!("Invalid value {}", String::from_utf8_lossy(field)), field)),
+        }
+        n += 1;
+    }
+    if n != values.len() {
+        return match options.lenient {
+            true => Ok(None),
+            false => Err(Invalid::new(format!("Malformed record, expected {} values after the station", values.len()), &l[l.len()..])),
+        };
+    }
+    if station.is_empty() {
+        return Ok(None);
+    }
+    match std::str::from_utf8(station) {
+        Ok(station) => Ok(Some(station)),
+        Err(_) if options.lenient => Ok(None),
+        Err(e) => Err(Invalid::new(format!("Invalid UTF-8 sequence: {}", e), &station[e.valid_up_to()..])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::*;
+    use crate::read::slice_sized;
+
+    #[test]
+    fn every_column_is_aggregated() {
+        let data = b"Oslo;-3.4;80;1013.2\nRome;15.0;40.5;1009.0\r\nOslo;12.5;60;1021.8\n\n;1.0;2.0;3.0\n";
+        let m = read_metrics_slice(data, 3, ParseOptions::default());
+        assert_eq!(m.len(), 2);
+        let oslo = &m["Oslo"];
+        assert_eq!((oslo[0].min_temp, oslo[0].max_temp, oslo[0].sum_temp, oslo[0].n), (-34, 125, 91, 2));
+        assert_eq!((oslo[1].min_temp, oslo[1].max_temp, oslo[2].min_temp, oslo[2].max_temp), (600, 800, 10132, 10218));
+        assert_eq!(m["Rome"], vec![StationData::new(150), StationData::new(405), StationData::new(10090)]);
+
+        let data = data.repeat(50);
+        let slices = slice_sized(&data, 64);
+        let (parallel, bytes) = read_metrics_parallel(&slices, 3, ParseOptions::default(), &Cancel::default());
+        assert_eq!(parallel, read_metrics_slice(&data, 3, ParseOptions::default()));
+        assert_eq!(bytes, slices.iter().map(|s| s.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn wrong_field_counts_are_invalid() {
+        let message = |data: &[u8]| {
+            let panic = catch_unwind(AssertUnwindSafe(|| read_metrics_slice(data, 2, ParseOptions::default()))).unwrap_err();
+            panic.downcast_ref::<String>().unwrap().lines().next().unwrap().to_owned()
+        };
+        assert_eq!(message(b"Oslo;1.0;2.0\nOslo;1.0\n"), "Malformed record, expected 2 values after the station at line 2, column 9:");
+        assert_eq!(message(b"Oslo;1.0;2.0;3.0\n"), "Malformed record, expected 2 values after the station at line 1, column 17:");
+        assert_eq!(message(b"Oslo;1.0;wet\n"), "Invalid value wet at line 1, column 10:");
+
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        let m = read_metrics_slice(b"Oslo;1.0\nOslo;1.0;2.0;3.0\nOslo;x;2.0\nOslo;1.0;2.0\n", 2, lenient);
+        assert_eq!(m["Oslo"], vec![StationData::new(10), StationData::new(20)]);
+    }
+}
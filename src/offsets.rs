@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Aggregator, MinMeanMax, StationData};
+
+/// [`Aggregator`] computing the min/mean/max of every station together with the byte offsets of the records
+/// that set its min and max, to find suspicious extremes in the input.
+///
+/// The offsets are those passed to [`Aggregator::init_at`] and [`Aggregator::observe_at`], the offsets of the
+/// records in the file for the readers of a single file. If several records have the same min or max,
+/// the offset of the first one in the input is kept, whatever order the parts of the input are merged in.
+pub struct TrackExtremes;
+
+/// Byte offsets of the records with the min and the max temperature of a station.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExtremeOffsets {
+    pub min: u64,
+    pub max: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TrackedStationData {
+    pub data: StationData,
+    pub offsets: ExtremeOffsets,
+}
+
+impl Aggregator for TrackExtremes {
+    type State = TrackedStationData;
+
+    // the readers always pass the offsets, without them the records are treated as being at the start of the input
+    fn init(&self, temp: i32) -> TrackedStationData {
+        self.init_at(temp, 0)
+    }
+
+    fn observe(&self, e: &mut TrackedStationData, temp: i32) {
+        self.observe_at(e, temp, 0)
+    }
+
+    fn init_at(&self, temp: i32, offset: u64) -> TrackedStationData {
+        TrackedStationData { data: MinMeanMax.init(temp), offsets: ExtremeOffsets { min: offset, max: offset } }
+    }
+
+    fn observe_at(&self, e: &mut TrackedStationData, temp: i32, offset: u64) {
+        // a worker does not necessarily see its slices in the order of the input
+        let (min, max) = (e.data.min_temp as i32, e.data.max_temp as i32);
+        if temp < min || (temp == min && offset < e.offsets.min) {
+            e.offsets.min = offset;
+        }
+        if temp > max || (temp == max && offset < e.offsets.max) {
+            e.offsets.max = offset;
+        }
+        MinMeanMax.observe(&mut e.data, temp);
+    }
+
+    fn merge(&self, e: &mut TrackedStationData, other: TrackedStationData) {
+        let (min, max) = (e.data.min_temp, e.data.max_temp);
+        if other.data.min_temp < min || (other.data.min_temp == min && other.offsets.min < e.offsets.min) {
+            e.offsets.min = other.offsets.min;
+        }
+        if other.data.max_temp > max || (other.data.max_temp == max && other.offsets.max < e.offsets.max) {
+            e.offsets.max = other.offsets.max;
+        }
+        MinMeanMax.merge(&mut e.data, other.data);
+    }
+}
+
+/// [`Aggregator`] computing the min/mean/max of every station together with its first and last temperature
+/// in the input, the start and the end of the measurement window of append-ordered files.
+///
+/// The first and the last records are those with the lowest and the highest offset passed to
+/// [`Aggregator::init_at`] and [`Aggregator::observe_at`], so the parts of the input can be merged in any order.
+pub struct TrackFirstLast;
+
+/// The first and the last temperature of a station in tenths of a degree, with the byte offsets of their records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FirstLast {
+    pub first: i16,
+    pub first_offset: u64,
+    pub last: i16,
+    pub last_offset: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FirstLastStationData {
+    pub data: StationData,
+    pub first_last: FirstLast,
+}
+
+impl Aggregator for TrackFirstLast {
+    type State = FirstLastStationData;
+
+    // without the offsets, the records are taken to be in the order they are observed in
+    fn init(&self, temp: i32) -> FirstLastStationData {
+        self.init_at(temp, 0)
+    }
+
+    fn observe(&self, e: &mut FirstLastStationData, temp: i32) {
+        self.observe_at(e, temp, e.first_last.last_offset)
+    }
+
+    fn init_at(&self, temp: i32, offset: u64) -> FirstLastStationData {
+        let first_last = FirstLast { first: temp as i16, first_offset: offset, last: temp as i16, last_offset: offset };
+        FirstLastStationData { data: MinMeanMax.init(temp), first_last }
+    }
+
+    fn observe_at(&self, e: &mut FirstLastStationData, temp: i32, offset: u64) {
+        // a worker does not necessarily see its slices in the order of the input
+        let f = &mut e.first_last;
+        if offset < f.first_offset {
+            (f.first, f.first_offset) = (temp as i16, offset);
+        }
+        if offset >= f.last_offset {
+            (f.last, f.last_offset) = (temp as i16, offset);
+        }
+        MinMeanMax.observe(&mut e.data, temp);
+    }
+
+    fn merge(&self, e: &mut FirstLastStationData, other: FirstLastStationData) {
+        let (f, o) = (&mut e.first_last, other.first_last);
+        if o.first_offset < f.first_offset {
+            (f.first, f.first_offset) = (o.first, o.first_offset);
+        }
+        if o.last_offset > f.last_offset {
+            (f.last, f.last_offset) = (o.last, o.last_offset);
+        }
+        MinMeanMax.merge(&mut e.data, other.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_of_the_first_extremes_are_kept() {
+        let mut a = TrackExtremes.init_at(10, 100);
+        TrackExtremes.observe_at(&mut a, -5, 120);
+        TrackExtremes.observe_at(&mut a, 30, 140);
+        TrackExtremes.observe_at(&mut a, -5, 160);
+        assert_eq!(a.offsets, ExtremeOffsets { min: 120, max: 140 });
+
+        // a part of the input before `a` with the same min and a lower max
+        let mut b = TrackExtremes.init_at(-5, 10);
+        TrackExtremes.observe_at(&mut b, 20, 20);
+        TrackExtremes.merge(&mut a, b);
+        assert_eq!(a.offsets, ExtremeOffsets { min: 10, max: 140 });
+        assert_eq!((a.data.min_temp, a.data.max_temp, a.data.n), (-5, 30, 6));
+    }
+
+    #[test]
+    fn first_and_last_records_are_kept() {
+        let mut a = TrackFirstLast.init_at(10, 100);
+        TrackFirstLast.observe_at(&mut a, -5, 140);
+        TrackFirstLast.observe_at(&mut a, 30, 120);
+        assert_eq!(a.first_last, FirstLast { first: 10, first_offset: 100, last: -5, last_offset: 140 });
+
+        // a part of the input before `a`, merged after it
+        let mut b = TrackFirstLast.init_at(-20, 10);
+        TrackFirstLast.observe_at(&mut b, 20, 20);
+        TrackFirstLast.merge(&mut a, b);
+        assert_eq!(a.first_last, FirstLast { first: -20, first_offset: 10, last: -5, last_offset: 140 });
+        assert_eq!((a.data.min_temp, a.data.max_temp, a.data.n), (-20, 30, 5));
+
+        // without the offsets, in the order of the records
+        let mut c = TrackFirstLast.init(1);
+        TrackFirstLast.observe(&mut c, 2);
+        TrackFirstLast.observe(&mut c, 3);
+        assert_eq!((c.first_last.first, c.first_last.last), (1, 3));
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use crate::read::ErrorLog;
+
+#[derive(Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Use the branchless [`parse_temp_fast`] and only fall back to [`parse_temp`] for other layouts
+    pub fast_parse: bool,
+    /// Skip records with an invalid temperature or station name instead of panicking
+    pub lenient: bool,
+    /// Fields of records with more than two fields, `None` for `station;temperature` records
+    pub columns: Option<Columns>,
+    /// Parse the slices of the parallel readers with two cursors advancing in an interleaved loop,
+    /// one in each half of the slice
+    pub dual_cursor: bool,
+    /// Look up the station of every record in the map, without the shortcut for the records of the same station
+    /// as the previous record
+    pub no_station_cache: bool,
+    /// Collect the invalid records of a strict run in the log instead of panicking at the first one, they are skipped
+    pub errors: Option<&'static ErrorLog>,
+    /// Fixed-point scale the temperatures are parsed into, tenths of a degree by default
+    pub scale: Scale,
+    /// Skip a record identical to the line right before it, byte for byte, the duplicates of a producer repeating its
+    /// records. Only consecutive duplicates are skipped, the same record elsewhere in the input is aggregated again
+    pub dedup_consecutive: bool,
+    /// Skip the records of the stations not in the set before their temperatures are parsed, `None` for all the
+    /// stations. The names are compared as they are in the records, an invalid temperature of a skipped record
+    /// is not reported
+    pub only: Option<&'static HashSet<String>>,
+}
+
+/// Fixed-point scale of the temperatures: they are kept as integers of `1 / factor` of a degree.
+///
+/// The factor is a power of ten, 10 (tenths) as in the challenge by default, 100 for inputs with hundredths.
+/// The temperatures must fit in an `i16` of the scale whatever it is, so the larger the factor, the narrower their range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scale {
+    decimals: u32,
+}
+
+impl Scale {
+    /// Tenths of a degree, the scale of the challenge.
+    pub const TENTHS: Scale = Scale { decimals: 1 };
+
+    /// Largest factor, temperatures of thousandths of a degree are already limited to ±32.767.
+    pub const MAX_FACTOR: u32 = 1000;
+
+    /// The scale of the factor, `None` if it is not a power of ten up to [`Scale::MAX_FACTOR`].
+    pub fn new(factor: u32) -> Option<Scale> {
+        let decimals = factor.checked_ilog10()?;
+        (10u32.pow(decimals) == factor && factor <= Scale::MAX_FACTOR).then_some(Scale { decimals })
+    }
+
+    pub fn factor(self) -> u32 {
+        10u32.pow(self.decimals)
+    }
+
+    /// Number of decimals of the temperatures in the scale.
+    pub fn decimals(self) -> u32 {
+        self.decimals
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::TENTHS
+    }
+}
+
+/// 0-based indices of the station and the temperature among the `;` separated fields of a record.
+///
+/// Station names cannot contain the delimiter when the fields are selected, the fields that are not selected are ignored,
+/// so `Columns { station: 0, temp: 1 }` reads `Paris;12.3;sensor7` as a temperature of Paris.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Columns {
+    pub station: usize,
+    pub temp: usize,
+}
+
+pub fn parse(s: &[u8], options: ParseOptions) -> Option<i32> {
+    if options.scale != Scale::TENTHS {
+        parse_scaled(s, options.scale)
+    } else if options.fast_parse {
+        parse_temp_fast(s).or_else(|| parse_temp(s))
+    } else {
+        parse_temp(s)
+    }
+}
+
+/// Parses a temperature with one or no decimal digit (`12.3` or `12`) into tenths of a degree.
+///
+/// The sign is optional, a leading `+` is accepted like a missing sign.
+/// Temperatures that do not fit in an `i16` of tenths (beyond ±3276.7) are rejected.
+pub fn parse_temp(s: &[u8]) -> Option<i32> {
+    let (negative, digits) = match s.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        Some((b'+', rest)) => (false, rest),
+        _ => (false, s),
+    };
+    let (int_part, frac) = match digits {
+        [int_part @ .., b'.', frac] => (int_part, *frac),
+        _ => (digits, b'0'),
+    };
+    if int_part.is_empty() {
+        return None;
+    }
+    let mut value: i32 = 0;
+    for &b in int_part.iter().chain(std::iter::once(&frac)) {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((b - b'0') as i32)?;
+    }
+    if value > i16::MAX as i32 {
+        return None;
+    }
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a temperature with up to the decimals of the scale (`12.34`, `12.3` or `12` for hundredths) into the scale.
+///
+/// The sign is optional like with [`parse_temp`]. Temperatures with more decimals than the scale and those that do not
+/// fit in an `i16` of the scale are rejected.
+pub fn parse_scaled(s: &[u8], scale: Scale) -> Option<i32> {
+    let (negative, digits) = match s.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        Some((b'+', rest)) => (false, rest),
+        _ => (false, s),
+    };
+    let (int_part, frac) = match memchr::memchr(b'.', digits) {
+        Some(i) if i + 1 < digits.len() => (&digits[..i], &digits[i + 1..]),
+        Some(_) => return None,
+        None => (digits, &[][..]),
+    };
+    if int_part.is_empty() || frac.len() > scale.decimals() as usize {
+        return None;
+    }
+    let mut value: i32 = 0;
+    for &b in int_part.iter().chain(frac) {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((b - b'0') as i32)?;
+    }
+    // the missing decimals are zeros
+    value = value.checked_mul(10i32.pow(scale.decimals() - frac.len() as u32))?;
+    if value > i16::MAX as i32 {
+        return None;
+    }
+    Some(if negative { -value } else { value })
+}
+
+/// Branchless variant of [`parse_temp`] for the `[-]d[d].d` layout of the challenge.
+///
+/// Returns `None` when the input does not have that exact layout, which includes temperatures with a leading `+`.
+pub fn parse_temp_fast(s: &[u8]) -> Option<i32> {
+    let len = s.len();
+    if !(3..=5).contains(&len) || s[len - 2] != b'.' {
+        return None;
+    }
+    let negative = (s[0] == b'-') as usize;
+    // 1 if there are two integer digits, 0 otherwise
+    let two_digits = (len - negative == 4) as usize;
+    let frac = s[len - 1].wrapping_sub(b'0');
+    let ones = s[len - 3].wrapping_sub(b'0');
+    // with a single integer digit this reads the ones digit again and is multiplied away
+    let tens = s[len - 3 - two_digits].wrapping_sub(b'0');
+    if (frac > 9) | (ones > 9) | (tens > 9) | (len - negative > 4) {
+        return None;
+    }
+    let value = (tens as i32 * two_digits as i32) * 100 + ones as i32 * 10 + frac as i32;
+    let mask = -(negative as i32);
+    Some((value ^ mask) - mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_tenths(t: i32) -> String {
+        format!("{}{}.{}", if t < 0 { "-" } else { "" }, t.abs() / 10, t.abs() % 10)
+    }
+
+    #[test]
+    fn parse_temp_full_range() {
+        for t in -999..=999 {
+            let s = format_tenths(t);
+            assert_eq!(parse_temp(s.as_bytes()), Some(t), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_temp_without_decimal() {
+        assert_eq!(parse_temp(b"12"), Some(120));
+        assert_eq!(parse_temp(b"-7"), Some(-70));
+        assert_eq!(parse_temp(b"0"), Some(0));
+        assert_eq!(parse_temp(b"-3276.7"), Some(-32767));
+        for s in ["", "-", "12.", ".5", "1.23", "1a", "--1", "3276.8", "-3276.8", "99999999"] {
+            assert_eq!(parse_temp(s.as_bytes()), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_temp_fast_full_range() {
+        for t in -999..=999 {
+            let s = format_tenths(t);
+            assert_eq!(parse_temp_fast(s.as_bytes()), Some(t), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_temp_fast_rejects_other_layouts() {
+        for s in ["", "1", "12", "1.", ".5", "-.5", "123.4", "-123.4", "1.23", "a.5", "1a.5", "--1.5", "1x5"] {
+            assert_eq!(parse_temp_fast(s.as_bytes()), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn fast_parse_falls_back_to_checked_parser() {
+        let options = ParseOptions { fast_parse: true, ..Default::default() };
+        assert_eq!(parse(b"123.4", options), Some(1234));
+        assert_eq!(parse(b"-0.5", options), Some(-5));
+        assert_eq!(parse(b"abc", options), None);
+    }
+
+    #[test]
+    fn leading_plus_is_positive() {
+        for options in [ParseOptions::default(), ParseOptions { fast_parse: true, ..Default::default() }] {
+            assert_eq!(parse(b"+12.3", options), Some(123));
+            assert_eq!(parse(b"+0.5", options), Some(5));
+            assert_eq!(parse(b"+7", options), Some(70));
+            for s in ["+", "+-1.0", "-+1.0", "++1.0", "+.5"] {
+                assert_eq!(parse(s.as_bytes(), options), None, "{}", s);
+            }
+        }
+        assert_eq!(parse_temp_fast(b"+1.5"), None);
+    }
+
+    #[test]
+    fn hundredths_are_parsed_into_the_scale() {
+        let hundredths = Scale::new(100).unwrap();
+        for options in [ParseOptions { scale: hundredths, ..Default::default() }, ParseOptions { scale: hundredths, fast_parse: true, ..Default::default() }] {
+            assert_eq!(parse(b"12.34", options), Some(1234));
+            assert_eq!(parse(b"-0.05", options), Some(-5));
+            assert_eq!(parse(b"+12.3", options), Some(1230));
+            assert_eq!(parse(b"-7", options), Some(-700));
+            assert_eq!(parse(b"327.67", options), Some(32767));
+            for s in ["", "-", "12.", ".5", "1.234", "1a", "--1", "327.68", "3276.7"] {
+                assert_eq!(parse(s.as_bytes(), options), None, "{}", s);
+            }
+        }
+        assert_eq!(parse_scaled(b"-12", Scale::new(1).unwrap()), Some(-12));
+        assert_eq!(parse_scaled(b"1.5", Scale::new(1).unwrap()), None);
+        assert_eq!(parse_scaled(b"-3.2", Scale::TENTHS), parse_temp(b"-3.2"));
+    }
+
+    #[test]
+    fn scales_are_powers_of_ten() {
+        assert_eq!(Scale::default(), Scale::TENTHS);
+        assert_eq!(Scale::new(100).map(|s| (s.factor(), s.decimals())), Some((100, 2)));
+        assert_eq!(Scale::new(1).map(Scale::decimals), Some(0));
+        for factor in [0, 2, 50, 101, 10_000] {
+            assert_eq!(Scale::new(factor), None, "{}", factor);
+        }
+    }
+}
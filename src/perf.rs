@@ -0,0 +1,116 @@
+//! Hardware performance counters of all the threads of the process.
+//!
+//! The counters are read with `perf_event_open` and are only available on Linux with the `perf-counters` feature.
+
+use std::fmt;
+
+/// Counts measured between [`PerfCounters::start`] and [`PerfCounters::stop`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct PerfCounts {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub branch_misses: u64,
+    pub llc_misses: u64,
+}
+
+impl PerfCounts {
+    /// Instructions per cycle.
+    pub fn ipc(&self) -> f64 {
+        self.instructions as f64 / self.cycles as f64
+    }
+}
+
+impl fmt::Display for PerfCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instructions, {} cycles, {:.2} IPC, {} branch misses, {} LLC misses",
+               self.instructions, self.cycles, self.ipc(), self.branch_misses, self.llc_misses)
+    }
+}
+
+#[cfg(all(feature = "perf-counters", target_os = "linux"))]
+mod imp {
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+
+    use perf_event::events::Hardware;
+    use perf_event::{Builder, Counter};
+
+    use super::PerfCounts;
+
+    const EVENTS: [Hardware; 4] = [Hardware::INSTRUCTIONS, Hardware::CPU_CYCLES, Hardware::BRANCH_MISSES, Hardware::CACHE_MISSES];
+
+    /// Running counters, one set for every thread of the process.
+    pub struct PerfCounters {
+        counters: Vec<[Counter; 4]>,
+    }
+
+    impl PerfCounters {
+        /// Starts counting in all the threads of the process, including the rayon worker threads.
+        pub fn start() -> Result<PerfCounters, Error> {
+            // make sure the worker threads exist, so that they are listed below
+            rayon::broadcast(|_| ());
+            let mut counters: Vec<[Counter; 4]> = Vec::new();
+            for task in fs::read_dir("/proc/self/task")? {
+                let tid: i32 = task?.file_name().to_string_lossy().parse()
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid thread id"))?;
+                let mut thread = Vec::with_capacity(EVENTS.len());
+                for event in EVENTS {
+                    thread.push(Builder::new().kind(event).observe_pid(tid).build()?);
+                }
+                counters.push(thread.try_into().unwrap_or_else(|_| unreachable!()));
+            }
+            for counter in counters.iter_mut().flatten() {
+                counter.enable()?;
+            }
+            Ok(PerfCounters { counters })
+        }
+
+        /// Stops counting and returns the counts summed over all the threads.
+        pub fn stop(mut self) -> Result<PerfCounts, Error> {
+            for counter in self.counters.iter_mut().flatten() {
+                counter.disable()?;
+            }
+            let mut counts = PerfCounts::default();
+            for [instructions, cycles, branch_misses, llc_misses] in &mut self.counters {
+                counts.instructions += instructions.read()?;
+                counts.cycles += cycles.read()?;
+                counts.branch_misses += branch_misses.read()?;
+                counts.llc_misses += llc_misses.read()?;
+            }
+            Ok(counts)
+        }
+    }
+}
+
+#[cfg(not(all(feature = "perf-counters", target_os = "linux")))]
+mod imp {
+    use std::io::{Error, ErrorKind};
+
+    use super::PerfCounts;
+
+    /// Placeholder used when the counters are not supported by the build.
+    pub struct PerfCounters;
+
+    impl PerfCounters {
+        pub fn start() -> Result<PerfCounters, Error> {
+            Err(Error::new(ErrorKind::Unsupported, "built without the perf-counters feature or not on Linux"))
+        }
+
+        pub fn stop(self) -> Result<PerfCounts, Error> {
+            unreachable!()
+        }
+    }
+}
+
+pub use imp::PerfCounters;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_ipc() {
+        let counts = PerfCounts { instructions: 3000, cycles: 2000, branch_misses: 7, llc_misses: 5 };
+        assert_eq!(counts.to_string(), "3000 instructions, 2000 cycles, 1.50 IPC, 7 branch misses, 5 LLC misses");
+    }
+}
@@ -0,0 +1,213 @@
+//! Health check of an input, counting the lines by what is wrong with them instead of failing at the first one.
+
+use std::fmt;
+use std::io::BufRead;
+
+use memchr::{memchr, memchr_iter, memrchr};
+use rayon::prelude::*;
+
+use crate::parse::{parse_temp, Columns};
+use crate::Cancel;
+
+// the temperatures of the challenge are within ±99.9 degrees
+const MAX_ABS_TEMP: i32 = 999;
+
+// lines between the checks of the cancellation of a streamed input
+const CANCEL_CHECK_LINES: usize = 4096;
+
+/// What a line of the input is, see [`classify`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineClass {
+    Valid,
+    /// A valid record whose station name has non-ASCII characters
+    ValidNonAscii,
+    Blank,
+    /// No delimiter, or fewer fields than the selected columns need
+    MissingDelimiter,
+    EmptyStation,
+    InvalidUtf8,
+    InvalidTemperature,
+    /// A temperature that parses but is beyond ±99.9 degrees
+    OutOfRange,
+}
+
+/// Classifies a line like the readers split it, the station name ends at the last delimiter unless `columns` are selected.
+pub fn classify(l: &[u8], columns: Option<Columns>) -> LineClass {
+    let l: &[u8] = l.strip_suffix(b"\r").unwrap_or(l);
+    if l.is_empty() {
+        return LineClass::Blank;
+    }
+    let fields = match columns {
+        Some(columns) => select(l, columns),
+        None => memrchr(b';', l).map(|delimiter| (&l[..delimiter], &l[delimiter + 1..])),
+    };
+    let Some((station, temp)) = fields else {
+        return LineClass::MissingDelimiter;
+    };
+    if station.is_empty() {
+        return LineClass::EmptyStation;
+    }
+    if std::str::from_utf8(station).is_err() {
+        return LineClass::InvalidUtf8;
+    }
+    match parse_temp(temp) {
+        None => LineClass::InvalidTemperature,
+        Some(t) if t.abs() > MAX_ABS_TEMP => LineClass::OutOfRange,
+        Some(_) if !station.is_ascii() => LineClass::ValidNonAscii,
+        Some(_) => LineClass::Valid,
+    }
+}
+
+// the station and the temperature fields, `None` if the record has too few fields
+fn select(l: &[u8], columns: Columns) -> Option<(&[u8], &[u8])> {
+    let (mut station, mut temp) = (None, None);
+    let mut start: usize = 0;
+    for (i, end) in memchr_iter(b';', l).chain(std::iter::once(l.len())).enumerate() {
+        if i == columns.station {
+            station = Some(&l[start..end]);
+        } else if i == columns.temp {
+            temp = Some(&l[start..end]);
+        }
+        start = end + 1;
+    }
+    station.zip(temp)
+}
+
+/// Counts of the lines of an input by their [`LineClass`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QualityReport {
+    pub lines: usize,
+    /// Valid records, including those with a non-ASCII station name
+    pub valid: usize,
+    pub non_ascii_stations: usize,
+    pub blank: usize,
+    pub missing_delimiter: usize,
+    pub empty_station: usize,
+    pub invalid_utf8: usize,
+    pub invalid_temperature: usize,
+    pub out_of_range: usize,
+}
+
+impl QualityReport {
+    /// Classifies the lines of a slice of the input.
+    pub fn of_slice(data: &[u8], columns: Option<Columns>) -> QualityReport {
+        let mut report = QualityReport::default();
+        let mut start: usize = 0;
+        while start < data.len() {
+            let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+            report.add(classify(&data[start..end], columns));
+            start = end + 1;
+        }
+        report
+    }
+
+    /// Classifies the lines of the slices in parallel.
+    pub fn of_slices(slices: &[&[u8]], columns: Option<Columns>, cancel: &Cancel) -> QualityReport {
+        slices
+            .par_iter()
+            .map(|slice| if cancel.is_cancelled() { QualityReport::default() } else { QualityReport::of_slice(slice, columns) })
+            .reduce(QualityReport::default, |mut r1, r2| {
+                r1.merge(r2);
+                r1
+            })
+    }
+
+    /// Classifies the lines of a streamed input.
+    pub fn of_reader<R: BufRead>(reader: R, columns: Option<Columns>, cancel: &Cancel) -> QualityReport {
+        let mut report = QualityReport::default();
+        for (i, l) in reader.split(b'\n').map_while(Result::ok).enumerate() {
+            if i % CANCEL_CHECK_LINES == 0 && cancel.is_cancelled() {
+                break;
+            }
+            report.add(classify(&l, columns));
+        }
+        report
+    }
+
+    pub fn add(&mut self, class: LineClass) {
+        self.lines += 1;
+        let count = match class {
+            LineClass::Valid => &mut self.valid,
+            LineClass::ValidNonAscii => {
+                self.non_ascii_stations += 1;
+                &mut self.valid
+            }
+            LineClass::Blank => &mut self.blank,
+            LineClass::MissingDelimiter => &mut self.missing_delimiter,
+            LineClass::EmptyStation => &mut self.empty_station,
+            LineClass::InvalidUtf8 => &mut self.invalid_utf8,
+            LineClass::InvalidTemperature => &mut self.invalid_temperature,
+            LineClass::OutOfRange => &mut self.out_of_range,
+        };
+        *count += 1;
+    }
+
+    pub fn merge(&mut self, other: QualityReport) {
+        self.lines += other.lines;
+        self.valid += other.valid;
+        self.non_ascii_stations += other.non_ascii_stations;
+        self.blank += other.blank;
+        self.missing_delimiter += other.missing_delimiter;
+        self.empty_station += other.empty_station;
+        self.invalid_utf8 += other.invalid_utf8;
+        self.invalid_temperature += other.invalid_temperature;
+        self.out_of_range += other.out_of_range;
+    }
+
+    /// Percentage of the lines that are valid records, the blank lines are not counted. 100 for an empty input.
+    pub fn score(&self) -> f64 {
+        let records = self.lines - self.blank;
+        if records == 0 { 100.0 } else { self.valid as f64 * 100.0 / records as f64 }
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Quality: {:.2}% of {} lines are valid records", self.score(), self.lines)?;
+        let counts = [
+            ("valid", self.valid),
+            ("  with a non-ASCII station name", self.non_ascii_stations),
+            ("blank", self.blank),
+            ("missing delimiter", self.missing_delimiter),
+            ("empty station", self.empty_station),
+            ("invalid UTF-8 station", self.invalid_utf8),
+            ("invalid temperature", self.invalid_temperature),
+            ("temperature out of range", self.out_of_range),
+        ];
+        for (name, count) in counts {
+            writeln!(f, "  {:<32} {}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_classified() {
+        let data = b"Oslo;1.5\r\n\nno delimiter\n;2.0\nZ\xc3\xbcrich;-3.0\nRome;x\nLima;100.0\nA;B;-99.9\n\xff;1.0";
+        let report = QualityReport::of_slice(data, None);
+        assert_eq!(report, QualityReport {
+            lines: 9,
+            valid: 3,
+            non_ascii_stations: 1,
+            blank: 1,
+            missing_delimiter: 1,
+            empty_station: 1,
+            invalid_utf8: 1,
+            invalid_temperature: 1,
+            out_of_range: 1,
+        });
+        assert_eq!(report.score(), 3.0 * 100.0 / 8.0);
+        assert_eq!(QualityReport::of_reader(&data[..], None, &Cancel::default()), report);
+        let slices = [&data[..23], &data[24..]];
+        assert_eq!(QualityReport::of_slices(&slices, None, &Cancel::default()), report);
+
+        let columns = Some(Columns { station: 1, temp: 0 });
+        assert_eq!(classify(b"1.0;Oslo;x", columns), LineClass::Valid);
+        assert_eq!(classify(b"1.0", columns), LineClass::MissingDelimiter);
+        assert_eq!(QualityReport::default().score(), 100.0);
+    }
+}
@@ -0,0 +1,1920 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::hint::black_box;
+use std::io::{self, BufRead, Error, ErrorKind, Read};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bzip2::read::MultiBzDecoder;
+use memchr::{memchr, memchr_iter, memrchr};
+use memmap::Mmap;
+use rayon::prelude::*;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::encoding::{decode, Decoder, Encoding};
+use crate::parse::{parse, Columns, ParseOptions};
+#[cfg(feature = "s3")]
+use crate::s3::S3Object;
+use crate::{Aggregator, Cancel, Interner};
+
+pub const SLICE_SIZE: usize = 2 << 15;
+
+/// How the parallel readers load and split the files.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    /// Size of the slices the files are split into by [`slice_sized`]
+    pub slice_size: usize,
+    /// Read the whole files into memory instead of memory mapping them
+    pub no_mmap: bool,
+    /// Encoding of the files, see [`decode`]
+    pub encoding: Encoding,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions { slice_size: SLICE_SIZE, no_mmap: false, encoding: Encoding::Utf8 }
+    }
+}
+
+/// Contents of a file loaded by [`load_file`].
+pub enum FileData {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileData::Mapped(mmap) => mmap,
+            FileData::Read(data) => data,
+        }
+    }
+}
+
+/// Memory maps the file, or reads it into memory with `no_mmap`, the errors include the path.
+///
+/// Files that are not regular files (like pipes) or that fail to map are read into memory instead.
+/// A mapped file must not be truncated while it is in use, the process would be killed by `SIGBUS`,
+/// other changes of the size are detected by [`check_unchanged`].
+/// Files that are not UTF-8 (see [`decode`]) are transcoded into memory.
+pub fn load_file(path: &Path, read: ReadOptions) -> Result<FileData, Error> {
+    let data = load_raw(path, read)?;
+    Ok(match decode(&data, read.encoding) {
+        Cow::Borrowed(_) => data,
+        Cow::Owned(decoded) => FileData::Read(decoded),
+    })
+}
+
+fn load_raw(path: &Path, read: ReadOptions) -> Result<FileData, Error> {
+    #[cfg(feature = "s3")]
+    if is_s3(path) {
+        return S3Object::open(path)?.read_all().map(FileData::Read);
+    }
+    let with_path = |e: Error| Error::new(e.kind(), format!("{}: {}", path.display(), e));
+    let file = File::open(path).map_err(with_path)?;
+    if read.no_mmap || !file.metadata().map_err(with_path)?.is_file() {
+        return read_all(file).map(FileData::Read).map_err(with_path);
+    }
+    match unsafe { Mmap::map(&file) } {
+        Ok(mmap) => Ok(FileData::Mapped(mmap)),
+        Err(_) => read_all(file).map(FileData::Read).map_err(with_path),
+    }
+}
+
+fn read_all(mut file: File) -> Result<Vec<u8>, Error> {
+    let mut data: Vec<u8> = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Fails if the size of a mapped file changed since it was loaded, the result would not match the file.
+pub fn check_unchanged(path: &Path, data: &FileData) -> Result<(), Error> {
+    if let FileData::Mapped(mmap) = data {
+        let len = fs::metadata(path)?.len();
+        if len != mmap.len() as u64 {
+            return Err(Error::other(format!("{}: the size of the file changed from {} to {} bytes during the read", path.display(), mmap.len(), len)));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether all the paths are regular files or objects that can be read more than once, unlike pipes.
+pub fn regular_files(paths: &[PathBuf]) -> Result<bool, Error> {
+    if paths.iter().any(|p| is_stdin(p)) {
+        return Ok(false);
+    }
+    for path in paths.iter().filter(|p| !is_s3(p)) {
+        let metadata = fs::metadata(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        if !metadata.is_file() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Asks the kernel to drop the cached pages of the file, so that the next read comes from the disk.
+///
+/// This is best effort: dirty pages and pages mapped by other processes stay cached.
+/// Returns `Ok(false)` on platforms without `posix_fadvise`.
+pub fn evict_from_page_cache(path: &Path) -> Result<bool, Error> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        let file = File::open(path)?;
+        // a length of 0 covers the whole file
+        match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+            0 => Ok(true),
+            errno => Err(Error::from_raw_os_error(errno)),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        Ok(false)
+    }
+}
+
+/// Returns whether the path is an `s3://bucket/key` URL of an object, read with the `s3` feature.
+pub fn is_s3(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| p.starts_with("s3://"))
+}
+
+/// Returns whether the path is `-`, the standard input, read with [`open_input`] only.
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Size of the file or the object in bytes.
+pub fn input_size(path: &Path) -> Result<u64, Error> {
+    #[cfg(feature = "s3")]
+    if is_s3(path) {
+        return S3Object::open(path)?.size();
+    }
+    fs::metadata(path).map(|m| m.len()).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// Returns whether the file is bzip2 compressed, either detected from the `.bz2` extension or forced with `bzip2`.
+pub fn is_bzip2(path: &Path, bzip2: bool) -> bool {
+    bzip2 || path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bz2"))
+}
+
+/// Returns whether the input is read through a [`Decoder`], it has a byte order mark or the encoding is not UTF-8.
+///
+/// Only the start of local files is checked, the objects are streamed anyway.
+pub fn is_transcoded(path: &Path, encoding: Encoding) -> Result<bool, Error> {
+    if encoding != Encoding::Utf8 || is_s3(path) {
+        return Ok(encoding != Encoding::Utf8);
+    }
+    let mut bom = Vec::with_capacity(3);
+    File::open(path).and_then(|f| f.take(3).read_to_end(&mut bom)).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    Ok(encoding.sniff(&bom).1 > 0)
+}
+
+/// Opens the file, the object or the standard input for a sequential read, see [`decode_input`].
+///
+/// The standard input has no extension, it is only decompressed with `bzip2`.
+pub fn open_input(path: &Path, bzip2: bool, encoding: Encoding) -> Result<Box<dyn Read + Send>, Error> {
+    let input: Box<dyn Read + Send> = match is_s3(path) {
+        #[cfg(feature = "s3")]
+        true => Box::new(S3Object::open(path)?.reader()),
+        _ if is_stdin(path) => Box::new(io::stdin()),
+        _ => Box::new(File::open(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?),
+    };
+    Ok(decode_input(input, is_bzip2(path, bzip2), encoding))
+}
+
+/// Decompresses the input with `bzip2` and transcodes it to UTF-8 if it is not, on the fly.
+pub fn decode_input(input: Box<dyn Read + Send>, bzip2: bool, encoding: Encoding) -> Box<dyn Read + Send> {
+    // concatenated streams, like the output of pbzip2, are decoded one after another
+    let input: Box<dyn Read + Send> = if bzip2 { Box::new(MultiBzDecoder::new(input)) } else { input };
+    // the byte order mark of a UTF-8 input is skipped too
+    Box::new(Decoder::new(input, encoding))
+}
+
+/// Keeps the first error of the inner reader, [`read_stations_data`] stops at an error without reporting it.
+pub struct ErrorTrap<R> {
+    inner: R,
+    error: Option<Error>,
+}
+
+impl<R: Read> ErrorTrap<R> {
+    pub fn new(inner: R) -> ErrorTrap<R> {
+        ErrorTrap { inner, error: None }
+    }
+
+    /// Returns the error the read stopped at, if any.
+    pub fn finish(self) -> Result<(), Error> {
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+impl<R: Read> Read for ErrorTrap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.inner.read(buf).inspect_err(|e| {
+            // interrupted reads are retried by the reader
+            if e.kind() != ErrorKind::Interrupted && self.error.is_none() {
+                self.error = Some(Error::new(e.kind(), e.to_string()));
+            }
+        })
+    }
+}
+
+// the errno of an I/O error of the device, the same on Linux, macOS and the BSDs
+const EIO: i32 = 5;
+
+// the backoff of a retry is at most this long, however many retries came before
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Whether the read failing with the error may succeed when repeated: an interrupted call (`EINTR`), a timeout
+/// or an I/O error of the device (`EIO`), as an occasional one of a network mount. Other errors like a missing
+/// file (`ENOENT`) are fatal.
+pub fn is_transient(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::TimedOut) || e.raw_os_error() == Some(EIO)
+}
+
+/// Repeats the reads of the inner reader failing with a [transient](is_transient) error up to `retries` times in a row,
+/// waiting `backoff` before the first retry and twice as long before every next one. `on_retry` is called with the error,
+/// the number of the retry and the wait before it. A failed read of a file reads nothing, so it is repeated at the same position.
+pub struct Retry<R, F> {
+    inner: R,
+    retries: u32,
+    backoff: Duration,
+    on_retry: F,
+}
+
+impl<R: Read, F: FnMut(&Error, u32, Duration)> Retry<R, F> {
+    pub fn new(inner: R, retries: u32, backoff: Duration, on_retry: F) -> Retry<R, F> {
+        Retry { inner, retries, backoff, on_retry }
+    }
+}
+
+impl<R: Read, F: FnMut(&Error, u32, Duration)> Read for Retry<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut retry = 0;
+        loop {
+            match self.inner.read(buf) {
+                Err(e) if retry < self.retries && is_transient(&e) => {
+                    retry += 1;
+                    let backoff = self.backoff.saturating_mul(1 << (retry - 1).min(16)).min(MAX_RETRY_BACKOFF);
+                    (self.on_retry)(&e, retry, backoff);
+                    thread::sleep(backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// how many lines the simple reader processes between cancellation checks
+const CANCEL_CHECK_LINES: usize = 4096;
+
+// first lines of a slice checked for runs of a station before it is parsed, see `has_runs`
+const RUN_SAMPLE_LINES: usize = 32;
+
+// at most this many files are memory mapped at the same time
+const MAX_OPEN_FILES: usize = 64;
+
+// `on_progress` is called with the map and the number of bytes read between cancellation checks,
+// the names of new stations are copied into the `interner`
+pub fn read_stations_data<'a, A: Aggregator, P: BufRead, F: FnMut(&HashMap<&'a str, A::State>, usize)>(reader: P, aggregator: &A, interner: &mut Interner<'a>, m: HashMap<&'a str, A::State>, options: ParseOptions, cancel: &Cancel, on_progress: F) -> (HashMap<&'a str, A::State>, usize) {
+    read_stations_data_from(reader, 0, aggregator, interner, m, options, cancel, on_progress)
+}
+
+/// Variant of [`read_stations_data`] for a reader starting at the offset `start` of the file, the offsets passed to
+/// the aggregator are counted from the start of the file.
+#[allow(clippy::too_many_arguments)]
+pub fn read_stations_data_from<'a, A: Aggregator, P: BufRead, F: FnMut(&HashMap<&'a str, A::State>, usize)>(reader: P, start: u64, aggregator: &A, interner: &mut Interner<'a>, mut m: HashMap<&'a str, A::State>, options: ParseOptions, cancel: &Cancel, mut on_progress: F) -> (HashMap<&'a str, A::State>, usize) {
+    let mut bytes_processed: usize = 0;
+    let mut previous: Option<Vec<u8>> = None;
+    for (i, l) in reader.split(b'\n').map_while(Result::ok).enumerate() {
+        if i % CANCEL_CHECK_LINES == 0 {
+            if cancel.is_cancelled() {
+                break;
+            }
+            on_progress(&m, bytes_processed);
+        }
+        let offset = start + bytes_processed as u64;
+        bytes_processed += l.len() + 1;
+        // the previous line is only kept with `dedup_consecutive`
+        if previous.as_ref() == Some(&l) {
+            continue;
+        }
+        if let Some((station, temp)) = parse_line(&l, options).unwrap_or_else(|e| { invalid_record(&l, e, &|| i, options); None }) {
+            match m.get_mut(station) {
+                Some(e) => aggregator.observe_at(e, temp, offset),
+                None => {
+                    m.insert(interner.intern(station), aggregator.init_at(temp, offset));
+                }
+            }
+        }
+        if options.dedup_consecutive {
+            previous = Some(l);
+        }
+    }
+    (m, bytes_processed)
+}
+
+/// Parses and validates the records like [`read_stations_data`] without aggregating them,
+/// returns the number of records and the number of bytes processed.
+///
+/// The parsed records are passed to [`black_box`] so that the parsing is not optimized away.
+pub fn scan_stations_data<P: BufRead>(reader: P, options: ParseOptions, cancel: &Cancel) -> (usize, usize) {
+    let mut records: usize = 0;
+    let mut bytes_processed: usize = 0;
+    let mut previous: Option<Vec<u8>> = None;
+    for (i, l) in reader.split(b'\n').map_while(Result::ok).enumerate() {
+        if i % CANCEL_CHECK_LINES == 0 && cancel.is_cancelled() {
+            break;
+        }
+        bytes_processed += l.len() + 1;
+        if options.dedup_consecutive {
+            if previous.as_ref() == Some(&l) {
+                continue;
+            }
+            previous = Some(l.clone());
+        }
+        if let Some(record) = parse_line(&l, options).unwrap_or_else(|e| { invalid_record(&l, e, &|| i, options); None }) {
+            black_box(record);
+            records += 1;
+        }
+    }
+    (records, bytes_processed)
+}
+
+// blank lines and records without a station name are skipped,
+// the station name ends at the last delimiter like in `for_each_record`
+pub(crate) fn parse_line(l: &[u8], options: ParseOptions) -> Result<Option<(&str, i32)>, Invalid> {
+    if let Some(columns) = options.columns {
+        return parse_columns(l, columns, options);
+    }
+    match memrchr(b';', l) {
+        None | Some(0) => Ok(None),
+        Some(delimiter) => parse_fields(&l[..delimiter], &l[delimiter + 1..], options),
+    }
+}
+
+/// An invalid record of a strict run, see [`report_invalid`] and [`invalid_record`].
+pub(crate) struct Invalid {
+    message: String,
+    // the address of the first invalid byte, translated to a line and a column by `report_invalid`
+    at: usize,
+}
+
+impl Invalid {
+    pub(crate) fn new(message: String, at: &[u8]) -> Invalid {
+        Invalid { message, at: at.as_ptr() as usize }
+    }
+}
+
+/// Panics with the number of the line of `data` with the invalid record, the line and a caret under the invalid byte.
+///
+/// `lines_before` counts the lines of the input before `data`, only when a record is invalid.
+#[cold]
+pub(crate) fn report_invalid(data: &[u8], invalid: Invalid, lines_before: &dyn Fn() -> usize) -> ! {
+    panic!("{}", locate(data, invalid, lines_before))
+}
+
+/// Reports the invalid record like [`report_invalid`], or adds it to the [`ErrorLog`] of the options if there is one.
+#[cold]
+pub(crate) fn invalid_record(data: &[u8], invalid: Invalid, lines_before: &dyn Fn() -> usize, options: ParseOptions) {
+    match options.errors {
+        Some(log) => log.add(|| locate(data, invalid, lines_before)),
+        None => report_invalid(data, invalid, lines_before),
+    }
+}
+
+fn locate(data: &[u8], invalid: Invalid, lines_before: &dyn Fn() -> usize) -> ParseError {
+    let at = (invalid.at - data.as_ptr() as usize).min(data.len());
+    let line_start = memrchr(b'\n', &data[..at]).map_or(0, |i| i + 1);
+    let line_end = memchr(b'\n', &data[at..]).map_or(data.len(), |i| at + i);
+    ParseError {
+        line: lines_before() + memchr_iter(b'\n', &data[..line_start]).count() + 1,
+        column: String::from_utf8_lossy(&data[line_start..at]).chars().count() + 1,
+        message: invalid.message,
+        text: String::from_utf8_lossy(&data[line_start..line_end]).trim_end_matches('\r').to_owned(),
+    }
+}
+
+/// An invalid record with its location, see [`ErrorLog`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based number of the line in the input
+    pub line: usize,
+    /// 1-based number of the character of the line the record is invalid at
+    pub column: usize,
+    pub message: String,
+    /// The line with the record
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}:\n{}\n{:>column$}", self.message, self.line, self.column, self.text, "^", column = self.column)
+    }
+}
+
+/// Invalid records collected by a strict run instead of failing at the first one, see [`ParseOptions::errors`].
+///
+/// The first `max` records found are kept, the others are only counted. The readers of several threads
+/// find them in no particular order.
+#[derive(Debug)]
+pub struct ErrorLog {
+    max: usize,
+    count: AtomicUsize,
+    errors: Mutex<Vec<ParseError>>,
+}
+
+impl ErrorLog {
+    pub fn new(max: usize) -> ErrorLog {
+        ErrorLog { max, count: AtomicUsize::new(0), errors: Mutex::new(Vec::new()) }
+    }
+
+    // the location is only computed for the kept records, counting the lines before a slice reads the input again
+    fn add<F: FnOnce() -> ParseError>(&self, error: F) {
+        if self.count.fetch_add(1, Ordering::Relaxed) < self.max {
+            let error = error();
+            self.errors.lock().unwrap().push(error);
+        }
+    }
+
+    /// Returns the kept records in the order of their lines and the number of all the invalid records,
+    /// and empties the log.
+    pub fn take(&self) -> (Vec<ParseError>, usize) {
+        let mut errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        errors.sort_by_key(|e| (e.line, e.column));
+        (errors, self.count.swap(0, Ordering::Relaxed))
+    }
+}
+
+// the lines before the slice at `index`, the slices of a file are separated by one newline
+pub(crate) fn lines_before(slices: &[&[u8]], index: usize) -> usize {
+    slices[..index].iter().map(|s| memchr_iter(b'\n', s).count() + 1).sum()
+}
+
+/// The slice at `index` without its first lines that repeat the last line of the slice right before it, with the number
+/// of lines dropped, the slices of a file are separated by one newline. The slice is whole unless `options.dedup_consecutive`
+/// is set, the duplicates within the slice are skipped by the parsers.
+pub(crate) fn without_repeats<'a>(slices: &[&'a [u8]], index: usize, options: ParseOptions) -> (&'a [u8], usize) {
+    let slice = slices[index];
+    let Some(before) = index.checked_sub(1).map(|i| slices[i]).filter(|_| options.dedup_consecutive) else {
+        return (slice, 0);
+    };
+    // a slice of another file, or of the same file not right before this one
+    if !follows(before, slice) {
+        return (slice, 0);
+    }
+    let line = &before[memrchr(b'\n', before).map_or(0, |i| i + 1)..];
+    let (mut start, mut lines) = (0, 0);
+    while start < slice.len() {
+        let end = memchr(b'\n', &slice[start..]).map_or(slice.len(), |i| start + i);
+        if &slice[start..end] != line {
+            break;
+        }
+        (start, lines) = ((end + 1).min(slice.len()), lines + 1);
+    }
+    (&slice[start..], lines)
+}
+
+/// Aggregates the slices in parallel, returns the merged map and the number of bytes processed.
+///
+/// Slices that have not been started when `cancel` is triggered are skipped.
+pub fn read_slices_parallel<'a, A: Aggregator>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel) -> (HashMap<&'a str, A::State>, usize) {
+    let parsed = parse_slices_parallel(slices, aggregator, options, cancel);
+    (merge_all(aggregator, parsed.maps), parsed.bytes_processed)
+}
+
+/// Work done by a rayon worker thread in [`parse_slices_parallel`].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct WorkerStats {
+    pub slices: usize,
+    pub bytes: usize,
+    pub rows: usize,
+    pub parse_time: Duration,
+}
+
+/// Result of [`parse_slices_parallel`].
+pub struct ParsedSlices<'a, S> {
+    /// One map per rayon job.
+    pub maps: Vec<HashMap<&'a str, S>>,
+    pub bytes_processed: usize,
+    /// Indexed by the rayon thread index.
+    pub workers: Vec<WorkerStats>,
+}
+
+/// First phase of [`read_slices_parallel`]: aggregates the slices into one map per rayon job without merging them.
+pub fn parse_slices_parallel<'a, A: Aggregator>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel) -> ParsedSlices<'a, A::State> {
+    parse_slices_parallel_with_progress(slices, aggregator, options, cancel, &AtomicUsize::new(0))
+}
+
+/// Variant of [`parse_slices_parallel`] raising `consumed` to the end offset of every aggregated slice,
+/// relative to the start of the file of the slice, the progress followed by [`with_readahead`] for the slices of one file.
+pub fn parse_slices_parallel_with_progress<'a, A: Aggregator>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel, consumed: &AtomicUsize) -> ParsedSlices<'a, A::State> {
+    let offsets = slice_offsets(slices);
+    // the jobs are created by the workers that run them
+    let results: Vec<_> = slices
+        .par_iter()
+        .enumerate()
+        .fold(|| (HashMap::new(), rayon::current_thread_index().unwrap_or(0), WorkerStats::default()),
+              |(mut m, thread, mut stats), (i, slice)| {
+                  // cancellation point: skip the remaining slices once cancelled
+                  if cancel.is_cancelled() {
+                      return (m, thread, stats);
+                  }
+                  let start = Instant::now();
+                  let (records, repeats) = without_repeats(slices, i, options);
+                  stats.rows += aggregate_slice(records, records_offset(&offsets, slices, i, records), &|| lines_before(slices, i) + repeats, aggregator, &mut m, options);
+                  consumed.fetch_max(offsets[i] as usize + slice.len(), Ordering::Relaxed);
+                  stats.parse_time += start.elapsed();
+                  stats.slices += 1;
+                  stats.bytes += slice.len();
+                  (m, thread, stats)
+              },
+        )
+        .collect();
+    let mut workers = vec![WorkerStats::default(); rayon::current_num_threads()];
+    for (_, thread, stats) in &results {
+        let w = &mut workers[*thread];
+        w.slices += stats.slices;
+        w.bytes += stats.bytes;
+        w.rows += stats.rows;
+        w.parse_time += stats.parse_time;
+    }
+    let bytes_processed = workers.iter().map(|w| w.bytes).sum();
+    ParsedSlices { maps: results.into_iter().map(|(m, _, _)| m).collect(), bytes_processed, workers }
+}
+
+// the readahead touches one byte of every page of this size, larger pages are touched more than once
+const READAHEAD_PAGE_SIZE: usize = 4096;
+
+// pages touched between the checks of the progress of the parsers
+const READAHEAD_BATCH_PAGES: usize = 256;
+
+// how long the readahead waits when it is `lead` bytes ahead
+const READAHEAD_POLL: Duration = Duration::from_millis(1);
+
+/// What the readahead thread of [`with_readahead`] did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadaheadStats {
+    /// Bytes the pages were touched ahead of the parsers
+    pub lead: usize,
+    /// Pages touched, possibly already faulted in by the parsers
+    pub pages: usize,
+}
+
+/// Runs `f` while a background thread faults in the pages of `data` up to `lead` bytes ahead of the
+/// furthest byte consumed by the parsers, reported by `f` through the counter it is given
+/// (see [`parse_slices_parallel_with_progress`]).
+///
+/// The thread reads one byte per page, at the lowest scheduling priority on Linux, so that the disk queue
+/// stays full while the parsers are busy. It never reads past the end of `data` and stops when `f` returns.
+pub fn with_readahead<R, F: FnOnce(&AtomicUsize) -> R>(data: &[u8], lead: usize, f: F) -> (R, ReadaheadStats) {
+    let consumed = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let readahead = scope.spawn(|| {
+            // a nice value of the calling thread only, the threads of a process have their own on Linux
+            #[cfg(target_os = "linux")]
+            unsafe {
+                libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+            }
+            let (mut next, mut pages) = (0, 0);
+            while next < data.len() && !done.load(Ordering::Relaxed) {
+                let target = consumed.load(Ordering::Relaxed).saturating_add(lead).min(data.len());
+                if next >= target {
+                    thread::sleep(READAHEAD_POLL);
+                    continue;
+                }
+                for offset in (next..target).step_by(READAHEAD_PAGE_SIZE).take(READAHEAD_BATCH_PAGES) {
+                    black_box(data[offset]);
+                    pages += 1;
+                    next = offset + READAHEAD_PAGE_SIZE;
+                }
+            }
+            pages
+        });
+        let result = f(&consumed);
+        done.store(true, Ordering::Relaxed);
+        let pages = readahead.join().unwrap();
+        (result, ReadaheadStats { lead, pages })
+    })
+}
+
+/// Variant of [`read_slices_parallel`] merging every slice into a shared map as soon as it is aggregated,
+/// returns the merged map and the number of bytes processed.
+///
+/// `on_snapshot` is called from a background thread every `interval` with the map merged so far and the number of
+/// bytes processed, while the map is locked.
+pub fn read_slices_streaming<'a, A: Aggregator, F: Fn(&HashMap<&'a str, A::State>, usize) + Sync>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel, interval: Duration, on_snapshot: F) -> (HashMap<&'a str, A::State>, usize) {
+    let merged: Mutex<HashMap<&str, A::State>> = Mutex::new(HashMap::new());
+    let bytes_processed = AtomicUsize::new(0);
+    let offsets = slice_offsets(slices);
+    let (done, wait) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        let (merged, bytes_processed, on_snapshot) = (&merged, &bytes_processed, &on_snapshot);
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(interval) {
+                on_snapshot(&merged.lock().unwrap(), bytes_processed.load(Ordering::Relaxed));
+            }
+        });
+        slices.par_iter().enumerate().for_each(|(i, slice)| {
+            // cancellation point: skip the remaining slices once cancelled
+            if cancel.is_cancelled() {
+                return;
+            }
+            let mut m: HashMap<&str, A::State> = HashMap::new();
+            let (records, repeats) = without_repeats(slices, i, options);
+            aggregate_slice(records, records_offset(&offsets, slices, i, records), &|| lines_before(slices, i) + repeats, aggregator, &mut m, options);
+            merge(aggregator, &mut merged.lock().unwrap(), m);
+            bytes_processed.fetch_add(slice.len(), Ordering::Relaxed);
+        });
+        // stops the snapshots
+        drop(done);
+    });
+    (merged.into_inner().unwrap(), bytes_processed.into_inner())
+}
+
+/// Progress of [`read_slices_snapshots`] at a snapshot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes: usize,
+    pub rows: usize,
+}
+
+/// Variant of [`read_slices_streaming`] calling `on_snapshot` on the current thread instead, every `interval` with
+/// a copy of the map merged so far and the progress it covers, returns the final map and progress.
+///
+/// The map is only locked to copy it, the workers go on merging their slices while `on_snapshot` runs.
+/// The final map is not passed to `on_snapshot`.
+pub fn read_slices_snapshots<'a, A, F>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel, interval: Duration, mut on_snapshot: F) -> (HashMap<&'a str, A::State>, Progress)
+where
+    A: Aggregator,
+    A::State: Clone,
+    F: FnMut(&HashMap<&'a str, A::State>, Progress),
+{
+    // the progress is updated with the map, so that a snapshot covers exactly the bytes and rows merged into it
+    let merged: Mutex<(HashMap<&str, A::State>, Progress)> = Mutex::new((HashMap::new(), Progress::default()));
+    let offsets = slice_offsets(slices);
+    let (done, wait) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        let merged = &merged;
+        scope.spawn(move || {
+            slices.par_iter().enumerate().for_each(|(i, slice)| {
+                // cancellation point: skip the remaining slices once cancelled
+                if cancel.is_cancelled() {
+                    return;
+                }
+                let mut m: HashMap<&str, A::State> = HashMap::new();
+                let (records, repeats) = without_repeats(slices, i, options);
+                let rows = aggregate_slice(records, records_offset(&offsets, slices, i, records), &|| lines_before(slices, i) + repeats, aggregator, &mut m, options);
+                let (merged, progress) = &mut *merged.lock().unwrap();
+                merge(aggregator, merged, m);
+                progress.bytes += slice.len();
+                progress.rows += rows;
+            });
+            // stops the snapshots, also when a worker panics
+            drop(done);
+        });
+        while let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(interval) {
+            let (snapshot, progress) = merged.lock().unwrap().clone();
+            on_snapshot(&snapshot, progress);
+        }
+    });
+    merged.into_inner().unwrap()
+}
+
+/// Second phase of [`read_slices_parallel`]: merges the maps in parallel.
+pub fn merge_all<K: Eq + Hash + Send, A: Aggregator>(aggregator: &A, maps: Vec<HashMap<K, A::State>>) -> HashMap<K, A::State> {
+    maps.into_par_iter().reduce(HashMap::new, |mut m1, m2| {
+        merge(aggregator, &mut m1, m2);
+        m1
+    })
+}
+
+/// Variant of [`merge_all`] sorting the entries of every map by station in parallel and merging the sorted lists
+/// with a k-way merge, so that every station is hashed once, when the merged map is built.
+///
+/// It is slower than [`merge_all`] in the `merge` benchmark, the comparisons of the names in the heap cost more
+/// than hashing them again, and more so the more maps there are.
+pub fn merge_all_sorted<K: Ord + Hash + Send, A: Aggregator>(aggregator: &A, maps: Vec<HashMap<K, A::State>>) -> HashMap<K, A::State> {
+    let lists: Vec<Vec<(K, A::State)>> = maps.into_par_iter()
+        .map(|m| {
+            let mut list: Vec<(K, A::State)> = m.into_iter().collect();
+            list.sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2));
+            list
+        })
+        .collect();
+    let mut merged: HashMap<K, A::State> = HashMap::with_capacity(lists.iter().map(Vec::len).max().unwrap_or(0));
+    let mut lists: Vec<std::vec::IntoIter<(K, A::State)>> = lists.into_iter().map(Vec::into_iter).collect();
+    // the smallest station of every list that is not exhausted, the ties are taken in the order of the lists
+    let mut heads: BinaryHeap<Reverse<(K, usize)>> = BinaryHeap::with_capacity(lists.len());
+    let mut states: Vec<Option<A::State>> = Vec::with_capacity(lists.len());
+    for (i, list) in lists.iter_mut().enumerate() {
+        states.push(list.next().map(|(station, state)| {
+            heads.push(Reverse((station, i)));
+            state
+        }));
+    }
+    while let Some(Reverse((station, i))) = heads.pop() {
+        let mut state = states[i].take().expect("the state of a head");
+        advance(&mut lists[i], &mut heads, &mut states, i);
+        while heads.peek().is_some_and(|Reverse((next, _))| *next == station) {
+            let Some(Reverse((_, j))) = heads.pop() else { break };
+            aggregator.merge(&mut state, states[j].take().expect("the state of a head"));
+            advance(&mut lists[j], &mut heads, &mut states, j);
+        }
+        merged.insert(station, state);
+    }
+    merged
+}
+
+// moves the head of the list `i` to its next entry
+fn advance<K: Ord, S>(list: &mut std::vec::IntoIter<(K, S)>, heads: &mut BinaryHeap<Reverse<(K, usize)>>, states: &mut [Option<S>], i: usize) {
+    if let Some((station, state)) = list.next() {
+        heads.push(Reverse((station, i)));
+        states[i] = Some(state);
+    }
+}
+
+/// Parses and validates the slices in parallel like [`read_slices_parallel`] without aggregating them,
+/// returns the number of records and the number of bytes processed.
+pub fn scan_slices_parallel(slices: &[&[u8]], options: ParseOptions, cancel: &Cancel) -> (usize, usize) {
+    slices
+        .par_iter()
+        .enumerate()
+        .map(|(i, slice)| {
+            if cancel.is_cancelled() {
+                return (0, 0);
+            }
+            let mut records: usize = 0;
+            let (distinct, repeats) = without_repeats(slices, i, options);
+            for_each_record(distinct, options, &|| lines_before(slices, i) + repeats, |station, temp, _| {
+                black_box((station, temp));
+                records += 1;
+            });
+            (records, slice.len())
+        })
+        .reduce(|| (0, 0), |(r1, n1), (r2, n2)| (r1 + r2, n1 + n2))
+}
+
+/// Counts the lines of the slices in parallel without parsing them, returns the number of lines and bytes processed.
+///
+/// A speed-of-light baseline for the readers: the count matches the number of records of well-formed input.
+pub fn count_lines_parallel(slices: &[&[u8]], cancel: &Cancel) -> (usize, usize) {
+    slices
+        .par_iter()
+        .map(|slice| {
+            if cancel.is_cancelled() {
+                return (0, 0);
+            }
+            // the newline ending the slice is not a part of it, unless it is the last one of the file
+            let unterminated = slice.last().is_some_and(|&b| b != b'\n') as usize;
+            (memchr_iter(b'\n', slice).count() + unterminated, slice.len())
+        })
+        .reduce(|| (0, 0), |(l1, n1), (l2, n2)| (l1 + l2, n1 + n2))
+}
+
+/// Aggregates the files in parallel, returns the merged map and the number of bytes processed.
+///
+/// Every file is loaded by [`load_file`], split into slices and aggregated by [`read_slices_parallel`] on its own,
+/// the results are merged at the end. The station names of the merged map are copied into the `interner`.
+pub fn read_files_parallel<'a, A: Aggregator>(paths: &[PathBuf], aggregator: &A, interner: &mut Interner<'a>, read: ReadOptions, options: ParseOptions, cancel: &Cancel) -> Result<(HashMap<&'a str, A::State>, usize), Error> {
+    let mut m: HashMap<&str, A::State> = HashMap::new();
+    let mut bytes_processed: usize = 0;
+    for chunk in paths.chunks(MAX_OPEN_FILES) {
+        let files: Vec<FileData> = chunk.iter().map(|path| load_file(path, read)).collect::<Result<_, Error>>()?;
+        let (m2, n2) = files
+            .par_iter()
+            .map(|data| read_slices_parallel(&slice_sized(data, read.slice_size), aggregator, options, cancel))
+            .reduce(|| (HashMap::new(), 0),
+                    |(mut m1, n1), (m2, n2)| {
+                        merge(aggregator, &mut m1, m2);
+                        (m1, n1 + n2)
+                    },
+            );
+        for (path, data) in chunk.iter().zip(&files) {
+            check_unchanged(path, data)?;
+        }
+        // the names borrow from the files of the chunk
+        merge(aggregator, &mut m, intern_keys(interner, m2));
+        bytes_processed += n2;
+    }
+    Ok((m, bytes_processed))
+}
+
+/// Memory maps the file and aggregates it with [`read_slices_parallel`], the station names are copied into the `interner`.
+pub fn read_file_parallel<'a, A: Aggregator>(path: &Path, aggregator: &A, interner: &mut Interner<'a>, options: ParseOptions, cancel: &Cancel) -> Result<(HashMap<&'a str, A::State>, usize), Error> {
+    let data = load_file(path, ReadOptions::default())?;
+    let slices = slice(&data);
+    let (m, bytes_processed) = read_slices_parallel(&slices, aggregator, options, cancel);
+    Ok((intern_keys(interner, m), bytes_processed))
+}
+
+fn intern_keys<'a, S>(interner: &mut Interner<'a>, m: HashMap<&str, S>) -> HashMap<&'a str, S> {
+    m.into_iter().map(|(station, state)| (interner.intern(station), state)).collect()
+}
+
+/// Merges the states of `m2` into `m1`.
+pub fn merge<K: Eq + Hash, A: Aggregator>(aggregator: &A, m1: &mut HashMap<K, A::State>, m2: HashMap<K, A::State>) {
+    for (station, station_data) in m2.into_iter() {
+        match m1.entry(station) {
+            Entry::Occupied(mut e) => aggregator.merge(e.get_mut(), station_data),
+            Entry::Vacant(e) => {
+                e.insert(station_data);
+            }
+        }
+    }
+}
+
+/// Steps of the normalization of the station names by [`normalize_keys`], applied in the order of the fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyNormalization {
+    /// Remove the leading and the trailing ASCII whitespace
+    pub trim: bool,
+    /// Convert the names to lowercase, with a fast path for the ASCII names
+    pub case_fold: bool,
+    /// Convert the names to the Unicode normalization form C
+    pub nfc: bool,
+}
+
+impl KeyNormalization {
+    /// Only the NFC normalization, see [`normalize`].
+    pub const NFC: KeyNormalization = KeyNormalization { trim: false, case_fold: false, nfc: true };
+
+    /// Normalizes a station name, names that are already normalized are not copied.
+    pub fn apply(self, station: &str) -> Cow<'_, str> {
+        let station = if self.trim { station.trim_matches(|c: char| c.is_ascii_whitespace()) } else { station };
+        let mut normalized = Cow::Borrowed(station);
+        if self.case_fold {
+            if station.is_ascii() {
+                if station.bytes().any(|b| b.is_ascii_uppercase()) {
+                    normalized = Cow::Owned(station.to_ascii_lowercase());
+                }
+            } else {
+                normalized = Cow::Owned(station.to_lowercase());
+            }
+        }
+        if self.nfc && !is_nfc(&normalized) {
+            normalized = Cow::Owned(normalized.nfc().collect());
+        }
+        normalized
+    }
+}
+
+/// Re-keys the map by the NFC normalized station names, merging the stations whose names only differ in the
+/// Unicode normalization form.
+pub fn normalize<'a, K: AsRef<str>, A: Aggregator>(aggregator: &A, interner: &mut Interner<'a>, m: HashMap<K, A::State>) -> HashMap<&'a str, A::State> {
+    normalize_keys(aggregator, interner, m, KeyNormalization::NFC)
+}
+
+/// Re-keys the map by the station names normalized by `keys`, merging the stations with the same normalized name.
+///
+/// Normalizing the final map gives the same result as normalizing every record, with every distinct name
+/// normalized once and nothing added to the parsing of the records.
+pub fn normalize_keys<'a, K: AsRef<str>, A: Aggregator>(aggregator: &A, interner: &mut Interner<'a>, m: HashMap<K, A::State>, keys: KeyNormalization) -> HashMap<&'a str, A::State> {
+    let mut normalized: HashMap<&str, A::State> = HashMap::with_capacity(m.len());
+    for (station, state) in m {
+        let station = interner.intern(&keys.apply(station.as_ref()));
+        match normalized.entry(station) {
+            Entry::Occupied(mut e) => aggregator.merge(e.get_mut(), state),
+            Entry::Vacant(e) => {
+                e.insert(state);
+            }
+        }
+    }
+    normalized
+}
+
+/// Checks the final states with [`Aggregator::validate`], the error names the offending station.
+pub fn validate<K: AsRef<str>, A: Aggregator>(aggregator: &A, m: &HashMap<K, A::State>) -> Result<(), Error> {
+    for (station, state) in m {
+        aggregator.validate(state).map_err(|e| Error::new(e.kind(), format!("Station {}: {}", station.as_ref(), e)))?;
+    }
+    Ok(())
+}
+
+/// Splits the data into slices of at least [`SLICE_SIZE`] bytes that end at a newline,
+/// records longer than a slice are kept whole.
+pub fn slice(data: &[u8]) -> Vec<&[u8]> {
+    slice_sized(data, SLICE_SIZE)
+}
+
+/// The records of the byte range `start..end` of the data, for splitting a file between independent processes.
+///
+/// Both ends are snapped forward to a record boundary like the ends of the slices of [`slice_sized`]:
+/// a range starts after the first newline at or after `start` (at 0 if `start` is 0) and ends after the first newline
+/// at or after `end`. So a record belongs to the range that has the newline before it, and the ranges `a..b`
+/// and `b..c` together have the records of `a..c`, none of them twice.
+pub fn byte_range(data: &[u8], start: usize, end: usize) -> &[u8] {
+    let snap = |at: usize| match at {
+        0 => 0,
+        _ => memchr(b'\n', &data[at.min(data.len())..]).map_or(data.len(), |i| at + i + 1),
+    };
+    let start = snap(start);
+    &data[start..snap(end).max(start)]
+}
+
+/// Splits the data like [`slice`] into slices of at least `size` bytes.
+pub fn slice_sized(data: &[u8], size: usize) -> Vec<&[u8]> {
+    let mut slices: Vec<&[u8]> = Vec::new();
+    let mut slice_start: usize = 0;
+    let len = data.len();
+    while slice_start < len {
+        let search_start: usize = (slice_start + size).min(len);
+        let slice_end: usize = memchr(b'\n', &data[search_start..]).map_or(len, |i| search_start + i);
+        slices.push(&data[slice_start..slice_end]);
+        slice_start = slice_end + 1;
+    }
+    slices
+}
+
+pub fn read_stations_data_slice<'a, A: Aggregator>(data: &'a [u8], aggregator: &A, options: ParseOptions) -> HashMap<&'a str, A::State> {
+    let mut m: HashMap<&str, A::State> = HashMap::new();
+    aggregate_slice(data, 0, &|| 0, aggregator, &mut m, options);
+    m
+}
+
+// returns the number of records, `data` starts at the offset `start` of the input after `lines_before` lines
+fn aggregate_slice<'a, A: Aggregator>(data: &'a [u8], start: u64, lines_before: &dyn Fn() -> usize, aggregator: &A, m: &mut HashMap<&'a str, A::State>, options: ParseOptions) -> usize {
+    // the halves would not compare the records at the split for duplicates
+    if options.dual_cursor && !options.dedup_consecutive {
+        return aggregate_slice_dual(data, start, lines_before, aggregator, m, options);
+    }
+    let mut rows: usize = 0;
+    // the check of the previous station costs a few percent when the stations are in random order
+    if options.no_station_cache || !has_runs(data) {
+        for_each_record(data, options, lines_before, |station, temp, offset| {
+            let offset = start + offset as u64;
+            m.entry(station)
+                .and_modify(|e| aggregator.observe_at(e, temp, offset))
+                .or_insert_with(|| aggregator.init_at(temp, offset));
+            rows += 1;
+        });
+        return rows;
+    }
+    // the station of the previous record, and the state of the records of the same station that follow it,
+    // which is merged into the map when another station comes, so that a run of a station is looked up twice
+    let mut previous: &'a str = "";
+    let mut run: Option<A::State> = None;
+    for_each_record(data, options, lines_before, |station, temp, offset| {
+        let offset = start + offset as u64;
+        rows += 1;
+        // the equality of the names compares their lengths first, most of the stations of a random order stop there
+        if station == previous {
+            match &mut run {
+                Some(e) => aggregator.observe_at(e, temp, offset),
+                None => run = Some(aggregator.init_at(temp, offset)),
+            }
+            return;
+        }
+        if let Some(e) = run.take() {
+            merge_entry(aggregator, m, previous, e);
+        }
+        previous = station;
+        m.entry(station)
+            .and_modify(|e| aggregator.observe_at(e, temp, offset))
+            .or_insert_with(|| aggregator.init_at(temp, offset));
+    });
+    if let Some(e) = run {
+        merge_entry(aggregator, m, previous, e);
+    }
+    rows
+}
+
+// whether most of the first lines of the data have the same station as the line before them, the station
+// is compared up to the first delimiter
+fn has_runs(data: &[u8]) -> bool {
+    let stations: Vec<Option<&[u8]>> = data.split(|&b| b == b'\n')
+        .take(RUN_SAMPLE_LINES)
+        .map(|l| memchr(b';', l).map(|i| &l[..i]))
+        .collect();
+    let same = stations.windows(2).filter(|w| w[0].is_some() && w[0] == w[1]).count();
+    2 * same >= stations.len().saturating_sub(1).max(1)
+}
+
+// the station is usually in the map already, it was looked up for the first record of the run
+fn merge_entry<'a, A: Aggregator>(aggregator: &A, m: &mut HashMap<&'a str, A::State>, station: &'a str, state: A::State) {
+    match m.entry(station) {
+        Entry::Occupied(mut e) => aggregator.merge(e.get_mut(), state),
+        Entry::Vacant(e) => {
+            e.insert(state);
+        }
+    }
+}
+
+// splits the data into two halves at a newline and parses a record of each half in every iteration, so that the
+// CPU can work on two independent records at the same time, the rest of the longer half is parsed by `aggregate_slice`
+fn aggregate_slice_dual<'a, A: Aggregator>(data: &'a [u8], start: u64, lines_before: &dyn Fn() -> usize, aggregator: &A, m: &mut HashMap<&'a str, A::State>, options: ParseOptions) -> usize {
+    let len = data.len();
+    // the first half ends with the newline at or after the middle
+    let split = memchr(b'\n', &data[len / 2..]).map_or(len, |i| len / 2 + i + 1);
+    let mut observe = |record: Option<(&'a str, i32)>, offset: usize| {
+        if let Some((station, temp)) = record {
+            let offset = start + offset as u64;
+            m.entry(station)
+                .and_modify(|e| aggregator.observe_at(e, temp, offset))
+                .or_insert_with(|| aggregator.init_at(temp, offset));
+        }
+        record.is_some() as usize
+    };
+    let (mut i, mut j) = (0, split);
+    let mut rows: usize = 0;
+    while i < split && j < len {
+        let (first, next_i) = next_record(data, i, split, lines_before, options);
+        let (second, next_j) = next_record(data, j, len, lines_before, options);
+        rows += observe(first, i) + observe(second, j);
+        (i, j) = (next_i, next_j);
+    }
+    let single = ParseOptions { dual_cursor: false, ..options };
+    // the lines before the tails are only counted for an invalid record
+    let (before_i, before_j) = (&data[..i], &data[..j]);
+    rows += aggregate_slice(&data[i..split], start + i as u64, &|| lines_before() + memchr_iter(b'\n', before_i).count(), aggregator, m, single);
+    rows + aggregate_slice(&data[j..], start + j as u64, &|| lines_before() + memchr_iter(b'\n', before_j).count(), aggregator, m, single)
+}
+
+// parses the line starting at `start` and ending at a newline or at `end`, returns the record and the start of the next line
+fn next_record<'a>(data: &'a [u8], start: usize, end: usize, lines_before: &dyn Fn() -> usize, options: ParseOptions) -> (Option<(&'a str, i32)>, usize) {
+    let line_end = memchr(b'\n', &data[start..end]).map_or(end, |i| start + i);
+    let record = parse_line(&data[start..line_end], options).unwrap_or_else(|e| { invalid_record(data, e, lines_before, options); None });
+    (record, (line_end + 1).min(end))
+}
+
+// the offset of every slice in its file, the slices of a file follow each other one newline apart, or right after each
+// other if they keep their newlines, and a slice that does not follow the one before it starts another file
+pub(crate) fn slice_offsets(slices: &[&[u8]]) -> Vec<u64> {
+    let mut offsets = Vec::with_capacity(slices.len());
+    for (i, slice) in slices.iter().enumerate() {
+        let offset = match i.checked_sub(1).map(|before| (before, slices[before])) {
+            Some((before, s)) if s.as_ptr_range().end == slice.as_ptr() => offsets[before] + s.len() as u64,
+            Some((before, s)) if follows(s, slice) => offsets[before] + s.len() as u64 + 1,
+            _ => 0,
+        };
+        offsets.push(offset);
+    }
+    offsets
+}
+
+// the offset in its file of `records`, the end of the slice at `index` left by `without_repeats`
+pub(crate) fn records_offset(offsets: &[u64], slices: &[&[u8]], index: usize, records: &[u8]) -> u64 {
+    offsets[index] + (slices[index].len() - records.len()) as u64
+}
+
+// whether `slice` starts right after the newline that ends `before`, the mappings of the files are not compared otherwise
+fn follows(before: &[u8], slice: &[u8]) -> bool {
+    before.as_ptr_range().end.wrapping_add(1) == slice.as_ptr()
+}
+
+// calls `f` with the station, the temperature and the offset in the slice of every record,
+// `lines_before` counts the lines before the slice for the report of an invalid record
+pub(crate) fn for_each_record<'a, F: FnMut(&'a str, i32, usize)>(data: &'a [u8], options: ParseOptions, lines_before: &dyn Fn() -> usize, mut f: F) {
+    // selecting the fields and skipping the duplicates are separate loops, so that the common layout does not pay for them
+    if options.dedup_consecutive {
+        return for_each_distinct_record(data, options, lines_before, f);
+    }
+    if let Some(columns) = options.columns {
+        return for_each_record_columns(data, columns, options, lines_before, f);
+    }
+    let mut i: usize = 0;
+    let len: usize = data.len();
+
+    let mut station_start: usize = 0;
+    let mut station_end: usize = 0;
+    let mut temp_start: usize = 0;
+    while i < len {
+        if data[i] == b'\n' {
+            match parse_record(data, station_start, station_end, temp_start, i, options) {
+                Ok(Some((station, temp))) => f(station, temp, station_start),
+                Ok(None) => {}
+                Err(e) => invalid_record(data, e, lines_before, options),
+            }
+            station_start = i + 1;
+        } else if data[i] == b';' {
+            station_end = i;
+            temp_start = i + 1;
+        }
+        i += 1;
+    }
+    // process the last record if the file does not end with a newline
+    if len > 0 && data[len - 1] != b'\n' {
+        match parse_record(data, station_start, station_end, temp_start, len, options) {
+            Ok(Some((station, temp))) => f(station, temp, station_start),
+            Ok(None) => {}
+            Err(e) => invalid_record(data, e, lines_before, options),
+        }
+    }
+}
+
+// like `for_each_record`, skipping the lines identical to the line before them
+fn for_each_distinct_record<'a, F: FnMut(&'a str, i32, usize)>(data: &'a [u8], options: ParseOptions, lines_before: &dyn Fn() -> usize, mut f: F) {
+    let mut previous: Option<&[u8]> = None;
+    let mut start: usize = 0;
+    while start < data.len() {
+        let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+        let l = &data[start..end];
+        if previous != Some(l) {
+            match parse_line(l, options) {
+                Ok(Some((station, temp))) => f(station, temp, start),
+                Ok(None) => {}
+                Err(e) => invalid_record(data, e, lines_before, options),
+            }
+            previous = Some(l);
+        }
+        start = end + 1;
+    }
+}
+
+fn for_each_record_columns<'a, F: FnMut(&'a str, i32, usize)>(data: &'a [u8], columns: Columns, options: ParseOptions, lines_before: &dyn Fn() -> usize, mut f: F) {
+    let mut start: usize = 0;
+    while start < data.len() {
+        let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+        match parse_columns(&data[start..end], columns, options) {
+            Ok(Some((station, temp))) => f(station, temp, start),
+            Ok(None) => {}
+            Err(e) => invalid_record(data, e, lines_before, options),
+        }
+        start = end + 1;
+    }
+}
+
+// finds the fields by counting the delimiters, records with too few fields are invalid unless `options.lenient` is set,
+// blank lines and records with an empty station name are skipped
+fn parse_columns(l: &[u8], columns: Columns, options: ParseOptions) -> Result<Option<(&str, i32)>, Invalid> {
+    let l: &[u8] = l.strip_suffix(b"\r").unwrap_or(l);
+    if l.is_empty() {
+        return Ok(None);
+    }
+    let (mut station, mut temp) = (None, None);
+    let mut start: usize = 0;
+    for (i, end) in memchr_iter(b';', l).chain(std::iter::once(l.len())).enumerate() {
+        if i == columns.station {
+            station = Some(&l[start..end]);
+        } else if i == columns.temp {
+            temp = Some(&l[start..end]);
+        }
+        if station.is_some() && temp.is_some() {
+            break;
+        }
+        start = end + 1;
+    }
+    match (station, temp) {
+        (Some([]), Some(_)) => Ok(None),
+        (Some(station), Some(temp)) => parse_fields(station, temp, options),
+        _ if options.lenient => Ok(None),
+        _ => Err(Invalid::new(format!("Malformed record, expected at least {} fields", columns.station.max(columns.temp) + 1), &l[l.len()..])),
+    }
+}
+
+fn parse_record(data: &[u8], station_start: usize, station_end: usize, temp_start: usize, temp_end: usize, options: ParseOptions) -> Result<Option<(&str, i32)>, Invalid> {
+    // the offsets are left over from the previous record if this one has no delimiter (e.g. a blank line),
+    // such records and records with an empty station name are skipped
+    if temp_start <= station_start || station_end == station_start {
+        return Ok(None);
+    }
+    parse_fields(&data[station_start..station_end], &data[temp_start..temp_end], options)
+}
+
+// a trailing `\r` of CRLF line endings is dropped, invalid records are errors unless `options.lenient` is set
+fn parse_fields<'a>(station: &'a [u8], temp: &[u8], options: ParseOptions) -> Result<Option<(&'a str, i32)>, Invalid> {
+    let temp: &[u8] = temp.strip_suffix(b"\r").unwrap_or(temp);
+    let station: &str = match std::str::from_utf8(station) {
+        Ok(station) => station,
+        Err(_) if options.lenient => return Ok(None),
+        Err(e) => return Err(Invalid::new(format!("Invalid UTF-8 sequence: {}", e), &station[e.valid_up_to()..])),
+    };
+    if options.only.is_some_and(|only| !only.contains(station)) {
+        return Ok(None);
+    }
+    match parse(temp, options) {
+        Some(temp) => Ok(Some((station, temp))),
+        None if options.lenient => Ok(None),
+        None => Err(Invalid::new(format!("Invalid temperature {}", String::from_utf8_lossy(temp)), temp)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::{BufReader, Read, Write};
+    use std::panic::AssertUnwindSafe;
+
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::generate::SplitMix64;
+    use crate::{generate, CancelReason, ExtremeOffsets, Histogram, MinMeanMax, StationData, TrackExtremes, TrackFirstLast};
+
+    /// Fails with the errors before reading from the inner reader.
+    struct FailingReader<R> {
+        inner: R,
+        errors: Vec<Error>,
+    }
+
+    impl<R: Read> Read for FailingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            match self.errors.pop() {
+                Some(e) => Err(e),
+                None => self.inner.read(buf),
+            }
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let eio = || Error::from_raw_os_error(EIO);
+        let read = |errors: Vec<Error>, retries: u32| {
+            let mut backoffs = Vec::new();
+            let mut r = Retry::new(FailingReader { inner: &b"Oslo;-3.4\n"[..], errors }, retries, Duration::from_micros(1), |_: &Error, _, backoff| backoffs.push(backoff));
+            let mut buf = Vec::new();
+            let result = r.read_to_end(&mut buf).map(|_| buf);
+            (result, backoffs)
+        };
+        let (result, backoffs) = read(vec![eio(), Error::from(ErrorKind::TimedOut), eio()], 3);
+        assert_eq!(result.unwrap(), b"Oslo;-3.4\n");
+        assert_eq!(backoffs, [1, 2, 4].map(Duration::from_micros));
+        // given up after the retries
+        let (result, backoffs) = read(vec![eio(), eio(), eio()], 2);
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(EIO));
+        assert_eq!(backoffs.len(), 2);
+        // fatal errors are not retried
+        let (result, backoffs) = read(vec![Error::from(ErrorKind::NotFound)], 3);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(backoffs.is_empty());
+        assert!(!is_transient(&Error::from_raw_os_error(2)));
+    }
+
+    /// Cancels once more than `after` bytes have been read from the inner reader.
+    struct CancellingReader<'a, R> {
+        inner: R,
+        read: usize,
+        after: usize,
+        cancel: &'a Cancel,
+    }
+
+    impl<R: Read> Read for CancellingReader<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            if self.read > self.after {
+                self.cancel.cancel(CancelReason::Interrupted);
+            }
+            Ok(n)
+        }
+    }
+
+    // aggregates every slice on its own and merges them in the order, returns the merged states serialized
+    // in the order of the stations, equal only for bit-identical states
+    fn merged_in_order<A: Aggregator>(slices: &[&[u8]], aggregator: &A, order: &[usize]) -> String
+    where
+        A::State: serde::Serialize,
+    {
+        let offsets = slice_offsets(slices);
+        let mut merged = HashMap::new();
+        for &i in order {
+            let mut m = HashMap::new();
+            aggregate_slice(slices[i], offsets[i], &|| 0, aggregator, &mut m, ParseOptions::default());
+            merge(aggregator, &mut merged, m);
+        }
+        serde_json::to_string(&merged.into_iter().collect::<std::collections::BTreeMap<_, _>>()).unwrap()
+    }
+
+    #[test]
+    fn hundredths_are_aggregated_in_the_scale() {
+        let data = b"Oslo;12.34\nOslo;-5.67\nRome;3\nOslo;0.05\n";
+        let options = ParseOptions { scale: crate::parse::Scale::new(100).unwrap(), ..Default::default() };
+        let cancel = Cancel::default();
+        let (m, _) = read_slices_parallel(&slice_sized(data, 16), &MinMeanMax, options, &cancel);
+        assert_eq!((m["Oslo"].min_temp, m["Oslo"].max_temp, m["Oslo"].sum_temp, m["Oslo"].n), (-567, 1234, 672, 3));
+        assert_eq!(m["Rome"].sum_temp, 300);
+        assert_eq!(read_stations_data_slice(data, &MinMeanMax, options), m);
+    }
+
+    #[test]
+    fn only_the_listed_stations_are_aggregated() {
+        let data = b"Oslo;1.0\nRome;hot\nOslo;3.0\nLima;2.0\nRome;4.0\nPerth;-1.0";
+        let only: &'static HashSet<String> = Box::leak(Box::new(HashSet::from(["Oslo".to_owned(), "Perth".to_owned()])));
+        let options = ParseOptions { only: Some(only), ..Default::default() };
+        let cancel = Cancel::default();
+        // the invalid temperature of a skipped station is not parsed
+        let m = read_stations_data_slice(data, &MinMeanMax, options);
+        assert_eq!(m.keys().copied().collect::<HashSet<_>>(), HashSet::from(["Oslo", "Perth"]));
+        assert_eq!((m["Oslo"].n, m["Oslo"].sum_temp), (2, 40));
+        let slices = slice_sized(data, 1);
+        assert_eq!(read_slices_parallel(&slices, &MinMeanMax, options, &cancel), (m.clone(), data.len() + 1 - slices.len()));
+        let arena = Bump::new();
+        let (simple, _) = read_stations_data(&data[..], &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), options, &cancel, |_, _| {});
+        assert_eq!(simple, m);
+        // the fields of the other layouts too
+        let columns = ParseOptions { columns: Some(Columns { station: 1, temp: 0 }), ..options };
+        let m = read_stations_data_slice(b"1.5;Oslo\n2.5;Rome\n", &MinMeanMax, columns);
+        assert_eq!((m.len(), m["Oslo"].n), (1, 1));
+    }
+
+    #[test]
+    fn consecutive_duplicates_are_skipped() {
+        let data = b"Oslo;1.0\nOslo;1.0\nOslo;1.0\nRome;2.0\nOslo;1.0\nOslo;1.5\nRome;2.0\nRome;2.0";
+        let options = ParseOptions { dedup_consecutive: true, ..Default::default() };
+        let cancel = Cancel::default();
+        let m = read_stations_data_slice(data, &MinMeanMax, options);
+        // the repeats right after the first record, not the same record after another one
+        assert_eq!((m["Oslo"].n, m["Oslo"].sum_temp, m["Rome"].n), (3, 35, 2));
+        assert_eq!(read_stations_data_slice(data, &MinMeanMax, ParseOptions::default())["Oslo"].n, 5);
+        // one line per slice, the repeats are found across the slices
+        let slices = slice_sized(data, 1);
+        assert_eq!(read_slices_parallel(&slices, &MinMeanMax, options, &cancel).0, m);
+        assert_eq!(read_slices_parallel(&slice_sized(data, 20), &MinMeanMax, ParseOptions { dual_cursor: true, ..options }, &cancel).0, m);
+        let arena = Bump::new();
+        let (simple, _) = read_stations_data(&data[..], &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), options, &cancel, |_, _| {});
+        assert_eq!(simple, m);
+        assert_eq!(scan_slices_parallel(&slices, options, &cancel).0, 5);
+        assert_eq!(without_repeats(&slices, 1, options), (&b""[..], 1));
+        // slices of two files are not compared
+        assert_eq!(without_repeats(&[&data[..8], &data[..8]], 1, options).1, 0);
+    }
+
+    #[test]
+    fn adjacent_byte_ranges_have_all_the_records() {
+        let data = b"Oslo;-3.4\n\nRome;15.0\nOslo;12.5\r\nParis;1.0";
+        let whole = read_stations_data_slice(data, &MinMeanMax, ParseOptions::default());
+        for split in 0..=data.len() + 1 {
+            let (first, second) = (byte_range(data, 0, split), byte_range(data, split, usize::MAX));
+            assert_eq!([first, second].concat(), data, "split at {}", split);
+            assert!(first.is_empty() || first.ends_with(b"\n") || second.is_empty());
+            let mut m = read_stations_data_slice(first, &MinMeanMax, ParseOptions::default());
+            merge(&MinMeanMax, &mut m, read_stations_data_slice(second, &MinMeanMax, ParseOptions::default()));
+            assert_eq!(m, whole, "split at {}", split);
+        }
+        // three ranges, the middle one within a record
+        let ranges: Vec<&[u8]> = [(0, 12), (12, 14), (14, 100)].iter().map(|&(s, e)| byte_range(data, s, e)).collect();
+        assert_eq!(ranges, [&data[..21], &data[21..21], &data[21..]]);
+        assert!(byte_range(data, 30, 20).is_empty());
+    }
+
+    #[test]
+    fn merge_is_independent_of_the_order() {
+        let mut data = Vec::new();
+        generate::generate(&mut data, 20_000, 30, 9).unwrap();
+        let histogram = Histogram::with_bucket_width(50);
+        let whole = [data.as_slice()];
+        let expected = (merged_in_order(&whole, &MinMeanMax, &[0]), merged_in_order(&whole, &histogram, &[0]),
+                        merged_in_order(&whole, &TrackExtremes, &[0]), merged_in_order(&whole, &TrackFirstLast, &[0]));
+        let mut rng = SplitMix64(1);
+        for size in [64, 1000, 1 << 14] {
+            let slices = slice_sized(&data, size);
+            let mut order: Vec<usize> = (0..slices.len()).collect();
+            for _ in 0..4 {
+                for i in (1..order.len()).rev() {
+                    order.swap(i, (rng.next() % (i as u64 + 1)) as usize);
+                }
+                let merged = (merged_in_order(&slices, &MinMeanMax, &order), merged_in_order(&slices, &histogram, &order),
+                              merged_in_order(&slices, &TrackExtremes, &order), merged_in_order(&slices, &TrackFirstLast, &order));
+                assert!(merged == expected, "slices of {} bytes in the order {:?}", size, &order[..order.len().min(10)]);
+            }
+        }
+    }
+
+    #[test]
+    fn cancellation_stops_simple_reader_midway() {
+        let data = "Hamburg;12.0\n".repeat(100_000);
+        let cancel = Cancel::default();
+        let reader = CancellingReader { inner: data.as_bytes(), read: 0, after: data.len() / 2, cancel: &cancel };
+        let arena = Bump::new();
+        let (m, bytes_processed) = read_stations_data(BufReader::with_capacity(8192, reader), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &cancel, |_, _| {});
+        assert_eq!(cancel.reason(), Some(CancelReason::Interrupted));
+        assert!(bytes_processed < data.len());
+        assert_eq!(m["Hamburg"].n as usize * "Hamburg;12.0\n".len(), bytes_processed);
+    }
+
+    #[test]
+    fn integer_and_decimal_temperatures() {
+        let data = "Paris;12\nParis;12.3\nOslo;-4\nOslo;-0.5\nParis;-1";
+        let cancel = Cancel::default();
+        let arena = Bump::new();
+        let (simple, _) = read_stations_data(data.as_bytes(), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &cancel, |_, _| {});
+        let slice = read_stations_data_slice(data.as_bytes(), &MinMeanMax, ParseOptions { fast_parse: true, ..Default::default() });
+        for m in [&simple, &slice] {
+            assert_eq!((m["Paris"].min_temp, m["Paris"].max_temp, m["Paris"].sum_temp, m["Paris"].n), (-10, 123, 233, 3));
+            assert_eq!((m["Oslo"].min_temp, m["Oslo"].max_temp, m["Oslo"].sum_temp, m["Oslo"].n), (-40, -5, -45, 2));
+        }
+    }
+
+    #[test]
+    fn newline_only_file_is_empty() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rust-1brc-newlines-{}.txt", std::process::id()));
+        std::fs::write(&path, "\n\n\n\n").unwrap();
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let result = read_file_parallel(&path, &MinMeanMax, &mut interner, ParseOptions::default(), &Cancel::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap().0.is_empty());
+
+        let (m, _) = read_stations_data("\n\n\n\n".as_bytes(), &MinMeanMax, &mut interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn blank_lines_between_records_are_skipped() {
+        let data = "Paris;12.0\n\nOslo;-4.0\n\n;1.0\n";
+        let m = read_stations_data_slice(data.as_bytes(), &MinMeanMax, ParseOptions::default());
+        assert_eq!(m.len(), 2);
+        assert_eq!((m["Paris"].n, m["Oslo"].n), (1, 1));
+    }
+
+    // the streaming and the slice parsers must agree on every input
+    fn assert_readers_agree(data: &[u8], options: ParseOptions) -> usize {
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let (simple, _) = read_stations_data(data, &MinMeanMax, &mut interner, HashMap::new(), options, &Cancel::default(), |_, _| {});
+        let slice = read_stations_data_slice(data, &MinMeanMax, options);
+        assert_eq!(simple, slice);
+        slice.len()
+    }
+
+    #[test]
+    fn crlf_line_endings_are_accepted() {
+        let data = b"Paris;12.0\r\nOslo;-4\r\nOslo;-0.5";
+        assert_eq!(assert_readers_agree(data, ParseOptions::default()), 2);
+    }
+
+    #[test]
+    fn station_name_ends_at_last_delimiter() {
+        let data = b"a;b;1.0\n;;2.0\na;b;3.0\n";
+        let m = read_stations_data_slice(data, &MinMeanMax, ParseOptions::default());
+        assert_eq!((m["a;b"].n, m[";"].n), (2, 1));
+        assert_readers_agree(data, ParseOptions::default());
+    }
+
+    #[test]
+    fn trailing_fields_are_ignored() {
+        let data = b"Paris;12.3;sensor7\nParis;10.0\nOslo;-4.0;sensor2;outdoor\r\n";
+        let options = ParseOptions { columns: Some(Columns { station: 0, temp: 1 }), ..Default::default() };
+        let m = read_stations_data_slice(data, &MinMeanMax, options);
+        assert_eq!((m["Paris"].n, m["Paris"].max_temp, m["Oslo"].min_temp), (2, 123, -40));
+        assert_eq!(assert_readers_agree(data, options), 2);
+        // the station name would end at the last delimiter
+        let lenient = ParseOptions { lenient: true, ..Default::default() };
+        assert_eq!(read_stations_data_slice(data, &MinMeanMax, lenient).keys().collect::<Vec<_>>(), [&"Paris"]);
+    }
+
+    #[test]
+    fn lenient_mode_skips_invalid_records() {
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let data = b"Paris;12.0\na;\n\xff;1.0\nOslo;x\n;\n\xff\n\rOslo;-4\n;1\r\r\n";
+        assert_eq!(assert_readers_agree(data, options), 2);
+        assert_readers_agree(b"\n\n;", options);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn files_are_evicted_from_the_page_cache() {
+        let path = std::env::temp_dir().join(format!("rust-1brc-evict-{}.txt", std::process::id()));
+        fs::write(&path, b"Paris;12.0\n").unwrap();
+        assert!(evict_from_page_cache(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(evict_from_page_cache(&path).is_err());
+    }
+
+    #[test]
+    fn bzip2_input_is_decompressed() {
+        let data = b"Paris;12.0\nOslo;-4.5\n".repeat(1000);
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let path = std::env::temp_dir().join(format!("rust-1brc-bzip2-{}.txt.bz2", std::process::id()));
+        fs::write(&path, &compressed).unwrap();
+        let mut decompressed = Vec::new();
+        let mut input = ErrorTrap::new(open_input(&path, false, Encoding::Utf8).unwrap());
+        input.read_to_end(&mut decompressed).unwrap();
+        input.finish().unwrap();
+        assert_eq!(decompressed, data);
+
+        // the error is kept even though the reader stops at it
+        fs::write(&path, &compressed[..compressed.len() / 2]).unwrap();
+        let mut input = ErrorTrap::new(open_input(&path, false, Encoding::Utf8).unwrap());
+        let arena = Bump::new();
+        let (m, _) = read_stations_data(BufReader::new(&mut input), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), ParseOptions { lenient: true, ..Default::default() }, &Cancel::default(), |_, _| {});
+        fs::remove_file(&path).unwrap();
+        assert!(m.values().all(|s| s.n < 1000));
+        assert!(input.finish().is_err());
+    }
+
+    #[test]
+    fn piped_bzip2_input_is_decompressed() {
+        // like `bzcat` piped to the standard input, without an extension to detect the compression from
+        let data = b"Paris;12.0\nOslo;-4.5\n".repeat(100);
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let piped = std::io::Cursor::new(encoder.finish().unwrap());
+        let arena = Bump::new();
+        let input = decode_input(Box::new(piped), is_bzip2(Path::new("-"), true), Encoding::Utf8);
+        let (m, bytes_read) = read_stations_data(BufReader::new(input), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        assert_eq!(bytes_read, data.len());
+        assert_eq!((m["Paris"].n, m["Oslo"].n), (100, 100));
+        assert!(is_stdin(Path::new("-")) && !is_bzip2(Path::new("-"), false));
+        assert!(!regular_files(&[PathBuf::from("-")]).unwrap());
+    }
+
+    #[test]
+    fn encoded_inputs_match_utf8() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/encoding");
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let read = |file: &str, encoding: Encoding, interner: &mut Interner<'_>| {
+            let paths = [dir.join(file)];
+            let read = ReadOptions { encoding, ..ReadOptions::default() };
+            let (parallel, _) = read_files_parallel(&paths, &MinMeanMax, interner, read, ParseOptions::default(), &Cancel::default()).unwrap();
+            let mut parallel: Vec<(String, StationData)> = parallel.into_iter().map(|(s, d)| (s.to_owned(), d)).collect();
+            parallel.sort_by(|a, b| a.0.cmp(&b.0));
+            assert!(is_transcoded(&paths[0], encoding).unwrap() == (file != "utf8.txt"), "{}", file);
+            let input = open_input(&paths[0], false, encoding).unwrap();
+            let (streamed, _) = read_stations_data(BufReader::new(input), &MinMeanMax, interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+            let mut streamed: Vec<(String, StationData)> = streamed.into_iter().map(|(s, d)| (s.to_owned(), d)).collect();
+            streamed.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(parallel, streamed, "{}", file);
+            parallel
+        };
+        let utf8 = read("utf8.txt", Encoding::Utf8, &mut interner);
+        assert_eq!(utf8.len(), 4);
+        assert!(utf8.iter().any(|(s, _)| s == "São Paulo"));
+        // the files with a byte order mark are read with the default encoding
+        for (file, encoding) in [("utf8-bom.txt", Encoding::Utf8), ("latin1.txt", Encoding::Latin1), ("utf16le.txt", Encoding::Utf16Le),
+                                 ("utf16le-bom.txt", Encoding::Utf8), ("utf16be-bom.txt", Encoding::Utf8)] {
+            assert_eq!(read(file, encoding, &mut interner), utf8, "{}", file);
+        }
+    }
+
+    #[test]
+    fn selected_columns() {
+        let options = ParseOptions { columns: Some(Columns { station: 2, temp: 0 }), ..Default::default() };
+        let data = b"1.5;s1;Oslo;90\n\n-2.0;s2;Rome\r\n3.5;s3;Oslo";
+        assert_eq!(assert_readers_agree(data, options), 2);
+        let m = read_stations_data_slice(data, &MinMeanMax, options);
+        assert_eq!((m["Oslo"].n, m["Oslo"].sum_temp, m["Rome"].min_temp), (2, 50, -20));
+        let lenient = ParseOptions { lenient: true, ..options };
+        assert_eq!(assert_readers_agree(b"1.5;s1\n2.0;s2;Oslo\n", lenient), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Malformed record")]
+    fn strict_mode_panics_on_missing_columns() {
+        let options = ParseOptions { columns: Some(Columns { station: 0, temp: 2 }), ..Default::default() };
+        read_stations_data_slice(b"Oslo;s1;1.0\nRome;2.0\n", &MinMeanMax, options);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid temperature")]
+    fn strict_mode_panics_on_invalid_temperature() {
+        read_stations_data_slice(b"Paris;12.0\nOslo;x\n", &MinMeanMax, ParseOptions::default());
+    }
+
+    #[test]
+    fn invalid_records_are_reported_with_their_line() {
+        let data: String = (0..100).map(|i| if i == 76 { "Zürich;1x5\r\n".to_owned() } else { format!("Rome;{}.0\n", i) }).collect();
+        let message = |f: &dyn Fn()| *std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err().downcast::<String>().unwrap();
+        let expected = "Invalid temperature 1x5 at line 77, column 8:\nZürich;1x5\n       ^";
+        let slices = slice_sized(data.as_bytes(), 64);
+        for options in [ParseOptions::default(), ParseOptions { dual_cursor: true, ..Default::default() }] {
+            assert_eq!(message(&|| { read_slices_parallel(&slices, &MinMeanMax, options, &Cancel::default()); }), expected);
+            assert_eq!(message(&|| { read_stations_data_slice(data.as_bytes(), &MinMeanMax, options); }), expected);
+        }
+        let arena = Bump::new();
+        let simple = || { read_stations_data(data.as_bytes(), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {}); };
+        assert_eq!(message(&simple), expected);
+
+        let columns = ParseOptions { columns: Some(Columns { station: 0, temp: 2 }), ..Default::default() };
+        assert_eq!(message(&|| { read_stations_data_slice(b"Oslo;s1;1.0\nRome;2.0\n", &MinMeanMax, columns); }),
+                   "Malformed record, expected at least 3 fields at line 2, column 9:\nRome;2.0\n        ^");
+    }
+
+    #[test]
+    fn invalid_records_are_collected() {
+        let data: String = (0..100).map(|i| if i % 30 == 7 { format!("Zürich;{}x\n", i) } else { format!("Rome;{}.0\n", i) }).collect();
+        let log: &'static ErrorLog = Box::leak(Box::new(ErrorLog::new(2)));
+        let options = ParseOptions { errors: Some(log), ..Default::default() };
+        let (m, _) = read_slices_parallel(&slice_sized(data.as_bytes(), 64), &MinMeanMax, options, &Cancel::default());
+        assert_eq!(m["Rome"].n, 96);
+        let (errors, count) = log.take();
+        assert_eq!((errors.len(), count), (2, 4));
+        assert!(errors.windows(2).all(|e| e[0].line < e[1].line));
+        let e = ParseError { line: 38, column: 8, message: "Invalid temperature 37x".to_owned(), text: "Zürich;37x".to_owned() };
+        assert_eq!(e.to_string(), "Invalid temperature 37x at line 38, column 8:\nZürich;37x\n       ^");
+
+        let log: &'static ErrorLog = Box::leak(Box::new(ErrorLog::new(10)));
+        let options = ParseOptions { errors: Some(log), ..Default::default() };
+        let arena = Bump::new();
+        read_stations_data(data.as_bytes(), &MinMeanMax, &mut Interner::new(&arena), HashMap::new(), options, &Cancel::default(), |_, _| {});
+        let (errors, count) = log.take();
+        assert_eq!(errors.iter().map(|e| e.line).collect::<Vec<usize>>(), [8, 38, 68, 98]);
+        assert_eq!((count, &errors[1]), (4, &e));
+        assert_eq!(log.take(), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn records_longer_than_a_slice_are_not_split() {
+        let long: String = "x".repeat(3 * SLICE_SIZE);
+        let data = format!("Paris;12.0\n{};-4.5\nOslo;1.0\n{};2.5", long, long);
+        let slices = slice(data.as_bytes());
+        assert_eq!(slices.len(), 2);
+        assert!(slices.iter().all(|s| s.ends_with(b";-4.5") || s.ends_with(b";2.5")));
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let (simple, _) = read_stations_data(data.as_bytes(), &MinMeanMax, &mut interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        let (parallel, _) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default());
+        assert_eq!(parallel, simple);
+        assert_eq!((parallel[long.as_str()].n, parallel[long.as_str()].sum_temp), (2, -20));
+        assert_eq!(slice(long.as_bytes()), vec![long.as_bytes()]);
+    }
+
+    #[test]
+    fn slices_end_at_a_newline() {
+        let record = "a;1.0\n";
+        let data = record.repeat(3 * SLICE_SIZE / record.len());
+        let slices = slice(data.as_bytes());
+        assert!(slices.len() > 1);
+        assert_eq!(slice_sized(data.as_bytes(), 1).len(), data.len() / record.len());
+        assert_eq!(slices.join(&b'\n'), data.as_bytes());
+        assert!(slices.iter().all(|s| s.starts_with(b"a;")));
+    }
+
+    #[test]
+    fn scan_counts_records() {
+        let data = "Paris;12\n\nOslo;-4\nOslo;-0.5";
+        let (records, bytes) = scan_stations_data(data.as_bytes(), ParseOptions::default(), &Cancel::default());
+        assert_eq!((records, bytes), (3, data.len() + 1));
+        let slices = slice(data.as_bytes());
+        assert_eq!(scan_slices_parallel(&slices, ParseOptions::default(), &Cancel::default()), (3, data.len()));
+    }
+
+    #[test]
+    fn normalization_forms_are_merged() {
+        // precomposed and decomposed e with an acute accent
+        let data = "Yaound\u{e9};12.0\nYaounde\u{301};14.0\nOslo;-4.0\n";
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let (simple, _) = read_stations_data(data.as_bytes(), &MinMeanMax, &mut interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        assert_eq!(simple.len(), 3);
+        let slice = read_stations_data_slice(data.as_bytes(), &MinMeanMax, ParseOptions::default());
+        for m in [normalize(&MinMeanMax, &mut interner, simple), normalize(&MinMeanMax, &mut interner, slice)] {
+            assert_eq!(m.len(), 2);
+            let yaounde = &m["Yaound\u{e9}"];
+            assert_eq!((yaounde.min_temp, yaounde.max_temp, yaounde.n), (120, 140, 2));
+        }
+    }
+
+    #[test]
+    fn trimmed_and_case_folded_keys_are_merged() {
+        let data = "HAMBURG;12.0\nHamburg;14.0\nhamburg ;10.0\nOslo;-4.0\n\u{c9}vora;20.0\n\u{e9}vora;22.0\n";
+        let arena = Bump::new();
+        let mut interner = Interner::new(&arena);
+        let m = read_stations_data_slice(data.as_bytes(), &MinMeanMax, ParseOptions::default());
+        assert_eq!(m.len(), 6);
+        let keys = KeyNormalization { trim: true, case_fold: true, nfc: true };
+        let m = normalize_keys(&MinMeanMax, &mut interner, m, keys);
+        assert_eq!(m.len(), 3);
+        let hamburg = &m["hamburg"];
+        assert_eq!((hamburg.min_temp, hamburg.max_temp, hamburg.n), (100, 140, 3));
+        assert_eq!((m["oslo"].n, m["\u{e9}vora"].n), (1, 2));
+        assert!(matches!(keys.apply("oslo"), Cow::Borrowed("oslo")));
+    }
+
+    #[test]
+    fn line_count_matches_records() {
+        let data: String = (0..100_000).map(|i| format!("Station {};{}.{}\n", i % 413, i % 201 - 100, i % 10)).collect();
+        for data in [&data[..], data.trim_end()] {
+            let slices = slice(data.as_bytes());
+            assert!(slices.len() > 1);
+            let (lines, _) = count_lines_parallel(&slices, &Cancel::default());
+            let (m, _) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default());
+            assert_eq!(lines, 100_000);
+            assert_eq!(m.values().map(|d| d.n as usize).sum::<usize>(), lines);
+        }
+    }
+
+    #[test]
+    fn streamed_files_have_offsets_of_their_own() {
+        let (a, b): (String, String) = ((0..1_000).map(|i| format!("A;{}.0\n", i % 10)).collect(), (0..1_000).map(|i| format!("B;{}.0\n", (i + 5) % 10)).collect());
+        // the second file below the first in memory, as the mappings of the files often are
+        let files = if a.as_ptr() > b.as_ptr() { [a.as_bytes(), b.as_bytes()] } else { [b.as_bytes(), a.as_bytes()] };
+        let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, 256)).collect();
+        let (m, n) = read_slices_streaming(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default(), Duration::from_micros(1), |_, _| {});
+        // the offsets are counted from the start of every file
+        assert_eq!((m["A"].offsets, m["B"].offsets), (ExtremeOffsets { min: 0, max: 54 }, ExtremeOffsets { min: 30, max: 24 }));
+        assert_eq!((m["A"].data.n, m["B"].data.n), (1_000, 1_000));
+        assert_eq!(n, read_slices_parallel(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default()).1);
+    }
+
+    #[test]
+    fn streaming_matches_parallel_read() {
+        let data: String = (0..100_000).map(|i| format!("Station {};{}.{}\n", i % 413, i % 201 - 100, i % 10)).collect();
+        let slices = slice(data.as_bytes());
+        let snapshots = AtomicUsize::new(0);
+        let (streamed, n1) = read_slices_streaming(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default(), Duration::from_micros(1), |m, bytes| {
+            assert!(m.len() <= 413 && bytes <= data.len());
+            snapshots.fetch_add(1, Ordering::Relaxed);
+        });
+        let (m, n2) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default());
+        assert_eq!(n1, n2);
+        assert_eq!(streamed.len(), m.len());
+        for (station, d) in &m {
+            let s = &streamed[station];
+            assert_eq!((d.min_temp, d.max_temp, d.sum_temp, d.n), (s.min_temp, s.max_temp, s.sum_temp, s.n));
+        }
+    }
+
+    #[test]
+    fn snapshots_converge_to_the_parallel_read() {
+        let data: String = (0..100_000).map(|i| format!("Station {};{}.{}\n", i % 413, i % 201 - 100, i % 10)).collect();
+        let slices = slice_sized(data.as_bytes(), 4096);
+        let mut last = Progress::default();
+        let (m, progress) = read_slices_snapshots(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default(), Duration::from_micros(1), |m, progress| {
+            assert_eq!(m.values().map(|d| d.n as usize).sum::<usize>(), progress.rows);
+            assert!(progress.bytes >= last.bytes && progress.rows >= last.rows);
+            last = progress;
+        });
+        let (parallel, bytes) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default());
+        assert_eq!(progress, Progress { bytes, rows: 100_000 });
+        assert_eq!(m, parallel);
+    }
+
+    #[test]
+    fn dual_cursor_matches_single_cursor() {
+        let data = "Hamburg;12.0\nBulawayo;8.9\r\n\nPalembang;38.8\nSt. John's;15.2\nA;B;-1.5\nHamburg;-3.4\nx;1";
+        let dual = ParseOptions { dual_cursor: true, ..Default::default() };
+        // every prefix covers the tiny slices, the odd numbers of records and the cuts in the middle of a record
+        for n in 0..=data.len() {
+            let prefix = &data.as_bytes()[..n];
+            let options = ParseOptions { lenient: true, ..Default::default() };
+            let single = read_stations_data_slice(prefix, &MinMeanMax, options);
+            assert_eq!(read_stations_data_slice(prefix, &MinMeanMax, ParseOptions { dual_cursor: true, ..options }), single, "{:?}", prefix);
+        }
+        assert_eq!(assert_readers_agree(data.as_bytes(), dual), 6);
+        let columns = ParseOptions { columns: Some(Columns { station: 1, temp: 0 }), ..dual };
+        assert_eq!(assert_readers_agree(b"1.0;Oslo\n2.0;Rome\n3.0;Oslo\n", columns), 2);
+
+        let data: String = (0..10_001).map(|i| format!("Station {};{}.{}\n", i % 37, i % 201 - 100, i % 10)).collect();
+        let slices = slice_sized(data.as_bytes(), 1000);
+        let (single, n1) = read_slices_parallel(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default());
+        let (dual, n2) = read_slices_parallel(&slices, &TrackExtremes, dual, &Cancel::default());
+        assert_eq!((n1, single.len()), (n2, dual.len()));
+        for (station, s) in &single {
+            assert_eq!((s.data, s.offsets), (dual[station].data, dual[station].offsets));
+        }
+    }
+
+    #[test]
+    fn runs_of_a_station_match_the_map_lookups() {
+        let mut data = Vec::new();
+        generate::generate_sorted(&mut data, 5000, 50, 3).unwrap();
+        data.extend_from_slice(b"Zzz;1.0\nZzz;-2.0\nYyy;3.0\nZzz;-2.0\nZzz;9.0\n");
+        assert!(has_runs(&data) && !has_runs(b"Oslo;1.0\nRome;2.0\nOslo;3.0\n") && !has_runs(b""));
+        let uncached = ParseOptions { no_station_cache: true, ..Default::default() };
+        assert_eq!(read_stations_data_slice(&data, &MinMeanMax, ParseOptions::default()), read_stations_data_slice(&data, &MinMeanMax, uncached));
+        let cached = read_stations_data_slice(&data, &TrackExtremes, ParseOptions::default());
+        let looked_up = read_stations_data_slice(&data, &TrackExtremes, uncached);
+        for (station, s) in &looked_up {
+            assert_eq!((s.data, s.offsets), (cached[station].data, cached[station].offsets));
+        }
+        // the first of the equal minimums, in the middle of a run
+        let z = &cached["Zzz"];
+        assert_eq!((z.data.n, z.offsets.min), (4, data.len() as u64 - 34));
+    }
+
+    #[test]
+    fn sorted_merge_matches_hashed_merge() {
+        let mut data = Vec::new();
+        generate::generate(&mut data, 5000, 300, 5).unwrap();
+        let maps = || slice_sized(&data, 500)
+            .iter()
+            .map(|slice| read_stations_data_slice(slice, &TrackExtremes, ParseOptions::default()))
+            .collect::<Vec<_>>();
+        let (hashed, sorted) = (merge_all(&TrackExtremes, maps()), merge_all_sorted(&TrackExtremes, maps()));
+        assert_eq!(sorted.len(), hashed.len());
+        for (station, s) in &hashed {
+            assert_eq!((s.data, s.offsets), (sorted[station].data, sorted[station].offsets));
+        }
+        assert!(merge_all_sorted(&MinMeanMax, Vec::<HashMap<&str, _>>::new()).is_empty());
+    }
+
+    #[test]
+    fn extreme_offsets_are_file_offsets() {
+        let data: String = (0..10_000).map(|i| format!("Station {};{}.{}\n", i % 37, i % 201 - 100, i % 10)).collect();
+        let arena = Bump::new();
+        let (simple, _) = read_stations_data(data.as_bytes(), &TrackExtremes, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        let (parallel, _) = read_slices_parallel(&slice_sized(data.as_bytes(), 1000), &TrackExtremes, ParseOptions::default(), &Cancel::default());
+        assert_eq!(simple.len(), parallel.len());
+        for (station, s) in &simple {
+            assert_eq!(s.offsets, parallel[station].offsets);
+            let line = |offset: u64| data[offset as usize..].lines().next().unwrap();
+            assert_eq!(line(s.offsets.min), format!("{};{:.1}", station, s.data.min()));
+            assert_eq!(line(s.offsets.max), format!("{};{:.1}", station, s.data.max()));
+        }
+        // read in two parts like the appended records of --follow
+        let split = data[..data.len() / 2].rfind('\n').unwrap() + 1;
+        let mut interner = Interner::new(&arena);
+        let (m, n) = read_stations_data(&data.as_bytes()[..split], &TrackExtremes, &mut interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        let (appended, _) = read_stations_data_from(&data.as_bytes()[n..], n as u64, &TrackExtremes, &mut interner, m, ParseOptions::default(), &Cancel::default(), |_, _| {});
+        for (station, s) in &simple {
+            assert_eq!(s.offsets, appended[station].offsets);
+        }
+    }
+
+    #[test]
+    fn readahead_stays_within_the_data() {
+        let data = b"Oslo;-3.4\nRome;15.0\n".repeat(10_000);
+        let slices = slice_sized(&data, 4096);
+        let (parsed, stats) = with_readahead(&data, 16 << 10, |consumed| {
+            let parsed = parse_slices_parallel_with_progress(&slices, &MinMeanMax, ParseOptions::default(), &Cancel::default(), consumed);
+            assert_eq!(consumed.load(Ordering::Relaxed), data.len());
+            parsed
+        });
+        assert_eq!(merge_all(&MinMeanMax, parsed.maps)["Rome"].n, 10_000);
+        assert!(stats.pages <= data.len().div_ceil(READAHEAD_PAGE_SIZE));
+
+        // the lead covers the whole data before the parsers start
+        let (_, stats) = with_readahead(&data[..10_000], 1 << 20, |_| thread::sleep(Duration::from_millis(50)));
+        assert_eq!(stats, ReadaheadStats { lead: 1 << 20, pages: 3 });
+        let (_, stats) = with_readahead(&[], 1 << 20, |_| ());
+        assert_eq!(stats.pages, 0);
+    }
+
+    #[test]
+    fn first_and_last_are_in_file_order() {
+        let data: String = (0..10_000).map(|i| format!("Station {};{}.{}\n", i % 37, i % 201 - 100, i % 10)).collect();
+        let arena = Bump::new();
+        let (simple, _) = read_stations_data(data.as_bytes(), &TrackFirstLast, &mut Interner::new(&arena), HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        let (parallel, _) = read_slices_parallel(&slice_sized(data.as_bytes(), 1000), &TrackFirstLast, ParseOptions::default(), &Cancel::default());
+        let lines: Vec<&str> = data.lines().collect();
+        assert_eq!(simple.len(), parallel.len());
+        for (station, s) in &simple {
+            assert_eq!(s.first_last, parallel[station].first_last);
+            let prefix = format!("{};", station);
+            let first = lines.iter().find(|l| l.starts_with(&prefix)).unwrap();
+            let last = lines.iter().rfind(|l| l.starts_with(&prefix)).unwrap();
+            assert_eq!(*first, format!("{}{:.1}", prefix, s.first_last.first as f64 / 10.0));
+            assert_eq!(*last, format!("{}{:.1}", prefix, s.first_last.last as f64 / 10.0));
+        }
+        // the records appended to a followed file come after the ones read before
+        let split = data[..data.len() / 2].rfind('\n').unwrap() + 1;
+        let mut interner = Interner::new(&arena);
+        let (m, n) = read_stations_data(&data.as_bytes()[..split], &TrackFirstLast, &mut interner, HashMap::new(), ParseOptions::default(), &Cancel::default(), |_, _| {});
+        let (appended, _) = read_stations_data_from(&data.as_bytes()[n..], n as u64, &TrackFirstLast, &mut interner, m, ParseOptions::default(), &Cancel::default(), |_, _| {});
+        for (station, s) in &simple {
+            assert_eq!(s.first_last, appended[station].first_last);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipes_are_read_into_memory() {
+        let path = std::env::temp_dir().join(format!("rust-1brc-fifo-{}", std::process::id()));
+        let status = std::process::Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || std::fs::write(path, "Hamburg;12.0\nBulawayo;8.9\n"))
+        };
+        let regular = regular_files(std::slice::from_ref(&path));
+        let data = load_file(&path, ReadOptions::default());
+        writer.join().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!regular.unwrap());
+        let data = data.unwrap();
+        assert!(matches!(data, FileData::Read(_)));
+        assert_eq!(read_stations_data_slice(&data, &MinMeanMax, ParseOptions::default()).len(), 2);
+    }
+
+    #[test]
+    fn multiple_files_are_merged() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("rust-1brc-multi-{}-{}.txt", std::process::id(), i))).collect();
+        std::fs::write(&paths[0], "Hamburg;12.0\nBulawayo;8.9\n").unwrap();
+        std::fs::write(&paths[1], "Hamburg;-3.4\n").unwrap();
+        std::fs::write(&paths[2], "Bulawayo;20.1\nPalembang;38.8").unwrap();
+        let arena = Bump::new();
+        let result = read_files_parallel(&paths, &MinMeanMax, &mut Interner::new(&arena), ReadOptions::default(), ParseOptions::default(), &Cancel::default());
+        let read = ReadOptions { slice_size: 8, no_mmap: true, encoding: Encoding::Utf8 };
+        let without_mmap = read_files_parallel(&paths, &MinMeanMax, &mut Interner::new(&arena), read, ParseOptions::default(), &Cancel::default());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        let (m, bytes_processed) = result.unwrap();
+        assert_eq!(without_mmap.unwrap().0, m);
+        assert_eq!(bytes_processed, 26 + 13 + 28);
+        assert_eq!(m.len(), 3);
+        assert_eq!((m["Hamburg"].min_temp, m["Hamburg"].max_temp, m["Hamburg"].n), (-34, 120, 2));
+        assert_eq!((m["Bulawayo"].min_temp, m["Bulawayo"].max_temp, m["Bulawayo"].n), (89, 201, 2));
+        assert_eq!(m["Palembang"].sum_temp, 388);
+    }
+}
@@ -0,0 +1,172 @@
+//! Input from S3-compatible object storage, `s3://bucket/key` paths, behind the `s3` feature.
+//!
+//! The credentials, the region and the endpoint come from the `AWS_*` environment variables, falling back to
+//! web identity and instance metadata credentials (see `AmazonS3Builder::from_env`). Profiles in `~/.aws` are not read.
+
+use std::io::{Error, ErrorKind, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreExt};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+// size of the ranges of an object downloaded in parallel
+const PART_SIZE: u64 = 8 << 20;
+
+// ranged GETs in flight at the same time
+const CONCURRENT_REQUESTS: usize = 16;
+
+// chunks of a streamed object buffered ahead of the reader
+const STREAM_BUFFER_CHUNKS: usize = 16;
+
+/// An object in a bucket, the errors of its operations include its URL.
+pub struct S3Object {
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+    url: String,
+    runtime: Arc<Runtime>,
+}
+
+impl S3Object {
+    /// Connects to the bucket of an `s3://bucket/key` URL, the object is not accessed yet.
+    pub fn open(path: &Path) -> Result<S3Object, Error> {
+        let url = path.to_string_lossy().into_owned();
+        let Some((bucket, key)) = url.strip_prefix("s3://").and_then(|p| p.split_once('/')).filter(|(b, k)| !b.is_empty() && !k.is_empty()) else {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid S3 URL {}, expected s3://bucket/key", url)));
+        };
+        let store = AmazonS3Builder::from_env().with_bucket_name(bucket).build().map_err(|e| error(&url, e))?;
+        let key = key.to_owned();
+        S3Object::with_store(Arc::new(store), &key, url)
+    }
+
+    fn with_store(store: Arc<dyn ObjectStore>, key: &str, url: String) -> Result<S3Object, Error> {
+        let location = ObjectPath::parse(key).map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", url, e)))?;
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        Ok(S3Object { store, location, url, runtime: Arc::new(runtime) })
+    }
+
+    /// Size of the object in bytes.
+    pub fn size(&self) -> Result<u64, Error> {
+        let meta = self.runtime.block_on(self.store.head(&self.location)).map_err(|e| error(&self.url, e))?;
+        Ok(meta.size)
+    }
+
+    /// Downloads the whole object with ranged GETs issued in parallel.
+    pub fn read_all(&self) -> Result<Vec<u8>, Error> {
+        self.read_in_parts(PART_SIZE)
+    }
+
+    fn read_in_parts(&self, part_size: u64) -> Result<Vec<u8>, Error> {
+        let size = self.size()?;
+        let ranges: Vec<Range<u64>> = (0..size).step_by(part_size as usize).map(|start| start..(start + part_size).min(size)).collect();
+        // the parts are collected in the order of the ranges, whatever order they arrive in
+        let parts: Vec<Bytes> = self.runtime
+            .block_on(futures::stream::iter(ranges)
+                .map(|range| self.store.get_range(&self.location, range))
+                .buffered(CONCURRENT_REQUESTS)
+                .try_collect())
+            .map_err(|e| error(&self.url, e))?;
+        let mut data: Vec<u8> = Vec::with_capacity(size as usize);
+        for part in parts {
+            data.extend_from_slice(&part);
+        }
+        Ok(data)
+    }
+
+    /// Streams the object with a single GET, the chunks are fetched in the background ahead of the reads.
+    pub fn reader(self) -> S3Reader {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+        let S3Object { store, location, url, runtime } = self;
+        runtime.spawn(async move {
+            let mut chunks = match store.get(&location).await {
+                Ok(result) => result.into_stream(),
+                Err(e) => {
+                    let _ = tx.send(Err(error(&url, e))).await;
+                    return;
+                }
+            };
+            while let Some(chunk) = chunks.next().await {
+                let failed = chunk.is_err();
+                // the reader was dropped or the download failed
+                if tx.send(chunk.map_err(|e| error(&url, e))).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        S3Reader { rx, chunk: Bytes::new(), _runtime: runtime }
+    }
+}
+
+/// Sequential reader of an object, see [`S3Object::reader`].
+pub struct S3Reader {
+    rx: mpsc::Receiver<Result<Bytes, Error>>,
+    chunk: Bytes,
+    // keeps the download running
+    _runtime: Arc<Runtime>,
+}
+
+impl Read for S3Reader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+fn error(url: &str, e: object_store::Error) -> Error {
+    let kind = match e {
+        object_store::Error::NotFound { .. } => ErrorKind::NotFound,
+        object_store::Error::PermissionDenied { .. } | object_store::Error::Unauthenticated { .. } => ErrorKind::PermissionDenied,
+        _ => ErrorKind::Other,
+    };
+    Error::new(kind, format!("{}: {}", url, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    fn object(data: &'static [u8]) -> S3Object {
+        let store = Arc::new(InMemory::new());
+        let object = S3Object::with_store(store.clone(), "data/measurements.txt", "s3://bucket/data/measurements.txt".to_owned()).unwrap();
+        object.runtime.block_on(store.put(&object.location, Bytes::from_static(data).into())).unwrap();
+        object
+    }
+
+    #[test]
+    fn objects_are_read_in_parts_and_streamed() {
+        let data = b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\n";
+        let o = object(data);
+        assert_eq!(o.size().unwrap(), data.len() as u64);
+        assert_eq!(o.read_in_parts(7).unwrap(), data);
+        assert_eq!(o.read_all().unwrap(), data);
+        let mut streamed = Vec::new();
+        o.reader().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, data);
+    }
+
+    #[test]
+    fn errors_include_the_url() {
+        let store = Arc::new(InMemory::new());
+        let o = S3Object::with_store(store, "missing.txt", "s3://bucket/missing.txt".to_owned()).unwrap();
+        let e = o.read_all().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().starts_with("s3://bucket/missing.txt: "), "{}", e);
+        assert_eq!(o.reader().read_to_end(&mut Vec::new()).unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(S3Object::open(Path::new("s3://bucket")).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Approximate aggregation of a random sample of the records.
+
+use std::collections::HashMap;
+
+use memchr::memchr;
+
+use crate::generate::SplitMix64;
+use crate::parse::ParseOptions;
+use crate::read::{invalid_record, parse_line};
+use crate::{Aggregator, MinMeanMax, StationData};
+
+/// Number of consecutive records parsed from every probe point.
+pub const RECORDS_PER_PROBE: usize = 1000;
+
+/// Statistics of the sampled records.
+pub struct Sample<'a> {
+    pub stations: HashMap<&'a str, StationData>,
+    pub records: usize,
+    pub bytes: usize,
+    pub probes: usize,
+}
+
+impl Sample<'_> {
+    /// Ratio of the size of the data to the sampled bytes, the counts are multiplied by it to estimate the full result.
+    pub fn scale(&self, total_bytes: usize) -> f64 {
+        if self.bytes == 0 { 0.0 } else { total_bytes as f64 / self.bytes as f64 }
+    }
+}
+
+/// Parses [`RECORDS_PER_PROBE`] records from random offsets until about `rate` of the bytes are sampled.
+///
+/// Every probe starts at the first record after a random offset, so only the sampled pages of a mapped file are read.
+/// The probes are independent and may overlap.
+pub fn sample(data: &[u8], rate: f64, seed: u64, options: ParseOptions) -> Sample<'_> {
+    let mut s = Sample { stations: HashMap::new(), records: 0, bytes: 0, probes: 0 };
+    let target = (data.len() as f64 * rate).ceil() as usize;
+    let mut rng = SplitMix64(seed);
+    while s.bytes < target {
+        let offset = (rng.next() % data.len() as u64) as usize;
+        let mut start = match offset {
+            0 => 0,
+            // the record under the offset is skipped, it may be partial
+            _ => memchr(b'\n', &data[offset - 1..]).map_or(0, |i| offset + i),
+        };
+        if start == data.len() {
+            start = 0;
+        }
+        s.probes += 1;
+        for _ in 0..RECORDS_PER_PROBE {
+            if start == data.len() {
+                break;
+            }
+            let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+            if let Some((station, temp)) = parse_line(&data[start..end], options).unwrap_or_else(|e| { invalid_record(data, e, &|| 0, options); None }) {
+                match s.stations.get_mut(station) {
+                    Some(e) => MinMeanMax.observe(e, temp),
+                    None => { s.stations.insert(station, StationData::new(temp)); }
+                }
+                s.records += 1;
+            }
+            let next = (end + 1).min(data.len());
+            s.bytes += next - start;
+            start = next;
+        }
+    }
+    s
+}
+
+/// Multiplies the counts and the sums by `scale`, the means stay the same and the min and max stay as observed.
+pub fn extrapolate(stations: &mut HashMap<&str, StationData>, scale: f64) {
+    for e in stations.values_mut() {
+        let n = ((e.n as f64 * scale).round() as u32).max(1);
+        e.sum_temp = (e.sum_temp as f64 * n as f64 / e.n as f64).round() as i64;
+        e.n = n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::generate;
+
+    #[test]
+    fn sampling_is_reproducible_and_reads_about_the_rate() {
+        let mut data = Vec::new();
+        generate(&mut data, 200_000, 10, 1).unwrap();
+        let a = sample(&data, 0.05, 7, ParseOptions::default());
+        let b = sample(&data, 0.05, 7, ParseOptions::default());
+        assert_eq!((a.stations, a.records), (b.stations, b.records));
+        assert!(a.bytes >= data.len() / 20 && a.bytes < data.len() / 10, "sampled {} of {} bytes", a.bytes, data.len());
+        assert_eq!(a.records, a.probes * RECORDS_PER_PROBE);
+    }
+
+    #[test]
+    fn extrapolated_counts_are_close() {
+        let mut data = Vec::new();
+        let expected = generate(&mut data, 200_000, 4, 3).unwrap();
+        let s = sample(&data, 0.1, 11, ParseOptions::default());
+        let scale = s.scale(data.len());
+        let mut stations = s.stations;
+        extrapolate(&mut stations, scale);
+        for (station, e) in &expected {
+            let sampled = &stations[station.as_str()];
+            assert!((sampled.n as f64 / e.count as f64 - 1.0).abs() < 0.1, "{}: {} vs {}", station, sampled.n, e.count);
+            assert!(sampled.min_temp as i32 >= e.min && sampled.max_temp as i32 <= e.max);
+            assert!((sampled.mean_tenths() - e.mean()).abs() <= 5);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Aggregation of all the workers into a single map of stations shared by them, instead of the per-job maps
+//! merged at the end, sharded by locks to reduce the contention.
+//!
+//! Measured with `--shared-map --repeat 3` on 20M records (a single core, so the numbers show the overhead of
+//! the locks rather than the contention of several cores): with 400 stations the shared map takes 1.43s against
+//! the 1.22s of the per-job maps, the second hash of the name to pick the shard and the locks cost more than the merge
+//! of a few hundred stations saves. With 10k stations and 4 workers it takes 1.58s against 1.81s, as the per-job maps
+//! grow to all the stations and merging them dominates. So the shared map only pays off for many stations, and the
+//! contention of more cores on the 400 hot stations can only widen the gap there.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+
+use crate::parse::ParseOptions;
+use crate::read::{for_each_record, lines_before, records_offset, slice_offsets, without_repeats};
+use crate::{Aggregator, Cancel};
+
+// shards of the map per worker thread, more shards make two workers less likely to wait for the same lock
+const SHARDS_PER_THREAD: usize = 16;
+
+/// Stations updated concurrently by the workers, split between shards of their own lock each by the hash of the name.
+pub struct SharedStations<'a, S> {
+    hasher: RandomState,
+    shards: Vec<Mutex<HashMap<&'a str, S>>>,
+}
+
+impl<'a, S> SharedStations<'a, S> {
+    /// A map of at least `shards` shards, rounded up to a power of two.
+    pub fn new(shards: usize) -> SharedStations<'a, S> {
+        let shards = (0..shards.max(1).next_power_of_two()).map(|_| Mutex::new(HashMap::new())).collect();
+        SharedStations { hasher: RandomState::new(), shards }
+    }
+
+    pub fn observe<A: Aggregator<State = S>>(&self, aggregator: &A, station: &'a str, temp: i32, offset: u64) {
+        let shard = self.hasher.hash_one(station) as usize & (self.shards.len() - 1);
+        let mut m = self.shards[shard].lock().unwrap();
+        match m.get_mut(station) {
+            Some(state) => aggregator.observe_at(state, temp, offset),
+            None => {
+                m.insert(station, aggregator.init_at(temp, offset));
+            }
+        }
+    }
+
+    pub fn into_map(self) -> HashMap<&'a str, S> {
+        self.shards.into_iter().flat_map(|shard| shard.into_inner().unwrap()).collect()
+    }
+}
+
+/// Variant of [`read_slices_parallel`](crate::read::read_slices_parallel) aggregating every rayon job into one
+/// [`SharedStations`], returns the map and the number of bytes processed.
+pub fn read_slices_shared<'a, A: Aggregator>(slices: &[&'a [u8]], aggregator: &A, options: ParseOptions, cancel: &Cancel) -> (HashMap<&'a str, A::State>, usize) {
+    let offsets = slice_offsets(slices);
+    let stations = SharedStations::new(rayon::current_num_threads() * SHARDS_PER_THREAD);
+    let bytes_processed = slices
+        .par_iter()
+        .enumerate()
+        .map(|(i, slice)| {
+            // cancellation point: skip the remaining slices once cancelled
+            if cancel.is_cancelled() {
+                return 0;
+            }
+            let (records, repeats) = without_repeats(slices, i, options);
+            let slice_start = records_offset(&offsets, slices, i, records);
+            for_each_record(records, options, &|| lines_before(slices, i) + repeats, |station, temp, offset| stations.observe(aggregator, station, temp, slice_start + offset as u64));
+            slice.len()
+        })
+        .sum();
+    (stations.into_map(), bytes_processed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::{read_slices_parallel, slice_sized};
+    use crate::{ExtremeOffsets, MinMeanMax, TrackExtremes, TrackFirstLast};
+
+    #[test]
+    fn matches_hash_map_aggregation() {
+        let data: String = (0..5_000).map(|i| format!("Station {};{}.{}\n", i % 97, i % 201 - 100, i % 10)).collect();
+        // one slice per line, so that the workers update the same stations concurrently
+        let slices: Vec<&[u8]> = data.as_bytes().split_inclusive(|&b| b == b'\n').collect();
+        let cancel = Cancel::default();
+        let (shared, n1) = read_slices_shared(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        let (map, n2) = read_slices_parallel(&slices, &MinMeanMax, ParseOptions::default(), &cancel);
+        assert_eq!(n1, n2);
+        assert_eq!(shared, map);
+        // the order of the updates does not matter to the order-dependent statistics either
+        let (shared, _) = read_slices_shared(&slices, &TrackFirstLast, ParseOptions::default(), &cancel);
+        let (map, _) = read_slices_parallel(&slices, &TrackFirstLast, ParseOptions::default(), &cancel);
+        assert_eq!(shared.len(), 97);
+        for (station, d) in &map {
+            assert_eq!(serde_json::to_string(&shared[station]).unwrap(), serde_json::to_string(d).unwrap());
+        }
+    }
+
+    #[test]
+    fn shards_are_a_power_of_two() {
+        assert_eq!(SharedStations::<u32>::new(0).shards.len(), 1);
+        assert_eq!(SharedStations::<u32>::new(48).shards.len(), 64);
+        let stations = SharedStations::new(3);
+        for (station, temp) in [("Oslo", -34), ("Rome", 150), ("Oslo", 125)] {
+            stations.observe(&MinMeanMax, station, temp, 0);
+        }
+        let m = stations.into_map();
+        assert_eq!((m["Oslo"].min_temp, m["Oslo"].max_temp, m["Oslo"].n), (-34, 125, 2));
+        assert_eq!(m["Rome"].n, 1);
+    }
+
+    #[test]
+    fn files_have_offsets_of_their_own() {
+        let (a, b): (String, String) = ((0..1_000).map(|i| format!("A;{}.0\n", i % 10)).collect(), (0..1_000).map(|i| format!("B;{}.0\n", (i + 5) % 10)).collect());
+        // the second file below the first in memory, as the mappings of the files often are
+        let files = if a.as_ptr() > b.as_ptr() { [a.as_bytes(), b.as_bytes()] } else { [b.as_bytes(), a.as_bytes()] };
+        let slices: Vec<&[u8]> = files.iter().flat_map(|data| slice_sized(data, 256)).collect();
+        let (m, n) = read_slices_shared(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default());
+        // the offsets are counted from the start of every file
+        assert_eq!((m["A"].offsets, m["B"].offsets), (ExtremeOffsets { min: 0, max: 54 }, ExtremeOffsets { min: 30, max: 24 }));
+        assert_eq!((m["A"].data.n, m["B"].data.n), (1_000, 1_000));
+        assert_eq!(n, read_slices_parallel(&slices, &TrackExtremes, ParseOptions::default(), &Cancel::default()).1);
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::round;
+use crate::Aggregator;
+
+// temperatures are stored in tenths of a degree (or the `Scale` of the parser), the parser rejects temperatures that do not fit in an i16
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StationData {
+    pub min_temp: i16,
+    pub max_temp: i16,
+    pub n: u32,
+    pub sum_temp: i64,
+}
+
+// 16 bytes without padding, so that the statistics of all the stations fit in the L2 cache
+const _: () = assert!(size_of::<StationData>() == 16);
+
+impl StationData {
+    /// Creates the statistics of a station with a single temperature, in tenths of a degree.
+    pub fn new(temp: i32) -> StationData {
+        StationData {
+            min_temp: temp as i16,
+            max_temp: temp as i16,
+            n: 1,
+            sum_temp: temp as i64,
+        }
+    }
+
+    /// Minimum temperature in degrees, of temperatures in tenths: the rows of [`format`](crate::format) also divide
+    /// the other scales of the parser.
+    pub fn min(&self) -> f64 {
+        self.min_temp as f64 / 10.0
+    }
+
+    /// Mean temperature in degrees, not rounded, of temperatures in tenths.
+    pub fn mean(&self) -> f64 {
+        self.sum_temp as f64 / 10.0 / self.n as f64
+    }
+
+    /// Maximum temperature in degrees, of temperatures in tenths.
+    pub fn max(&self) -> f64 {
+        self.max_temp as f64 / 10.0
+    }
+
+    /// Mean temperature in tenths of a degree, rounded half up like the reference implementation.
+    ///
+    /// Computed with integers, so it is exact even for sums that do not fit in the mantissa of an `f64`.
+    pub fn mean_tenths(&self) -> i64 {
+        rounded_mean(self.sum_temp, self.n as u64)
+    }
+
+    /// Number of measurements.
+    pub fn count(&self) -> u32 {
+        self.n
+    }
+
+    /// Adds a temperature, returns `None` without changing the statistics if the sum or the count would overflow.
+    pub fn checked_observe(&mut self, temp: i32) -> Option<()> {
+        let sum_temp = self.sum_temp.checked_add(temp as i64)?;
+        let n = self.n.checked_add(1)?;
+        self.min_temp = self.min_temp.min(temp as i16);
+        self.max_temp = self.max_temp.max(temp as i16);
+        self.sum_temp = sum_temp;
+        self.n = n;
+        Some(())
+    }
+
+    /// Merges the statistics, returns `None` without changing them if the sum or the count would overflow.
+    pub fn checked_merge(&mut self, other: &StationData) -> Option<()> {
+        let sum_temp = self.sum_temp.checked_add(other.sum_temp)?;
+        let n = self.n.checked_add(other.n)?;
+        self.min_temp = self.min_temp.min(other.min_temp);
+        self.max_temp = self.max_temp.max(other.max_temp);
+        self.sum_temp = sum_temp;
+        self.n = n;
+        Some(())
+    }
+}
+
+// the mean of the tenths rounded half up, the count must not be zero
+pub(crate) fn rounded_mean(sum: i64, count: u64) -> i64 {
+    let (sum, count) = (sum as i128, count as i128);
+    (2 * sum + count).div_euclid(2 * count) as i64
+}
+
+/// Formats the statistics of temperatures in tenths as `min/mean/max` rounded to one decimal, as in the output
+/// of the challenge.
+impl Display for StationData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}/{:.1}/{:.1}", round(self.min()), self.mean_tenths() as f64 / 10.0, round(self.max()))
+    }
+}
+
+/// The default [`Aggregator`] computing the min/mean/max temperature of every station.
+///
+/// The sum is kept in integer tenths rather than as an `f64`, so merging the parts of an input is exact
+/// and the result is the same for any thread count, slice size and merge order.
+pub struct MinMeanMax;
+
+impl Aggregator for MinMeanMax {
+    type State = StationData;
+
+    fn init(&self, temp: i32) -> StationData {
+        StationData::new(temp)
+    }
+
+    fn observe(&self, e: &mut StationData, temp: i32) {
+        let temp16 = temp as i16;
+        if temp16 > e.max_temp {
+            e.max_temp = temp16;
+        }
+        if temp16 < e.min_temp {
+            e.min_temp = temp16;
+        }
+        e.sum_temp += temp as i64;
+        e.n += 1;
+    }
+
+    fn merge(&self, e: &mut StationData, other: StationData) {
+        e.max_temp = other.max_temp.max(e.max_temp);
+        e.min_temp = other.min_temp.min(e.min_temp);
+        e.sum_temp += other.sum_temp;
+        e.n += other.n;
+    }
+}
+
+/// [`Aggregator`] like [`MinMeanMax`] that detects overflows of the sum and the count instead of wrapping around.
+///
+/// A station whose statistics overflowed fails [`Aggregator::validate`]. With the temperatures limited to an `i16`,
+/// the sum cannot overflow before the count does, so whether a station overflows does not depend on the merge order either.
+pub struct CheckedMinMeanMax;
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckedStationData {
+    pub data: StationData,
+    // the statistics stop being updated after an overflow
+    pub overflow: bool,
+}
+
+impl Aggregator for CheckedMinMeanMax {
+    type State = CheckedStationData;
+
+    fn init(&self, temp: i32) -> CheckedStationData {
+        CheckedStationData { data: StationData::new(temp), overflow: false }
+    }
+
+    fn observe(&self, e: &mut CheckedStationData, temp: i32) {
+        if !e.overflow {
+            e.overflow = e.data.checked_observe(temp).is_none();
+        }
+    }
+
+    fn merge(&self, e: &mut CheckedStationData, other: CheckedStationData) {
+        if !e.overflow {
+            e.overflow = other.overflow || e.data.checked_merge(&other.data).is_none();
+        }
+    }
+
+    fn validate(&self, e: &CheckedStationData) -> Result<(), Error> {
+        if e.overflow {
+            Err(Error::new(ErrorKind::InvalidData, "Sum of the temperatures or number of measurements overflows"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_and_display() {
+        let mut d = StationData::new(-34);
+        MinMeanMax.observe(&mut d, 120);
+        MinMeanMax.observe(&mut d, 99);
+        assert_eq!((d.min(), d.max(), d.count()), (-3.4, 12.0, 3));
+        assert!((d.mean() - 6.1666).abs() < 1e-3);
+        assert_eq!(d.to_string(), "-3.4/6.2/12.0");
+    }
+
+    #[test]
+    fn mean_is_exact_for_large_sums() {
+        let d = |sum_temp: i64, n: u32| StationData { min_temp: 0, max_temp: 0, n, sum_temp };
+        assert_eq!((d(5, 2).mean_tenths(), d(-5, 2).mean_tenths(), d(-7, 2).mean_tenths()), (3, -2, -3));
+        // 2^60 + 1 is rounded to 2^60 as an f64, which would make the half disappear
+        assert_eq!(d((1 << 60) + 1, 2).mean_tenths(), (1 << 59) + 1);
+        assert_eq!(d(-(1 << 60) - 1, 2).mean_tenths(), -(1 << 59));
+        assert_eq!(d(i64::MAX, 1).mean_tenths(), i64::MAX);
+        assert_eq!(d(-19_999 * 2_000_000_000, 4_000_000_000).to_string(), "0.0/-999.9/0.0");
+    }
+
+    #[test]
+    fn checked_sum_detects_overflow() {
+        let mut e = CheckedMinMeanMax.init(10);
+        e.data.sum_temp = i64::MAX - 10;
+        CheckedMinMeanMax.observe(&mut e, 10);
+        assert!(CheckedMinMeanMax.validate(&e).is_ok());
+        assert_eq!(e.data.sum_temp, i64::MAX);
+        CheckedMinMeanMax.observe(&mut e, 1);
+        assert!(CheckedMinMeanMax.validate(&e).is_err());
+        assert_eq!((e.data.sum_temp, e.data.n), (i64::MAX, 2));
+
+        let mut a = CheckedMinMeanMax.init(-5);
+        a.data.n = u32::MAX;
+        CheckedMinMeanMax.merge(&mut a, CheckedMinMeanMax.init(5));
+        assert!(CheckedMinMeanMax.validate(&a).is_err());
+    }
+}